@@ -14,7 +14,7 @@ extern crate dmm_tools;
 
 use std::collections::HashMap;
 use std::fmt;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicIsize, Ordering};
 use std::sync::RwLock;
 use std::collections::HashSet;
@@ -60,13 +60,7 @@ struct Context {
 
 impl Context {
     fn objtree(&mut self, opt: &Opt) {
-        let environment = match opt.environment {
-            Some(ref env) => env.into(),
-            None => match dm::detect_environment_default() {
-                Ok(Some(found)) => found,
-                _ => dm::DEFAULT_ENV.into(),
-            },
-        };
+        let environment = environment_path(opt);
         println!("parsing {}", environment.display());
 
         if let Some(parent) = environment.parent() {
@@ -74,19 +68,43 @@ impl Context {
         }
 
         self.dm_context.autodetect_config(&environment);
-        let pp = match dm::preprocessor::Preprocessor::new(&self.dm_context, environment) {
+        if let Some(ref cache) = opt.cache {
+            self.objtree = match dm::cache::parse_environment(&self.dm_context, &environment, cache.as_ref(), self.procs) {
+                Ok(objtree) => objtree,
+                Err(e) => {
+                    eprintln!("i/o error opening environment:\n{}", e);
+                    std::process::exit(1);
+                }
+            };
+            return;
+        }
+        let mut pp = match dm::preprocessor::Preprocessor::new(&self.dm_context, environment) {
             Ok(pp) => pp,
             Err(e) => {
                 eprintln!("i/o error opening environment:\n{}", e);
                 std::process::exit(1);
             }
         };
+        pp.enable_macro_provenance();
         let indents = dm::indents::IndentProcessor::new(&self.dm_context, pp);
         let mut parser = dm::parser::Parser::new(&self.dm_context, indents);
         if self.procs {
-            parser.enable_procs();
+            parser.defer_procs();
         }
         self.objtree = parser.parse_object_tree();
+        if self.procs {
+            self.objtree.parse_proc_bodies(&self.dm_context);
+        }
+    }
+}
+
+fn environment_path(opt: &Opt) -> PathBuf {
+    match opt.environment {
+        Some(ref env) => env.into(),
+        None => match dm::detect_environment_default() {
+            Ok(Some(found)) => found,
+            _ => dm::DEFAULT_ENV.into(),
+        },
     }
 }
 
@@ -109,6 +127,11 @@ struct Opt {
     #[structopt(long="jobs", default_value="1")]
     jobs: usize,
 
+    /// Cache the parsed environment in this file, and reuse it if no source
+    /// files have changed.
+    #[structopt(long="cache")]
+    cache: Option<String>,
+
     #[structopt(subcommand)]
     command: Command,
 }
@@ -185,6 +208,24 @@ enum Command {
         /// The list of maps to show info on.
         files: Vec<String>,
     },
+    /// Show the include order and find files which are never included.
+    #[structopt(name="includes")]
+    Includes {
+        /// Output format: "tree" for the include order and orphaned files,
+        /// "dot" for a Graphviz graph, or "json".
+        #[structopt(long="format", default_value="tree")]
+        format: String,
+    },
+    /// Print the fully macro-expanded source of a file.
+    #[structopt(name="expand")]
+    Expand {
+        /// The line or range of lines to show, like "12" or "12-20".
+        #[structopt(long="lines")]
+        lines: Option<LineRange>,
+
+        /// The file to expand, relative to the environment.
+        file: String,
+    },
 }
 
 fn run(opt: &Opt, command: &Command, context: &mut Context) {
@@ -432,6 +473,68 @@ fn run(opt: &Opt, command: &Command, context: &mut Context) {
             output_json(&report);
         },
         // --------------------------------------------------------------------
+        Command::Includes { ref format } => {
+            let environment = environment_path(opt);
+            context.dm_context.autodetect_config(&environment);
+            let root = environment.parent().map(Path::to_owned).unwrap_or_default();
+            let mut pp = match dm::preprocessor::Preprocessor::new(&context.dm_context, environment) {
+                Ok(pp) => pp,
+                Err(e) => {
+                    eprintln!("i/o error opening environment:\n{}", e);
+                    std::process::exit(1);
+                }
+            };
+            pp.by_ref().for_each(drop);
+            let graph = pp.include_graph();
+            let orphans = match graph.orphans(&root) {
+                Ok(orphans) => orphans,
+                Err(e) => {
+                    eprintln!("i/o error searching for orphaned files:\n{}", e);
+                    std::process::exit(1);
+                }
+            };
+
+            let stdout = std::io::stdout();
+            match format.as_str() {
+                "dot" => graph.write_dot(&mut stdout.lock()).unwrap(),
+                "json" => {
+                    #[derive(Serialize)]
+                    struct Report<'a> {
+                        files: &'a [dm::includes::IncludedFile],
+                        orphans: &'a [std::path::PathBuf],
+                    }
+                    output_json(&Report { files: graph.files(), orphans: &orphans });
+                },
+                _ => {
+                    use std::io::Write;
+                    let mut stdout = stdout.lock();
+                    writeln!(stdout, "include order:").unwrap();
+                    graph.write_tree(&mut stdout).unwrap();
+                    writeln!(stdout, "\norphaned files:").unwrap();
+                    for orphan in orphans.iter() {
+                        writeln!(stdout, "{}", orphan.display()).unwrap();
+                    }
+                },
+            }
+            *context.exit_status.get_mut() = orphans.len() as isize;
+        },
+        // --------------------------------------------------------------------
+        Command::Expand {
+            lines, ref file,
+        } => {
+            let environment = environment_path(opt);
+            context.dm_context.autodetect_config(&environment);
+            let pp = match dm::preprocessor::Preprocessor::new(&context.dm_context, environment) {
+                Ok(pp) => pp,
+                Err(e) => {
+                    eprintln!("i/o error opening environment:\n{}", e);
+                    std::process::exit(1);
+                }
+            };
+            let lines = lines.unwrap_or(LineRange { start: 1, end: u32::max_value() });
+            print!("{}", pp.expand_lines(file.as_ref(), lines.start..=lines.end));
+        },
+        // --------------------------------------------------------------------
     }
 }
 
@@ -480,6 +583,29 @@ impl std::str::FromStr for CoordArg {
     }
 }
 
+#[derive(Debug, Copy, Clone)]
+struct LineRange {
+    start: u32,
+    end: u32,
+}
+
+impl std::str::FromStr for LineRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let mut parts = s.splitn(2, "-");
+        let start = parts.next().unwrap_or("").parse().map_err(|e: std::num::ParseIntError| e.to_string())?;
+        let end = match parts.next() {
+            Some(end) => end.parse().map_err(|e: std::num::ParseIntError| e.to_string())?,
+            None => start,
+        };
+        if end < start {
+            return Err("the range must not end before it starts".into());
+        }
+        Ok(LineRange { start, end })
+    }
+}
+
 fn clamp(val: usize, min: usize, max: usize) -> usize {
     if val < min {
         min
@@ -0,0 +1,117 @@
+//! dmfmt, a source code formatter for DreamMaker.
+
+extern crate dreammaker as dm;
+
+use std::path::{Path, PathBuf};
+
+// ----------------------------------------------------------------------------
+// Command-line interface
+
+fn main() {
+    // command-line args
+    let mut config_file = None;
+    let mut check = false;
+    let mut paths = Vec::new();
+
+    let mut args = std::env::args();
+    let _ = args.next();  // skip executable name
+    while let Some(arg) = args.next() {
+        if arg == "-V" || arg == "--version" {
+            println!(
+                "dmfmt {}  Copyright (C) 2017-2020  Tad Hardesty",
+                env!("CARGO_PKG_VERSION")
+            );
+            println!("{}", include_str!(concat!(env!("OUT_DIR"), "/build-info.txt")));
+            println!("This program comes with ABSOLUTELY NO WARRANTY. This is free software,");
+            println!("and you are welcome to redistribute it under the conditions of the GNU");
+            println!("General Public License version 3.");
+            return;
+        } else if arg == "-c" {
+            config_file = Some(args.next().expect("must specify a file for -c"));
+        } else if arg == "--check" {
+            check = true;
+        } else if arg.starts_with('-') {
+            eprintln!("unknown argument: {}", arg);
+            std::process::exit(2);
+        } else {
+            paths.push(PathBuf::from(arg));
+        }
+    }
+
+    let context = dm::Context::default();
+    if let Some(filepath) = config_file {
+        context.force_config(filepath.as_ref());
+    } else if let Ok(Some(dme)) = dm::detect_environment_default() {
+        context.autodetect_config(&dme);
+    }
+    let options = context.config().format.clone();
+
+    if paths.is_empty() {
+        paths.push(PathBuf::from("."));
+    }
+    let mut files = Vec::new();
+    for path in paths {
+        collect_files(&path, &mut files);
+    }
+
+    let mut unformatted = 0;
+    let mut failed = 0;
+    for path in files {
+        let source = match std::fs::read(&path) {
+            Ok(bytes) => dm::lexer::from_utf8_or_latin1(bytes),
+            Err(e) => {
+                eprintln!("{}: {}", path.display(), e);
+                failed += 1;
+                continue;
+            }
+        };
+        let file_id = context.register_file(&path);
+        let formatted = match dm::formatter::format(&context, file_id, &source, &options) {
+            Ok(formatted) => formatted,
+            Err(e) => {
+                context.register_error(e);
+                failed += 1;
+                continue;
+            }
+        };
+        if formatted == source {
+            continue;
+        }
+        unformatted += 1;
+        if check {
+            println!("{}", path.display());
+        } else if let Err(e) = std::fs::write(&path, formatted) {
+            eprintln!("{}: {}", path.display(), e);
+            failed += 1;
+        }
+    }
+
+    context.print_all_errors(dm::Severity::Error);
+
+    if check && unformatted > 0 {
+        eprintln!("{} file(s) need formatting", unformatted);
+    }
+    std::process::exit(if failed > 0 || (check && unformatted > 0) { 1 } else { 0 });
+}
+
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) {
+    if !path.is_dir() {
+        files.push(path.to_owned());
+        return;
+    }
+    let mut entries: Vec<_> = match std::fs::read_dir(path) {
+        Ok(entries) => entries.filter_map(Result::ok).map(|entry| entry.path()).collect(),
+        Err(e) => {
+            eprintln!("{}: {}", path.display(), e);
+            return;
+        }
+    };
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() {
+            collect_files(&entry, files);
+        } else if entry.extension().map_or(false, |ext| ext == "dm") {
+            files.push(entry);
+        }
+    }
+}
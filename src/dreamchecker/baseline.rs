@@ -0,0 +1,200 @@
+//! Baselines of known diagnostics, so that only new ones need to fail a build.
+//!
+//! Each diagnostic is fingerprinted by its errortype, the type, var, or proc
+//! it appears in, and its description with any numbers removed. Line numbers
+//! are left out so that the fingerprint survives edits elsewhere in the file.
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use dm::{Context, DMError, FileId, Location};
+use dm::objtree::ObjectTree;
+
+const HEADER: &str = "# dreamchecker baseline v1";
+
+/// A diagnostic's identity, independent of its exact position.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fingerprint {
+    /// The errortype, or `-` if there is none.
+    pub errortype: String,
+    /// The path of the enclosing proc, var, or type, or the file name if
+    /// there is none.
+    pub scope: String,
+    /// The description, with runs of digits replaced by `#`.
+    pub message: String,
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}\t{}\t{}", self.errortype, self.scope, self.message)
+    }
+}
+
+impl FromStr for Fingerprint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Fingerprint, String> {
+        let mut parts = s.splitn(3, '\t');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(errortype), Some(scope), Some(message)) => Ok(Fingerprint {
+                errortype: errortype.to_owned(),
+                scope: scope.to_owned(),
+                message: message.to_owned(),
+            }),
+            _ => Err(format!("malformed baseline entry: {:?}", s)),
+        }
+    }
+}
+
+/// Finds the type, var, or proc definition enclosing a location.
+pub struct Scopes<'ctx> {
+    context: &'ctx Context,
+    by_file: HashMap<FileId, Vec<(Location, String)>>,
+}
+
+impl<'ctx> Scopes<'ctx> {
+    pub fn new(context: &'ctx Context, objtree: &ObjectTree) -> Scopes<'ctx> {
+        let mut by_file: HashMap<FileId, Vec<(Location, String)>> = HashMap::new();
+        let mut add = |location: Location, path: String| {
+            if !location.is_builtins() && location.line > 0 {
+                by_file.entry(location.file).or_default().push((location, path));
+            }
+        };
+        objtree.root().recurse(&mut |ty| {
+            add(ty.location, ty.path.clone());
+            for (name, var) in ty.vars.iter() {
+                add(var.value.location, format!("{}/var/{}", ty.path, name));
+            }
+            for (name, proc) in ty.procs.iter() {
+                for value in proc.value.iter() {
+                    add(value.location, format!("{}/proc/{}", ty.path, name));
+                }
+            }
+        });
+        for entries in by_file.values_mut() {
+            entries.sort();
+        }
+        Scopes { context, by_file }
+    }
+
+    /// The path of the nearest definition at or before the location in the
+    /// same file.
+    pub fn scope_of(&self, location: Location) -> String {
+        if let Some(entries) = self.by_file.get(&location.file) {
+            let after = entries.iter().position(|&(each, _)| each > location).unwrap_or(entries.len());
+            if after > 0 {
+                return entries[after - 1].1.clone();
+            }
+        }
+        self.context.file_path(location.file).display().to_string()
+    }
+
+    pub fn fingerprint(&self, error: &DMError) -> Fingerprint {
+        Fingerprint {
+            errortype: error.errortype().unwrap_or("-").to_owned(),
+            scope: self.scope_of(error.location()),
+            message: normalize(error.description()),
+        }
+    }
+}
+
+/// Replace runs of digits with `#` and collapse whitespace.
+fn normalize(description: &str) -> String {
+    let mut out = String::with_capacity(description.len());
+    let mut in_digits = false;
+    for ch in description.split_whitespace().collect::<Vec<_>>().join(" ").chars() {
+        if ch.is_ascii_digit() {
+            if !in_digits {
+                out.push('#');
+            }
+            in_digits = true;
+        } else {
+            out.push(ch);
+            in_digits = false;
+        }
+    }
+    out
+}
+
+/// A multiset of diagnostic fingerprints.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Baseline {
+    counts: BTreeMap<Fingerprint, usize>,
+}
+
+/// The difference between a baseline and the current diagnostics.
+#[derive(Debug)]
+pub struct Comparison<'e> {
+    /// Diagnostics which are not in the baseline.
+    pub new: Vec<&'e DMError>,
+    /// Baseline entries which no longer occur, and how many times.
+    pub gone: Vec<(Fingerprint, usize)>,
+}
+
+impl Baseline {
+    pub fn from_errors<'e, I>(scopes: &Scopes, errors: I) -> Baseline
+    where
+        I: IntoIterator<Item=&'e DMError>,
+    {
+        let mut baseline = Baseline::default();
+        for error in errors {
+            *baseline.counts.entry(scopes.fingerprint(error)).or_insert(0) += 1;
+        }
+        baseline
+    }
+
+    /// The total number of diagnostics in the baseline.
+    pub fn len(&self) -> usize {
+        self.counts.values().sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    pub fn read(path: &Path) -> io::Result<Baseline> {
+        let mut baseline = Baseline::default();
+        for line in fs::read_to_string(path)?.lines() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fingerprint = line.parse().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            *baseline.counts.entry(fingerprint).or_insert(0) += 1;
+        }
+        Ok(baseline)
+    }
+
+    /// Write one line per diagnostic, sorted, so that changes to the
+    /// baseline are easy to review.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let mut out = io::BufWriter::new(fs::File::create(path)?);
+        writeln!(out, "{}", HEADER)?;
+        for (fingerprint, &count) in self.counts.iter() {
+            for _ in 0..count {
+                writeln!(out, "{}", fingerprint)?;
+            }
+        }
+        out.flush()
+    }
+
+    /// Find the diagnostics which exceed the baseline, and the baseline
+    /// entries which no longer occur.
+    pub fn compare<'e, I>(&self, scopes: &Scopes, errors: I) -> Comparison<'e>
+    where
+        I: IntoIterator<Item=&'e DMError>,
+    {
+        let mut remaining = self.counts.clone();
+        let mut new = Vec::new();
+        for error in errors {
+            match remaining.get_mut(&scopes.fingerprint(error)) {
+                Some(count) if *count > 0 => *count -= 1,
+                _ => new.push(error),
+            }
+        }
+        let gone = remaining.into_iter().filter(|&(_, count)| count > 0).collect();
+        Comparison { new, gone }
+    }
+}
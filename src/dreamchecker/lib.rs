@@ -6,14 +6,21 @@
 extern crate dreammaker as dm;
 use dm::{Context, DMError, Location, Severity};
 use dm::objtree::{ObjectTree, TypeRef, ProcRef, Code};
+use dm::config::ByondVersion;
 use dm::constants::{Constant, ConstFn};
 use dm::ast::*;
 
 use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::path::Path;
 
 mod type_expr;
 use type_expr::TypeExpr;
 
+mod unused;
+
+pub mod report;
+pub mod baseline;
+
 #[doc(hidden)]  // Intended for the tests only.
 pub mod test_helpers;
 
@@ -80,6 +87,8 @@ enum Assumption<'o> {
     IsType(bool, TypeRef<'o>),
     IsPath(bool, TypeRef<'o>),
     IsTypeVar(bool, TypeRef<'o>),
+    /// Null on at least one path, such as the result of `locate()`.
+    MaybeNull,
 }
 
 impl<'o> Assumption<'o> {
@@ -165,6 +174,37 @@ impl<'o> AssumptionSet<'o> {
         assumption_set![Assumption::Truthy(true), Assumption::IsNull(false), Assumption::IsType(true, ty)]
     }
 
+    fn may_be_null(&self) -> bool {
+        self.set.contains(&Assumption::IsNull(true)) || self.set.contains(&Assumption::MaybeNull)
+    }
+
+    /// Forget whether the value is null or truthy.
+    fn forget_null(&mut self) {
+        self.set.retain(|each| match each {
+            Assumption::IsNull(_) | Assumption::MaybeNull | Assumption::Truthy(_) => false,
+            _ => true,
+        });
+    }
+
+    fn set_maybe_null(&mut self) {
+        self.forget_null();
+        self.set.insert(Assumption::MaybeNull);
+    }
+
+    fn set_not_null(&mut self) {
+        self.forget_null();
+        self.set.insert(Assumption::IsNull(false));
+    }
+
+    /// What is known about a value which came from one of two paths.
+    fn join(&self, other: &AssumptionSet<'o>) -> AssumptionSet<'o> {
+        let mut set: HashSet<_> = self.set.intersection(&other.set).cloned().collect();
+        if (self.may_be_null() || other.may_be_null()) && !set.contains(&Assumption::IsNull(true)) {
+            set.insert(Assumption::MaybeNull);
+        }
+        AssumptionSet { set }
+    }
+
     fn conflicts_with(&self, new: &Assumption) -> Option<&Assumption> {
         for each in self.set.iter() {
             if each.oneway_conflict(new) || new.oneway_conflict(each) {
@@ -227,12 +267,34 @@ impl<'o> Analysis<'o> {
         }
     }
 
+    fn possibly_null(mut self) -> Self {
+        self.aset.set_maybe_null();
+        self
+    }
+
     fn with_fix_hint<S: Into<String>>(mut self, location: Location, desc: S) -> Self {
         if location != Location::default() {
             self.fix_hint = Some((location, desc.into()));
         }
         self
     }
+
+    /// The type this value is statically known to be an instance of.
+    fn known_type(&self) -> Option<TypeRef<'o>> {
+        self.static_ty.basic_type().or_else(|| self.aset.set.iter().find_map(|assumption| match assumption {
+            Assumption::IsType(true, ty) => Some(*ty),
+            _ => None,
+        }))
+    }
+
+    /// The type searched by a `::` operator applied to this value: either
+    /// the type of the value or, for a type path, the type itself.
+    fn scope_type(&self) -> Option<TypeRef<'o>> {
+        self.static_ty.basic_type().or_else(|| self.aset.set.iter().find_map(|assumption| match assumption {
+            Assumption::IsPath(true, ty) => Some(*ty),
+            _ => None,
+        }))
+    }
 }
 
 trait WithFixHint {
@@ -296,15 +358,21 @@ impl<'o> From<StaticType<'o>> for Analysis<'o> {
 
 /// Run DreamChecker, registering diagnostics to the context.
 pub fn run(context: &Context, objtree: &ObjectTree) {
-    run_inner(context, objtree, false)
+    run_inner(context, objtree, &[], false)
 }
 
 /// Run DreamChecker, registering diagnostics and printing progress to stdout.
 pub fn run_cli(context: &Context, objtree: &ObjectTree) {
-    run_inner(context, objtree, true)
+    run_inner(context, objtree, &[], true)
 }
 
-fn run_inner(context: &Context, objtree: &ObjectTree, cli: bool) {
+/// Run DreamChecker, counting vars set in the given maps as used, and
+/// optionally printing progress to stdout.
+pub fn run_with_maps(context: &Context, objtree: &ObjectTree, maps: &[&Path], cli: bool) {
+    run_inner(context, objtree, maps, cli)
+}
+
+fn run_inner(context: &Context, objtree: &ObjectTree, maps: &[&Path], cli: bool) {
     macro_rules! cli_println {
         ($($rest:tt)*) => {
             if cli { println!($($rest)*) }
@@ -321,6 +389,7 @@ fn run_inner(context: &Context, objtree: &ObjectTree, cli: bool) {
     let mut present = 0;
     let mut invalid = 0;
     let mut builtin = 0;
+    let mut unparsed = Unparsed::default();
 
     cli_println!("============================================================");
     cli_println!("Gathering proc settings...\n");
@@ -343,12 +412,13 @@ fn run_inner(context: &Context, objtree: &ObjectTree, cli: bool) {
                 }
                 Code::Invalid(_) => invalid += 1,
                 Code::Builtin => builtin += 1,
-                Code::Disabled => panic!("proc parsing was enabled, but also disabled. this is a bug"),
+                Code::Disabled | Code::Deferred(_) => unparsed.add(proc.location),
             }
         }
     });
+    unparsed.report(context);
 
-    cli_println!("Procs analyzed: {}. Errored: {}. Builtins: {}.\n", present, invalid, builtin);
+    cli_println!("Procs analyzed: {}. Errored: {}. Builtins: {}. Unparsed: {}.\n", present, invalid, builtin, unparsed.count);
 
     cli_println!("============================================================");
     cli_println!("Analyzing proc call tree...\n");
@@ -363,6 +433,43 @@ fn run_inner(context: &Context, objtree: &ObjectTree, cli: bool) {
     });
 
     analyzer.finish_check_kwargs();
+
+    if analyzer.wants_unused_checks() {
+        cli_println!("============================================================");
+        cli_println!("Analyzing unused procs and vars...\n");
+        analyzer.check_unused(maps);
+    }
+
+    context.register_unused_suppressions();
+}
+
+/// Proc bodies which were never parsed, because the caller disabled or
+/// deferred proc parsing, and so could not be checked.
+#[derive(Default)]
+pub(crate) struct Unparsed {
+    count: usize,
+    first: Option<Location>,
+}
+
+impl Unparsed {
+    pub(crate) fn add(&mut self, location: Location) {
+        self.count += 1;
+        self.first.get_or_insert(location);
+    }
+
+    /// Note the unchecked bodies once, rather than once per proc.
+    pub(crate) fn report(&self, context: &Context) {
+        if let Some(first) = self.first {
+            let description = if self.count == 1 {
+                "1 proc body was never parsed, so it was not checked".to_owned()
+            } else {
+                format!("{} proc bodies were never parsed, so they were not checked", self.count)
+            };
+            error(first, description)
+                .set_severity(Severity::Info)
+                .register(context);
+        }
+    }
 }
 
 // ----------------------------------------------------------------------------
@@ -387,6 +494,17 @@ struct KwargInfo {
     bad_overrides_at: BTreeMap<String, BadOverride>,
 }
 
+/// What the call sites of a proc and its overrides pass to it.
+#[derive(Default)]
+struct PassedArgs<'o> {
+    /// The most positional arguments passed by any call.
+    positional: usize,
+    keywords: HashSet<&'o str>,
+    /// Whether any call passes arguments which can't be counted, such as
+    /// `arglist()` or a bare `..()`.
+    all: bool,
+}
+
 /// Struct for SpacemanDMM_* directives
 struct ProcDirective<'o> {
     directive: HashMap<ProcRef<'o>, (bool, Location)>,
@@ -537,6 +655,19 @@ pub struct AnalyzeObjectTree<'o> {
     used_kwargs: BTreeMap<String, KwargInfo>,
 
     call_tree: HashMap<ProcRef<'o>, Vec<(ProcRef<'o>, Location)>>,
+    /// Procs referenced by path, such as `/proc/x`, rather than called.
+    referenced_procs: HashSet<ProcRef<'o>>,
+    /// Names of procs which may be called by a call that can't be resolved.
+    dynamic_proc_names: HashSet<&'o str>,
+    /// Whether `possible_null_access` is enabled.
+    check_null_access: bool,
+    /// The arguments passed to each proc, keyed by its first declaration.
+    passed_args: HashMap<ProcRef<'o>, PassedArgs<'o>>,
+    /// Whether each proc may use more arguments than it declares.
+    extra_args: HashMap<ProcRef<'o>, bool>,
+    /// The most positional arguments each proc may be passed, either
+    /// exactly or through any of its overrides.
+    max_args: HashMap<(ProcRef<'o>, bool), Option<usize>>,
 
     sleeping_procs: ViolatingProcs<'o>,
     impure_procs: ViolatingProcs<'o>,
@@ -561,6 +692,12 @@ impl<'o> AnalyzeObjectTree<'o> {
             must_be_pure: ProcDirective::new("SpacemanDMM_should_be_pure", false, true, true),
             used_kwargs: Default::default(),
             call_tree: Default::default(),
+            referenced_procs: Default::default(),
+            dynamic_proc_names: Default::default(),
+            check_null_access: context.opted_in("possible_null_access"),
+            passed_args: Default::default(),
+            extra_args: Default::default(),
+            max_args: Default::default(),
             sleeping_procs: Default::default(),
             impure_procs: Default::default(),
             waitfor_procs: Default::default(),
@@ -862,6 +999,53 @@ impl<'o> AnalyzeObjectTree<'o> {
         }
     }
 
+    /// Whether a proc may use more arguments than it declares, because it is
+    /// varargs or reads `args`.
+    fn takes_extra_args(&mut self, proc: ProcRef<'o>) -> bool {
+        if let Some(&extra) = self.extra_args.get(&proc) {
+            return extra
+        }
+        let extra = proc.is_varargs() || match proc.get().code {
+            Code::Present(ref code) => {
+                let mut read = ReadNames::default();
+                read.visit_block(code);
+                read.names.contains("args")
+            },
+            _ => false,
+        };
+        self.extra_args.insert(proc, extra);
+        extra
+    }
+
+    /// The most positional arguments a call to a proc may pass, or `None` if
+    /// there is no limit.
+    fn max_arguments(&mut self, proc: ProcRef<'o>, is_exact: bool) -> Option<usize> {
+        if let Some(&max) = self.max_args.get(&(proc, is_exact)) {
+            return max
+        }
+        // Overrides may add parameters, or leave out parameters which are
+        // still passed to the proc they override.
+        let mut procs = Vec::new();
+        let mut next = Some(proc);
+        while let Some(current) = next {
+            procs.push(current);
+            next = current.parent_proc();
+        }
+        if !is_exact {
+            proc.recurse_children(&mut |child| procs.push(child));
+        }
+        let mut max = Some(0);
+        for each in procs {
+            if self.takes_extra_args(each) {
+                max = None;
+                break;
+            }
+            max = max.map(|max| max.max(each.parameters.len()));
+        }
+        self.max_args.insert((proc, is_exact), max);
+        max
+    }
+
     fn static_type(&mut self, location: Location, of: &[String]) -> StaticType<'o> {
         match static_type(self.objtree, location, of) {
             Ok(s) => s,
@@ -893,6 +1077,15 @@ fn static_type<'o>(objtree: &'o ObjectTree, location: Location, mut of: &[String
     }
 }
 
+/// The first declaration of a proc which the given one overrides.
+fn base_proc(proc: ProcRef) -> ProcRef {
+    let mut base = proc;
+    while let Some(parent) = base.parent_proc() {
+        base = parent;
+    }
+    base
+}
+
 fn error<S: Into<String>>(location: Location, desc: S) -> DMError {
     DMError::new(location, desc).with_component(dm::Component::DreamChecker)
 }
@@ -958,6 +1151,46 @@ pub fn check_var_defs(objtree: &ObjectTree, context: &Context) {
 
 // ----------------------------------------------------------------------------
 // Procedure analyzer
+
+/// Builtin procs which return null when nothing is found, like `locate()`.
+const NULLABLE_BUILTINS: &[&str] = &[
+    "get_step",
+    "get_step_away",
+    "get_step_rand",
+    "get_step_to",
+    "get_step_towards",
+    "text2path",
+];
+
+/// Builtin checks which are only true of instances of a type, besides
+/// `istype()` itself.
+const TYPE_CHECKS: &[(&str, &str)] = &[
+    ("isarea", "/area"),
+    ("islist", "/list"),
+    ("isloc", "/atom"),
+    ("ismob", "/mob"),
+    ("ismovable", "/atom/movable"),
+    ("isobj", "/obj"),
+    ("isturf", "/turf"),
+];
+
+/// Builtin checks which are only true of non-null values.
+const NON_NULL_CHECKS: &[&str] = &[
+    "istype",
+    "isarea",
+    "isfile",
+    "isicon",
+    "islist",
+    "isloc",
+    "ismob",
+    "ismovable",
+    "isnum",
+    "isobj",
+    "ispath",
+    "istext",
+    "isturf",
+];
+
 #[derive(Debug)]
 pub struct ControlFlow {
     pub returns: bool,
@@ -991,6 +1224,12 @@ impl ControlFlow {
         return !self.fuzzy && ( self.returns || self.breaks )
     }
 
+    /// Whether the block never falls through to the next statement, even if
+    /// it may leave an outer loop rather than the nearest one.
+    pub fn jumps(&self) -> bool {
+        return self.returns || self.continues || self.breaks
+    }
+
     pub fn no_else(&mut self) {
         self.returns = false;
         self.continues = false;
@@ -1048,11 +1287,109 @@ impl ControlFlow {
 struct LocalVar<'o> {
     location: Location,
     analysis: Analysis<'o>,
+    /// The declared type, which `analysis` may narrow within a branch.
+    declared_ty: StaticType<'o>,
 }
 
 impl<'o> From<Analysis<'o>> for LocalVar<'o> {
     fn from(analysis: Analysis<'o>) -> Self {
-        LocalVar { location: Location::default(), analysis }
+        LocalVar { location: Location::default(), declared_ty: analysis.static_ty.clone(), analysis }
+    }
+}
+
+impl<'o> LocalVar<'o> {
+    fn new(location: Location, analysis: Analysis<'o>) -> Self {
+        LocalVar { location, declared_ty: analysis.static_ty.clone(), analysis }
+    }
+}
+
+/// Whether a condition may narrow the type of a local, which is tracked even
+/// without null tracking.
+fn narrows_type(condition: &Expression) -> bool {
+    match condition {
+        Expression::Base { term, follow, .. } if follow.is_empty() => match &term.elem {
+            Term::Expr(expr) => narrows_type(expr),
+            Term::Call(name, _) => name == "istype" || TYPE_CHECKS.iter().any(|&(check, _)| check == name.as_str()),
+            _ => false,
+        },
+        Expression::BinaryOp { op: BinaryOp::And, lhs, rhs } |
+        Expression::BinaryOp { op: BinaryOp::Or, lhs, rhs } => narrows_type(lhs) || narrows_type(rhs),
+        _ => false,
+    }
+}
+
+/// Record whether a local compared against null is null.
+fn narrow_null<'o>(expr: &Expression, is_null: bool, local_vars: &mut HashMap<String, LocalVar<'o>>) {
+    let name = match expr.as_term() {
+        Some(Term::Ident(name)) => name,
+        _ => return,
+    };
+    if let Some(var) = local_vars.get_mut(name) {
+        if is_null {
+            var.analysis.aset.forget_null();
+            var.analysis.aset.set.insert(Assumption::IsNull(true));
+        } else {
+            var.analysis.aset.set_not_null();
+        }
+    }
+}
+
+/// Collects the name of every identifier read in a proc body. Assigning to a
+/// name with `=`, or passing it as a keyword argument, does not read it.
+#[derive(Default)]
+struct ReadNames<'o> {
+    names: HashSet<&'o str>,
+    /// Fields accessed on any value, including string list keys and the vars
+    /// set by prefabs, which may name a var.
+    fields: HashSet<&'o str>,
+}
+
+impl<'o> Visitor<'o> for ReadNames<'o> {
+    fn visit_expression(&mut self, expression: &'o Expression) {
+        if let Expression::AssignOp { op: AssignOp::Assign, lhs, rhs } = expression {
+            if let Some(Term::Ident(_)) = lhs.as_term() {
+                return self.visit_expression(rhs);
+            }
+        }
+        walk_expression(self, expression)
+    }
+
+    fn visit_term(&mut self, location: Location, term: &'o Term) {
+        match term {
+            Term::Ident(name) | Term::GlobalIdent(name) => { self.names.insert(name); },
+            _ => {},
+        }
+        walk_term(self, location, term)
+    }
+
+    fn visit_follow(&mut self, location: Location, follow: &'o Follow) {
+        match follow {
+            Follow::Field(_, name) => { self.fields.insert(name); },
+            Follow::Index(_, expr) => if let Some(Term::String(key)) = expr.as_term() {
+                self.fields.insert(key);
+            },
+            Follow::Call(..) => {},
+        }
+        walk_follow(self, location, follow)
+    }
+
+    fn visit_index_or_field(&mut self, index_or_field: &'o IndexOrField) {
+        if let IndexOrField::Field(_, name) = index_or_field {
+            self.fields.insert(name);
+        }
+        walk_index_or_field(self, index_or_field)
+    }
+
+    fn visit_new_type(&mut self, new_type: &'o NewType) {
+        if let NewType::MiniExpr { ident, .. } = new_type {
+            self.names.insert(ident);
+        }
+        walk_new_type(self, new_type)
+    }
+
+    fn visit_prefab(&mut self, prefab: &'o Prefab) {
+        self.fields.extend(prefab.vars.keys().map(|name| &name[..]));
+        walk_prefab(self, prefab)
     }
 }
 
@@ -1064,6 +1401,8 @@ struct AnalyzeProc<'o, 's> {
     proc_ref: ProcRef<'o>,
     calls_parent: bool,
     inside_newcontext: u32,
+    /// Every name read in the proc body, if unused locals are being checked.
+    read_names: Option<HashSet<&'o str>>,
 }
 
 impl<'o, 's> AnalyzeProc<'o, 's> {
@@ -1078,6 +1417,7 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
             proc_ref,
             calls_parent: false,
             inside_newcontext: 0,
+            read_names: None,
         }
     }
 
@@ -1100,15 +1440,25 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
         for param in self.proc_ref.get().parameters.iter() {
             let mut analysis = self.static_type(param.location, &param.var_type.type_path);
             analysis.is_impure = Some(true); // all params are impure
-            local_vars.insert(param.name.to_owned(), LocalVar {
-                location: self.proc_ref.location,
-                analysis,
-            });
+            local_vars.insert(param.name.to_owned(), LocalVar::new(self.proc_ref.location, analysis));
             //println!("adding parameters {:#?}", self.local_vars);
         }
 
         self.env.call_tree.insert(self.proc_ref, Default::default());
 
+        let check_locals = self.context.opted_in("unused_local");
+        let check_arguments = self.context.opted_in("unused_argument");
+        if check_locals || check_arguments {
+            let mut read = ReadNames::default();
+            read.visit_block(block);
+            if check_arguments {
+                self.check_unused_arguments(block, &read.names);
+            }
+            if check_locals {
+                self.read_names = Some(read.names);
+            }
+        }
+
         self.visit_block(block, &mut local_vars);
 
         //println!("purity {}", self.is_pure);
@@ -1141,20 +1491,67 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
         }
     }
 
+    fn check_unused_arguments(&self, block: &'o [Spanned<Statement>], read_names: &HashSet<&'o str>) {
+        // Overrides must accept the parent's arguments whether they use them
+        // or not, and an empty proc is usually meant to be overridden. A proc
+        // which reads `args` may use any of its arguments through it.
+        if self.proc_ref.parent_proc().is_some() || block.is_empty() || read_names.contains("args") {
+            return;
+        }
+        let unused: Vec<&Parameter> = self.proc_ref.get().parameters.iter()
+            .filter(|param| !param.name.starts_with('_') && !read_names.contains(&param.name[..]))
+            .collect();
+        if unused.is_empty() {
+            return;
+        }
+        // A proc which is overridden defines the arguments its overrides
+        // take, which may use them even if it does not.
+        let mut overridden = false;
+        self.proc_ref.recurse_children(&mut |child| overridden |= child != self.proc_ref);
+        if overridden {
+            return;
+        }
+        for param in unused {
+            error(param.location, format!("unused argument: {:?}", param.name))
+                .set_severity(Severity::Hint)
+                .with_errortype("unused_argument")
+                .register(self.context);
+        }
+    }
+
     fn visit_block(&mut self, block: &'o [Spanned<Statement>], local_vars: &mut HashMap<String, LocalVar<'o>>) -> ControlFlow {
         let mut term = ControlFlow::allfalse();
+        // Set after a `break` or `continue`, which are fuzzy because they may
+        // name an outer loop, but always end this block.
+        let mut jumped = false;
         for stmt in block.iter() {
-            if term.terminates() {
+            if let Statement::Label { .. } = stmt.elem {
+                // A label may be reached by `goto` even after the block ends.
+                term = ControlFlow::allfalse();
+                jumped = false;
+            } else if term.terminates() || jumped {
                 error(stmt.location,"possible unreachable code here")
+                    .with_errortype("unreachable_code")
                     .register(self.context);
                 return term // stop evaluating
             }
             let state = self.visit_statement(stmt.location, &stmt.elem, local_vars);
             term.merge(state);
+            if let Statement::Break(_) | Statement::Continue(_) = stmt.elem {
+                jumped = true;
+            }
         }
         return term
     }
 
+    fn unreachable_block(&self, block: &'o [Spanned<Statement>], desc: &str) {
+        if let Some(first) = block.first() {
+            error(first.location, desc)
+                .with_errortype("unreachable_code")
+                .register(self.context);
+        }
+    }
+
     fn loop_condition_check(&mut self, location: Location, expression: &'o Expression) {
         match expression.is_truthy() {
             Some(true) => {
@@ -1182,6 +1579,234 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
         }
     }
 
+    /// Forget what is known about a local's value, such as a loop var.
+    fn forget_value(&self, name: &str, local_vars: &mut HashMap<String, LocalVar<'o>>) {
+        if let Some(var) = local_vars.get_mut(name) {
+            var.analysis.static_ty = var.declared_ty.clone();
+            if self.env.check_null_access {
+                var.analysis.aset.forget_null();
+                var.analysis.value = None;
+            }
+        }
+    }
+
+    /// Join what is known about each local at the end of each branch which
+    /// flows onward, such as the arms of an `if`. Without null tracking, only
+    /// narrowed types are carried.
+    fn merge_locals(&self, local_vars: &mut HashMap<String, LocalVar<'o>>, ends: &[HashMap<String, LocalVar<'o>>]) {
+        guard!(let Some((first, rest)) = ends.split_first() else {
+            return  // nothing flows onward
+        });
+        for (name, var) in local_vars.iter_mut() {
+            // The return value is tracked on its own.
+            if name == "." {
+                continue;
+            }
+            if let Some(end) = first.get(name) {
+                var.analysis.static_ty = end.analysis.static_ty.clone();
+                if self.env.check_null_access {
+                    var.analysis.aset = end.analysis.aset.clone();
+                    var.analysis.value = end.analysis.value.clone();
+                }
+            }
+        }
+        for end in rest {
+            self.join_locals(local_vars, end);
+        }
+    }
+
+    /// Join what is known about each local with another path to the same
+    /// point, such as the end of a loop body.
+    fn join_locals(&self, local_vars: &mut HashMap<String, LocalVar<'o>>, other: &HashMap<String, LocalVar<'o>>) {
+        for (name, var) in local_vars.iter_mut() {
+            if name == "." {
+                continue;
+            }
+            if let Some(other) = other.get(name) {
+                if var.analysis.static_ty != other.analysis.static_ty {
+                    // Narrowed differently on each path.
+                    var.analysis.static_ty = var.declared_ty.clone();
+                }
+                if self.env.check_null_access {
+                    var.analysis.aset = var.analysis.aset.join(&other.analysis.aset);
+                    if var.analysis.value != other.analysis.value {
+                        var.analysis.value = None;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Update what is known about locals in a condition, given whether it
+    /// evaluated truthy.
+    fn narrow(&self, condition: &Expression, truthy: bool, local_vars: &mut HashMap<String, LocalVar<'o>>) {
+        match condition {
+            Expression::Base { unary, term, follow } if follow.is_empty() => {
+                self.narrow_term(unary, &term.elem, truthy, local_vars)
+            },
+            Expression::BinaryOp { op: BinaryOp::And, lhs, rhs } if truthy => {
+                self.narrow(lhs, true, local_vars);
+                self.narrow(rhs, true, local_vars);
+            },
+            Expression::BinaryOp { op: BinaryOp::Or, lhs, rhs } if !truthy => {
+                self.narrow(lhs, false, local_vars);
+                self.narrow(rhs, false, local_vars);
+            },
+            Expression::BinaryOp { op, lhs, rhs } if self.env.check_null_access && (*op == BinaryOp::Eq || *op == BinaryOp::NotEq) => {
+                let is_null = truthy == (*op == BinaryOp::Eq);
+                match (lhs.as_term(), rhs.as_term()) {
+                    (_, Some(Term::Null)) => narrow_null(lhs, is_null, local_vars),
+                    (Some(Term::Null), _) => narrow_null(rhs, is_null, local_vars),
+                    _ => {},
+                }
+            },
+            _ => {},
+        }
+    }
+
+    fn narrow_term(&self, unary: &[UnaryOp], term: &Term, truthy: bool, local_vars: &mut HashMap<String, LocalVar<'o>>) {
+        match unary.split_first() {
+            Some((UnaryOp::Not, rest)) => return self.narrow_term(rest, term, !truthy, local_vars),
+            Some(_) => return,
+            None => {},
+        }
+        match term {
+            Term::Expr(expr) => self.narrow(expr, truthy, local_vars),
+            Term::Ident(name) if self.env.check_null_access => if let Some(var) = local_vars.get_mut(name) {
+                if truthy {
+                    var.analysis.aset.set_not_null();
+                    var.analysis.aset.set.insert(Assumption::Truthy(true));
+                } else if !var.analysis.aset.set.contains(&Assumption::IsNull(true)) {
+                    // Could also be 0 or "".
+                    var.analysis.aset.set_maybe_null();
+                }
+            },
+            Term::Call(name, args) if self.env.check_null_access && name == "isnull" => if let Some(arg) = args.first() {
+                narrow_null(arg, truthy, local_vars);
+            },
+            Term::Call(name, args) if truthy && NON_NULL_CHECKS.contains(&name.as_str()) => {
+                if self.env.check_null_access {
+                    if let Some(arg) = args.first() {
+                        narrow_null(arg, false, local_vars);
+                    }
+                }
+                if name == "istype" {
+                    if let [arg, Expression::Base { unary, term, follow }] = &args[..] {
+                        if let (true, true, Term::Prefab(prefab)) = (unary.is_empty(), follow.is_empty(), &term.elem) {
+                            if let Some(nav) = self.ty.navigate_path(&prefab.path) {
+                                self.narrow_type(arg, nav.ty(), local_vars);
+                            }
+                        }
+                    }
+                } else if let Some(&(_, path)) = TYPE_CHECKS.iter().find(|&&(check, _)| check == name.as_str()) {
+                    let ty = self.objtree.expect(path);
+                    for arg in args.iter() {
+                        self.narrow_type(arg, ty, local_vars);
+                    }
+                }
+            },
+            _ => {},
+        }
+    }
+
+    /// Record that a local passed an `istype()`-style check.
+    fn narrow_type(&self, expr: &Expression, ty: TypeRef<'o>, local_vars: &mut HashMap<String, LocalVar<'o>>) {
+        guard!(let Some(Term::Ident(name)) = expr.as_term() else { return });
+        guard!(let Some(var) = local_vars.get_mut(name) else { return });
+        // Keep a type which is already more specific.
+        if let Some(current) = var.analysis.static_ty.basic_type() {
+            if current.is_subtype_of(ty.get()) {
+                return
+            }
+        }
+        var.analysis.static_ty = if ty == self.objtree.expect("/list") {
+            StaticType::plain_list(self.objtree)
+        } else {
+            StaticType::Type(ty)
+        };
+    }
+
+    /// Visit the right side of `&&` or `||`, which is only evaluated when the
+    /// left side is truthy or falsy respectively.
+    fn visit_short_circuit(&mut self, location: Location, lhs: &'o Expression, truthy: bool, rhs: &'o Expression, type_hint: Option<TypeRef<'o>>, local_vars: &mut HashMap<String, LocalVar<'o>>) -> Analysis<'o> {
+        if !self.env.check_null_access && !narrows_type(lhs) {
+            return self.visit_expression(location, rhs, type_hint, local_vars)
+        }
+        let mut rhs_locals = local_vars.clone();
+        self.narrow(lhs, truthy, &mut rhs_locals);
+        let analysis = self.visit_expression(location, rhs, type_hint, &mut rhs_locals);
+        self.join_locals(local_vars, &rhs_locals);
+        analysis
+    }
+
+    /// If the given errortype is enabled and both types are known, find
+    /// whether a value's type is unrelated to the type it is stored as.
+    /// Returns the expected and actual types on a mismatch. Storing a parent
+    /// type as one of its subtypes is allowed, as with `var/obj/O = loc`.
+    fn type_mismatch(&self, expected: &StaticType<'o>, actual: &Analysis<'o>, errortype: &str) -> Option<(TypeRef<'o>, TypeRef<'o>)> {
+        if !self.context.opted_in(errortype) {
+            return None
+        }
+        let expected = expected.basic_type()?;
+        let actual = actual.known_type()?;
+        if actual.is_subtype_of(expected.get()) || expected.is_subtype_of(actual.get()) {
+            return None
+        }
+        Some((expected, actual))
+    }
+
+    /// Report a use of a builtin newer than the target BYOND version,
+    /// returning whether one was reported.
+    fn newer_builtin(&self, location: Location, name: &str, since: Option<ByondVersion>) -> bool {
+        match (since, self.context.config().byond_version()) {
+            (Some(since), Some(target)) => {
+                error(location, format!("{:?} requires BYOND {}, but the target version is {}", name, since, target))
+                    .set_severity(Severity::Warning)
+                    .with_errortype("byond_version")
+                    .register(self.context);
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Report a call to a proc which doesn't exist, or which is a builtin
+    /// newer than the target BYOND version.
+    fn undefined_proc(&self, location: Location, ty: TypeRef<'o>, name: &str) {
+        if !self.newer_builtin(location, name, self.objtree.newer_builtin_proc(ty, name)) {
+            error(location, format!("undefined proc: {:?} on {}", name, ty))
+                .register(self.context);
+        }
+    }
+
+    fn check_null_access(&mut self, location: Location, name: &str, follow: &Follow, local_vars: &mut HashMap<String, LocalVar<'o>>) {
+        match follow {
+            Follow::Field(IndexKind::Dot, _) |
+            Follow::Field(IndexKind::Colon, _) |
+            Follow::Call(IndexKind::Dot, _, _) |
+            Follow::Call(IndexKind::Colon, _, _) => {},
+            _ => return,
+        }
+        if !self.env.check_null_access {
+            return
+        }
+        guard!(let Some(var) = local_vars.get_mut(name) else { return });
+        let desc = if var.analysis.aset.set.contains(&Assumption::IsNull(true)) {
+            "is null here"
+        } else if var.analysis.aset.set.contains(&Assumption::MaybeNull) {
+            "may be null here"
+        } else {
+            return
+        };
+        error(location, format!("local var {:?} {}", name, desc))
+            .set_severity(Severity::Warning)
+            .with_errortype("possible_null_access")
+            .with_note(var.location, "declared here")
+            .register(self.context);
+        // Execution only continues past the access if it wasn't null.
+        var.analysis.aset.set_not_null();
+    }
+
     fn visit_statement(&mut self, location: Location, statement: &'o Statement, local_vars: &mut HashMap<String, LocalVar<'o>>) -> ControlFlow {
         match statement {
             Statement::Expr(expr) => {
@@ -1212,6 +1837,15 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
             Statement::Return(Some(expr)) => {
                 // TODO: factor in the previous return type if there was one
                 let return_type = self.visit_expression(location, expr, None, local_vars);
+                if let Some(TypeExpr::Static(declared)) = self.env.return_type.get(&self.proc_ref) {
+                    if let Some((expected, actual)) = self.type_mismatch(declared, &return_type, "return_type_mismatch") {
+                        error(location, format!("returning {} from {}, which has return type {}", actual, self.proc_ref, expected))
+                            .set_severity(Severity::Warning)
+                            .with_errortype("return_type_mismatch")
+                            .with_fix_hint(&return_type)
+                            .register(self.context);
+                    }
+                }
                 local_vars.get_mut(".").unwrap().analysis = return_type;
                 // TODO: break out of the analysis for this branch?
                 return ControlFlow { returns: true, continues: false, breaks: false, fuzzy: false }
@@ -1225,7 +1859,14 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
             Statement::While { condition, block } => {
                 let mut scoped_locals = local_vars.clone();
                 self.visit_expression(location, condition, None, &mut scoped_locals);
+                if condition.is_truthy() == Some(false) {
+                    self.unreachable_block(block, "unreachable while block, condition is always false");
+                }
+                self.narrow(condition, true, &mut scoped_locals);
                 let mut state = self.visit_block(block, &mut scoped_locals);
+                // Locals may hold their value from before the loop or from
+                // any iteration of it.
+                self.join_locals(local_vars, &scoped_locals);
                 state.end_loop();
                 return state
             },
@@ -1238,6 +1879,8 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
                     return state
                 }
                 self.visit_expression(location, condition, None, &mut scoped_locals);
+                // The body always runs at least once.
+                self.merge_locals(local_vars, &[scoped_locals]);
 
                 state.end_loop();
                 return state
@@ -1245,15 +1888,25 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
             Statement::If { arms, else_arm } => {
                 let mut allterm = ControlFlow::alltrue();
                 let mut alwaystrue = false;
+                // The locals where every condition so far was false, and at
+                // the end of each arm which flows past the if.
+                let mut else_locals = local_vars.clone();
+                let mut ends = Vec::new();
                 for (condition, ref block) in arms.iter() {
-                    let mut scoped_locals = local_vars.clone();
                     self.visit_control_condition(condition.location, &condition.elem);
                     if alwaystrue {
                         error(condition.location,"unreachable if block, preceeding if/elseif condition(s) are always true")
+                            .with_errortype("unreachable_code")
                             .register(self.context);
                     }
-                    self.visit_expression(condition.location, &condition.elem, None, &mut scoped_locals);
+                    self.visit_expression(condition.location, &condition.elem, None, &mut else_locals);
+                    let mut scoped_locals = else_locals.clone();
+                    self.narrow(&condition.elem, true, &mut scoped_locals);
+                    self.narrow(&condition.elem, false, &mut else_locals);
                     let state = self.visit_block(block, &mut scoped_locals);
+                    if !state.jumps() {
+                        ends.push(scoped_locals);
+                    }
                     match condition.elem.is_truthy() {
                         Some(true) => {
                             error(condition.location,"if condition is always true")
@@ -1264,19 +1917,28 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
                         Some(false) => {
                             error(condition.location,"if condition is always false")
                                 .register(self.context);
+                            if !alwaystrue {
+                                self.unreachable_block(block, "unreachable if block, condition is always false");
+                            }
                         },
                         None => allterm.merge_false(state)
                     };
                 }
                 if let Some(else_arm) = else_arm {
                     if alwaystrue {
-                        // TODO: fix location for else blocks
-                        error(location,"unreachable else block, preceeding if/elseif condition(s) are always true")
-                            .register(self.context);
+                        self.unreachable_block(else_arm, "unreachable else block, preceeding if/elseif condition(s) are always true");
                     }
-                    let state = self.visit_block(else_arm, &mut local_vars.clone());
+                    let state = self.visit_block(else_arm, &mut else_locals);
+                    if !state.jumps() {
+                        ends.push(else_locals);
+                    }
+                    self.merge_locals(local_vars, &ends);
                     allterm.merge_false(state);
                 } else {
+                    if !alwaystrue {
+                        ends.push(else_locals);
+                    }
+                    self.merge_locals(local_vars, &ends);
                     allterm.no_else();
                     return allterm
                 }
@@ -1296,7 +1958,11 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
                 if let Some(inc) = inc {
                     self.visit_statement(location, inc, &mut scoped_locals);
                 }
+                if let Some(test) = test {
+                    self.narrow(test, true, &mut scoped_locals);
+                }
                 let mut state = self.visit_block(block, &mut scoped_locals);
+                self.join_locals(local_vars, &scoped_locals);
                 state.end_loop();
                 return state
             },
@@ -1308,7 +1974,22 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
                 if let Some(var_type) = var_type {
                     self.visit_var(location, var_type, name, None, &mut scoped_locals);
                 }
+                self.forget_value(name, &mut scoped_locals);
                 let mut state = self.visit_block(block, &mut scoped_locals);
+                self.join_locals(local_vars, &scoped_locals);
+                state.end_loop();
+                return state
+            },
+            Statement::ForKeyValue { var_type, key, value, in_list, block } => {
+                let mut scoped_locals = local_vars.clone();
+                self.visit_expression(location, in_list, None, &mut scoped_locals);
+                if let Some(var_type) = var_type {
+                    self.visit_var(location, var_type, key, None, &mut scoped_locals);
+                }
+                self.forget_value(key, &mut scoped_locals);
+                scoped_locals.insert(value.to_owned(), LocalVar::new(location, Analysis::empty()));
+                let mut state = self.visit_block(block, &mut scoped_locals);
+                self.join_locals(local_vars, &scoped_locals);
                 state.end_loop();
                 return state
             },
@@ -1321,12 +2002,15 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
                 if let Some(var_type) = var_type {
                     self.visit_var(location, var_type, name, Some(start), &mut scoped_locals);
                 }
+                self.forget_value(name, &mut scoped_locals);
                 let mut state = self.visit_block(block, &mut scoped_locals);
+                self.join_locals(local_vars, &scoped_locals);
                 if let Some(startterm) = start.as_term() {
                     if let Some(endterm) = end.as_term() {
                         if let Some(validity) = startterm.valid_for_range(endterm, step) {
                             if !validity {
                                 error(location,"for range loop body is never reached due to invalid range")
+                                    .with_errortype("unreachable_code")
                                     .register(self.context);
                             } else {
                                 return state
@@ -1338,7 +2022,7 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
                 return state
             },
             Statement::Var(var) => self.visit_var_stmt(location, var, local_vars),
-            Statement::Vars(vars) => {
+            Statement::Vars(vars) | Statement::Error(vars) => {
                 for each in vars.iter() {
                     self.visit_var_stmt(location, each, local_vars);
                 }
@@ -1370,6 +2054,7 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
                 let mut allterm = ControlFlow::alltrue();
                 self.visit_control_condition(location, input);
                 self.visit_expression(location, input, None, local_vars);
+                let mut ends = Vec::new();
                 for &(ref case, ref block) in cases.iter() {
                     let mut scoped_locals = local_vars.clone();
                     for case_part in case.iter() {
@@ -1382,12 +2067,22 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
                         }
                     }
                     let state = self.visit_block(block, &mut scoped_locals);
+                    if !state.jumps() {
+                        ends.push(scoped_locals);
+                    }
                     allterm.merge_false(state);
                 }
                 if let Some(default) = default {
-                    let state = self.visit_block(default, &mut local_vars.clone());
+                    let mut default_locals = local_vars.clone();
+                    let state = self.visit_block(default, &mut default_locals);
+                    if !state.jumps() {
+                        ends.push(default_locals);
+                    }
+                    self.merge_locals(local_vars, &ends);
                     allterm.merge_false(state);
                 } else {
+                    ends.push(local_vars.clone());
+                    self.merge_locals(local_vars, &ends);
                     allterm.no_else();
                     return allterm
                 }
@@ -1395,7 +2090,8 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
                 return allterm
             },
             Statement::TryCatch { try_block, catch_params, catch_block } => {
-                self.visit_block(try_block, &mut local_vars.clone());
+                let mut try_locals = local_vars.clone();
+                self.visit_block(try_block, &mut try_locals);
                 if catch_params.len() > 1 {
                     error(location, format!("Expected 0 or 1 catch parameters, got {}", catch_params.len()))
                         .set_severity(Severity::Warning)
@@ -1413,19 +2109,39 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
                     }
                     let var_type: VarType = type_path.iter().map(ToOwned::to_owned).collect();
                     self.visit_var(location, &var_type, var_name, None, &mut catch_locals);
+                    self.forget_value(var_name, &mut catch_locals);
                 }
                 self.visit_block(catch_block, &mut catch_locals);
+                // The catch block may start anywhere in the try block.
+                self.join_locals(local_vars, &try_locals);
+                self.join_locals(local_vars, &catch_locals);
             },
             Statement::Continue(_) => { return ControlFlow { returns: false, continues: true, breaks: false, fuzzy: true } },
             Statement::Break(_) => { return ControlFlow { returns: false, continues: false, breaks: true, fuzzy: true } },
             Statement::Goto(_) => {},
             Statement::Label { name: _, block } => { self.visit_block(block, &mut local_vars.clone()); },
-            Statement::Del(expr) => { self.visit_expression(location, expr, None, local_vars); },
+            Statement::Del(expr) => {
+                self.visit_expression(location, expr, None, local_vars);
+                // Deleting src ends the proc.
+                if let Some(Term::Ident(name)) = expr.as_term() {
+                    if name == "src" {
+                        return ControlFlow { returns: true, continues: false, breaks: false, fuzzy: false }
+                    }
+                }
+            },
         }
         return ControlFlow::allfalse()
     }
 
     fn visit_var_stmt(&mut self, location: Location, var: &'o VarStatement, local_vars: &mut HashMap<String, LocalVar<'o>>) {
+        if let Some(ref read_names) = self.read_names {
+            if !var.name.starts_with('_') && !read_names.contains(&var.name[..]) {
+                error(location, format!("unused local var: {:?}", var.name))
+                    .set_severity(Severity::Hint)
+                    .with_errortype("unused_local")
+                    .register(self.context);
+            }
+        }
         self.visit_var(location, &var.var_type, &var.name, var.value.as_ref(), local_vars)
     }
 
@@ -1437,10 +2153,17 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
             Some(ref expr) => self.visit_expression(location, expr, static_type.basic_type(), local_vars),
             None => Analysis::null(),
         };
+        if let Some((expected, actual)) = self.type_mismatch(&static_type, &analysis, "local_type_mismatch") {
+            error(location, format!("initializing local var {:?} of type {} with {}", name, expected, actual))
+                .set_severity(Severity::Warning)
+                .with_errortype("local_type_mismatch")
+                .with_fix_hint(&analysis)
+                .register(self.context);
+        }
         analysis.static_ty = static_type;
 
         // Save var to locals
-        local_vars.insert(name.to_owned(), LocalVar { location, analysis });
+        local_vars.insert(name.to_owned(), LocalVar::new(location, analysis));
     }
 
     fn visit_expression(&mut self, location: Location, expression: &'o Expression, type_hint: Option<TypeRef<'o>>, local_vars: &mut HashMap<String, LocalVar<'o>>) -> Analysis<'o> {
@@ -1452,6 +2175,9 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
                     None
                 };
                 let mut ty = self.visit_term(term.location, &term.elem, base_type_hint, local_vars);
+                if let (Term::Ident(name), Some(first)) = (&term.elem, follow.first()) {
+                    self.check_null_access(first.location, name, &first.elem, local_vars);
+                }
                 for each in follow.iter() {
                     ty = self.visit_follow(each.location, ty, &each.elem, local_vars);
                 }
@@ -1521,26 +2247,70 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
                 // this is the only case I've seen it used in the wild.
                 // ex: var/datum/cache_entry/E = cache[key] || new
                 let lty = self.visit_expression(location, lhs, type_hint, local_vars);
-                let rty = self.visit_expression(location, rhs, type_hint, local_vars);
+                let rty = self.visit_short_circuit(location, lhs, false, rhs, type_hint, local_vars);
                 self.visit_binary(lty, rty, BinaryOp::Or)
             },
+            Expression::BinaryOp { op: BinaryOp::And, lhs, rhs } => {
+                let lty = self.visit_expression(location, lhs, None, local_vars);
+                let rty = self.visit_short_circuit(location, lhs, true, rhs, None, local_vars);
+                self.visit_binary(lty, rty, BinaryOp::And)
+            },
             Expression::BinaryOp { op, lhs, rhs } => {
                 let lty = self.visit_expression(location, lhs, None, local_vars);
                 let rty = self.visit_expression(location, rhs, None, local_vars);
                 self.visit_binary(lty, rty, *op)
             },
-            Expression::AssignOp { lhs, rhs, .. } => {
-                let lhs = self.visit_expression(location, lhs, None, local_vars);
+            Expression::AssignOp { op, lhs: lhs_expr, rhs } => {
+                let lhs = self.visit_expression(location, lhs_expr, None, local_vars);
                 if let Some(true) = lhs.is_impure {
                     self.env.impure_procs.insert_violator(self.proc_ref, "Assignment on purity breaking expression", location);
                 }
-                self.visit_expression(location, rhs, lhs.static_ty.basic_type(), local_vars)
+                let rhs = self.visit_expression(location, rhs, lhs.static_ty.basic_type(), local_vars);
+                // Forget a narrowed type, and remember what was stored for null
+                // tracking.
+                if let Some(Term::Ident(name)) = lhs_expr.as_term() {
+                    if *op == AssignOp::Assign {
+                        if let Some(var) = local_vars.get(name) {
+                            if let Some((expected, actual)) = self.type_mismatch(&var.declared_ty, &rhs, "local_type_mismatch") {
+                                error(location, format!("assigning {} to local var {:?} of type {}", actual, name, expected))
+                                    .set_severity(Severity::Warning)
+                                    .with_errortype("local_type_mismatch")
+                                    .with_note(var.location, "declared here")
+                                    .with_fix_hint(&rhs)
+                                    .register(self.context);
+                            }
+                        }
+                    }
+                    if let Some(var) = local_vars.get_mut(name) {
+                        var.analysis.static_ty = var.declared_ty.clone();
+                        if self.env.check_null_access {
+                            if *op == AssignOp::Assign {
+                                var.analysis.aset = rhs.aset.clone();
+                                var.analysis.value = rhs.value.clone();
+                            } else {
+                                var.analysis.aset.forget_null();
+                                var.analysis.value = None;
+                            }
+                        }
+                    }
+                }
+                rhs
             },
             Expression::TernaryOp { cond, if_, else_ } => {
                 // TODO: be sensible
                 self.visit_expression(location, cond, None, local_vars);
-                let ty = self.visit_expression(location, if_, type_hint, local_vars);
-                self.visit_expression(location, else_, type_hint, local_vars);
+                if !self.env.check_null_access && !narrows_type(cond) {
+                    let ty = self.visit_expression(location, if_, type_hint, local_vars);
+                    self.visit_expression(location, else_, type_hint, local_vars);
+                    return ty
+                }
+                let mut if_locals = local_vars.clone();
+                self.narrow(cond, true, &mut if_locals);
+                let ty = self.visit_expression(location, if_, type_hint, &mut if_locals);
+                let mut else_locals = local_vars.clone();
+                self.narrow(cond, false, &mut else_locals);
+                self.visit_expression(location, else_, type_hint, &mut else_locals);
+                self.merge_locals(local_vars, &[if_locals, else_locals]);
                 ty
             }
         }
@@ -1567,8 +2337,25 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
                     ana.is_impure = Some(true);
                     return ana
                 } else {
-                    error(location, format!("undefined var: {:?}", unscoped_name))
-                        .register(self.context);
+                    if !self.newer_builtin(location, unscoped_name, self.objtree.newer_builtin_var(self.ty, unscoped_name)) {
+                        error(location, format!("undefined var: {:?}", unscoped_name))
+                            .register(self.context);
+                    }
+                    Analysis::empty()
+                }
+            },
+
+            Term::GlobalIdent(name) => {
+                if let Some(decl) = self.objtree.root().get_var_declaration(name) {
+                    let mut ana = self.static_type(location, &decl.var_type.type_path)
+                        .with_fix_hint(decl.location, "add additional type info here");
+                    ana.is_impure = Some(true);
+                    ana
+                } else {
+                    if !self.newer_builtin(location, name, self.objtree.newer_builtin_var(self.objtree.root(), name)) {
+                        error(location, format!("undefined global var: {:?}", name))
+                            .register(self.context);
+                    }
                     Analysis::empty()
                 }
             },
@@ -1576,6 +2363,9 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
             Term::Expr(expr) => self.visit_expression(location, expr, type_hint, local_vars),
             Term::Prefab(prefab) => {
                 if let Some(nav) = self.ty.navigate_path(&prefab.path) {
+                    if let dm::objtree::NavigatePathResult::ProcPath(proc, _) = nav {
+                        self.env.referenced_procs.insert(proc);
+                    }
                     let ty = nav.ty();  // TODO: handle proc/verb paths here
                     let pop = dm::constants::Pop::from(ty.path.split("/").skip(1).map(ToOwned::to_owned).collect::<Vec<_>>());
                     Analysis {
@@ -1586,6 +2376,13 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
                         is_impure: None,
                     }
                 } else {
+                    // A proc path which doesn't resolve may still name a proc
+                    // on some subtype.
+                    if prefab.path.iter().any(|(_, piece)| piece == "proc" || piece == "verb") {
+                        if let Some((_, name)) = prefab.path.last() {
+                            self.env.dynamic_proc_names.insert(name);
+                        }
+                    }
                     error(location, format!("failed to resolve path {}", FormatTypePath(&prefab.path)))
                         .register(self.context);
                     Analysis::empty()
@@ -1620,8 +2417,7 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
                     }
                     Analysis::empty()
                 } else {
-                    error(location, format!("undefined proc: {:?} on {}", unscoped_name, self.ty))
-                        .register(self.context);
+                    self.undefined_proc(location, self.ty, unscoped_name);
                     Analysis::empty()
                 }
             },
@@ -1637,7 +2433,12 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
                     // TODO: if args are empty, call w/ same args
                     let src = self.ty;
                     // Parent calls are exact, and won't ever call an override.
-                    self.visit_call(location, src, proc, args, true, local_vars)
+                    let analysis = self.visit_call(location, src, proc, args, true, local_vars);
+                    if args.is_empty() {
+                        // A bare `..()` passes along this proc's arguments.
+                        self.env.passed_args.entry(base_proc(proc)).or_default().all = true;
+                    }
+                    analysis
                 } else {
                     error(location, format!("proc has no parent: {}", self.proc_ref))
                         .register(self.context);
@@ -1685,7 +2486,7 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
                         error(location, format!("couldn't find {}/proc/New", typepath.path))
                             .register(self.context);
                     }
-                    assumption_set![Assumption::IsType(true, typepath)].into()
+                    AssumptionSet::from_valid_instance(typepath).into()
                 } else {
                     Analysis::empty()
                 }
@@ -1734,10 +2535,11 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
                     self.visit_expression(location, expr, None, local_vars);
                 }
 
+                // Null if nothing matches.
                 if args.len() == 3 {  // X,Y,Z - it's gotta be a turf
-                    assumption_set![Assumption::IsType(true, self.objtree.expect("/turf"))].into()
+                    Analysis::from(assumption_set![Assumption::IsType(true, self.objtree.expect("/turf"))]).possibly_null()
                 } else {
-                    Analysis::empty()
+                    Analysis::empty().possibly_null()
                 }
             },
             Term::Pick(choices) => {
@@ -1752,10 +2554,26 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
                 Analysis::empty()
             },
             Term::DynamicCall(lhs_args, rhs_args) => {
+                // `call(src, "name")` may call any proc with that name.
+                for arg in lhs_args.iter() {
+                    if let Some(Term::String(name)) = arg.as_term() {
+                        self.env.dynamic_proc_names.insert(name);
+                    }
+                }
                 self.visit_arguments(location, lhs_args, local_vars);
                 self.visit_arguments(location, rhs_args, local_vars);
                 Analysis::empty()  // TODO
             },
+            Term::GlobalCall(name, args) => {
+                let root = self.objtree.root();
+                if let Some(proc) = root.get_proc(name) {
+                    self.visit_call(location, root, proc, args, false, local_vars)
+                } else {
+                    error(location, format!("undefined global proc: {:?}", name))
+                        .register(self.context);
+                    Analysis::empty()
+                }
+            },
         }
     }
 
@@ -1763,8 +2581,9 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
         match rhs {
             Follow::Field(IndexKind::Colon, _) => Analysis::empty(),
             Follow::Field(IndexKind::SafeColon, _) => Analysis::empty(),
-            Follow::Call(IndexKind::Colon, _, args) |
-            Follow::Call(IndexKind::SafeColon, _, args) => {
+            Follow::Call(IndexKind::Colon, name, args) |
+            Follow::Call(IndexKind::SafeColon, name, args) => {
+                self.env.dynamic_proc_names.insert(name);
                 // No analysis yet, but be sure to visit the arguments
                 for arg in args {
                     let mut argument_value = arg;
@@ -1783,18 +2602,51 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
                 Analysis::empty()
             },
 
-            Follow::Index(expr) => {
+            Follow::Field(IndexKind::Scope, name) => {
+                // The initial value of a var, rather than the current one.
+                match lhs.scope_type() {
+                    Some(ty) => match ty.get_var_declaration(name) {
+                        Some(decl) => self.static_type(location, &decl.var_type.type_path)
+                            .with_fix_hint(decl.location, "add additional type info here"),
+                        None => {
+                            if !self.newer_builtin(location, name, self.objtree.newer_builtin_var(ty, name)) {
+                                error(location, format!("undefined field: {:?} on {}", name, ty))
+                                    .register(self.context);
+                            }
+                            Analysis::empty()
+                        }
+                    },
+                    None => Analysis::empty(),
+                }
+            },
+            Follow::Call(IndexKind::Scope, name, args) => {
+                // A reference to a proc, rather than a call to it.
+                self.visit_arguments(location, args, local_vars);
+                if let Some(ty) = lhs.scope_type() {
+                    if let Some(proc) = ty.get_proc(name) {
+                        self.env.referenced_procs.insert(proc);
+                    } else {
+                        self.undefined_proc(location, ty, name);
+                    }
+                } else {
+                    self.env.dynamic_proc_names.insert(name);
+                }
+                Analysis::empty()
+            },
+
+            Follow::Index(_, expr) => {
                 self.visit_expression(location, expr, None, local_vars);
                 // TODO: differentiate between L[1] and L[non_numeric_key]
+                // A missing key or an empty slot looks up null.
                 match lhs.static_ty {
                     StaticType::List { keys, .. } => {
                         let mut res = Analysis::from(*keys);
                         if let Some((loc, _)) = lhs.fix_hint {
                             res.fix_hint = Some((loc, "add a type annotation after /list here".to_owned()))
                         }
-                        res
+                        res.possibly_null()
                     },
-                    _ => lhs.clone()  // carry through fix_hint
+                    _ => lhs.clone().possibly_null()  // carry through fix_hint
                 }
             },
             Follow::Field(kind, name) => {
@@ -1816,8 +2668,10 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
                         self.static_type(location, &decl.var_type.type_path)
                             .with_fix_hint(decl.location, "add additional type info here")
                     } else {
-                        error(location, format!("undefined field: {:?} on {}", name, ty))
-                            .register(self.context);
+                        if !self.newer_builtin(location, name, self.objtree.newer_builtin_var(ty, name)) {
+                            error(location, format!("undefined field: {:?} on {}", name, ty))
+                                .register(self.context);
+                        }
                         Analysis::empty()
                     }
                 } else {
@@ -1851,11 +2705,11 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
                         }
                         self.visit_call(location, ty, proc, arguments, false, local_vars)
                     } else {
-                        error(location, format!("undefined proc: {:?} on {}", name, ty))
-                            .register(self.context);
+                        self.undefined_proc(location, ty, name);
                         Analysis::empty()
                     }
                 } else {
+                    self.env.dynamic_proc_names.insert(name);
                     error(location, format!("proc call requires static type: {:?}", name))
                         .set_severity(Severity::Warning)
                         .with_errortype("proc_call_static_type")
@@ -2049,6 +2903,23 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
             }
 
             let analysis = self.visit_expression(location, argument_value, None, local_vars);
+            let param = match this_kwarg {
+                Some(kw) => proc.parameters.iter().find(|p| p.name == *kw),
+                None if !arglist_used => proc.parameters.get(param_idx),
+                None => None,
+            };
+            if let Some(param) = param {
+                // Undefined types are reported where the proc is defined.
+                let declared = static_type(self.objtree, param.location, &param.var_type.type_path).unwrap_or(StaticType::None);
+                if let Some((expected, actual)) = self.type_mismatch(&declared, &analysis, "argument_type_mismatch") {
+                    error(location, format!("passing {} to argument {:?} of {}, which expects {}", actual, param.name, proc, expected))
+                        .set_severity(Severity::Warning)
+                        .with_errortype("argument_type_mismatch")
+                        .with_note(param.location, "declared here")
+                        .with_fix_hint(&analysis)
+                        .register(self.context);
+                }
+            }
             if let Some(kw) = this_kwarg {
                 param_name_map.insert(kw.as_str(), analysis);
                 param_expr_map.insert(kw.as_str(), argument_value);
@@ -2058,6 +2929,23 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
             }
         }
 
+        if !arglist_used && !proc.is_builtin() {
+            if let Some(max) = self.env.max_arguments(proc, is_exact) {
+                if param_idx > max {
+                    error(location, format!("too many arguments to {}, expected at most {} but got {}", proc, max, param_idx))
+                        .set_severity(Severity::Warning)
+                        .with_errortype("too_many_arguments")
+                        .with_note(proc.location, format!("{} is defined here", proc))
+                        .register(self.context);
+                }
+            }
+        }
+
+        let passed = self.env.passed_args.entry(base_proc(proc)).or_default();
+        passed.positional = passed.positional.max(param_idx);
+        passed.keywords.extend(param_name_map.keys());
+        passed.all |= arglist_used;
+
         // filter call checking
         // TODO: some filters have limits for their numerical params
         //  eg "rays" type "threshold" param defaults to 0.5, can be 0 to 1
@@ -2094,7 +2982,12 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
         }
 
         if proc.ty().is_root() && proc.is_builtin() {
-            Analysis::from(self.global_builtin_returntype(proc))
+            let analysis = Analysis::from(self.global_builtin_returntype(proc));
+            if NULLABLE_BUILTINS.contains(&proc.name()) {
+                analysis.possibly_null()
+            } else {
+                analysis
+            }
         } else if let Some(return_type) = self.env.return_type.get(&proc) {
             let ec = type_expr::TypeExprContext {
                 objtree: self.objtree,
@@ -14,6 +14,13 @@ fn main() {
     let mut environment = None;
     let mut config_file = None;
     let mut json = false;
+    let mut cache = None;
+    let mut format = None;
+    let mut output = None;
+    let mut baseline = None;
+    let mut write_baseline = false;
+    let mut maps = Vec::new();
+    let mut hints = false;
 
     let mut args = std::env::args();
     let _ = args.next();  // skip executable name
@@ -34,12 +41,41 @@ fn main() {
             config_file = Some(args.next().expect("must specify a file for -c"));
         } else if arg == "--json" {
             json = true;
+        } else if arg == "--cache" {
+            cache = Some(args.next().expect("must specify a file for --cache"));
+        } else if arg == "--format" {
+            let value = args.next().expect("must specify json or sarif for --format");
+            if value != "json" && value != "sarif" {
+                eprintln!("unknown format: {}", value);
+                std::process::exit(2);
+            }
+            format = Some(value);
+        } else if arg == "-o" || arg == "--output" {
+            output = Some(args.next().expect("must specify a file for --output"));
+        } else if arg == "--baseline" {
+            baseline = Some(args.next().expect("must specify a file for --baseline"));
+        } else if arg == "--write-baseline" {
+            write_baseline = true;
+        } else if arg == "--map" {
+            maps.push(std::path::PathBuf::from(args.next().expect("must specify a file for --map")));
+        } else if arg == "--hints" {
+            hints = true;
         } else {
             eprintln!("unknown argument: {}", arg);
             return;
         }
     }
 
+    if json && format.is_some() && output.is_none() {
+        eprintln!("--json and --format both write to standard output; use -o <file> for the report");
+        std::process::exit(2);
+    }
+
+    if write_baseline && baseline.is_none() {
+        eprintln!("--write-baseline requires --baseline <file>");
+        return;
+    }
+
     let dme = environment
         .map(std::path::PathBuf::from)
         .unwrap_or_else(|| dm::detect_environment_default()
@@ -52,31 +88,113 @@ fn main() {
     } else {
         context.autodetect_config(&dme);
     }
-    context.set_print_severity(Some(dm::Severity::Info));
+    // Hints, such as unused procs and vars, are only shown when asked for.
+    let report_severity = if hints { dm::Severity::Hint } else { dm::Severity::Info };
+    // With a baseline, only the diagnostics not in it are printed.
+    if baseline.is_none() {
+        context.set_print_severity(Some(report_severity));
+    }
 
-    println!("============================================================");
-    println!("Parsing {}...\n", dme.display());
-    let pp = dm::preprocessor::Preprocessor::new(&context, dme)
-        .expect("i/o error opening .dme");
-    let indents = dm::indents::IndentProcessor::new(&context, pp);
-    let mut parser = dm::parser::Parser::new(&context, indents);
-    parser.enable_procs();
-    let tree = parser.parse_object_tree();
+    // Keep standard output clean when the report is written there.
+    let quiet = format.is_some() && output.is_none();
+    macro_rules! status {
+        ($($rest:tt)*) => {
+            if quiet { eprintln!($($rest)*) } else { println!($($rest)*) }
+        }
+    }
 
-    dreamchecker::run_cli(&context, &tree);
+    status!("============================================================");
+    status!("Parsing {}...\n", dme.display());
+    let tree = if let Some(cache) = cache {
+        dm::cache::parse_environment(&context, &dme, cache.as_ref(), true)
+            .expect("i/o error opening .dme")
+    } else {
+        let mut pp = dm::preprocessor::Preprocessor::new(&context, dme)
+            .expect("i/o error opening .dme");
+        pp.enable_macro_provenance();
+        let indents = dm::indents::IndentProcessor::new(&context, pp);
+        let mut parser = dm::parser::Parser::new(&context, indents);
+        parser.defer_procs();
+        let mut tree = parser.parse_object_tree();
+        tree.parse_proc_bodies(&context);
+        tree
+    };
 
-    println!("============================================================");
-    let errors = context.errors().iter().filter(|each| each.severity() <= dm::Severity::Info).count();
-    println!("Found {} diagnostics", errors);
+    let maps: Vec<&std::path::Path> = maps.iter().map(AsRef::as_ref).collect();
+    dreamchecker::run_with_maps(&context, &tree, &maps, !quiet);
+
+    status!("============================================================");
+    let all_errors = context.errors();
+    let errors = all_errors.iter().filter(|each| each.severity() <= dm::Severity::Info).count();
+    status!("Found {} diagnostics", errors);
+
+    if let Some(format) = format {
+        let report = match &format[..] {
+            "sarif" => dreamchecker::report::sarif_report(&context, report_severity),
+            _ => dreamchecker::report::json_report(&context, report_severity),
+        };
+        let result = match output {
+            Some(path) => std::fs::File::create(&path)
+                .map_err(serde_json::Error::io)
+                .and_then(|file| serde_json::to_writer_pretty(std::io::BufWriter::new(file), &report)),
+            None => serde_json::to_writer_pretty(std::io::stdout().lock(), &report),
+        };
+        if let Err(e) = result {
+            eprintln!("error writing {} report: {}", format, e);
+            std::process::exit(2);
+        }
+    }
 
     if json {
         serde_json::to_writer(std::io::stdout().lock(), &json! {{
-            "hint": context.errors().iter().filter(|each| each.severity() == dm::Severity::Hint).count(),
-            "info": context.errors().iter().filter(|each| each.severity() == dm::Severity::Info).count(),
-            "warning": context.errors().iter().filter(|each| each.severity() == dm::Severity::Warning).count(),
-            "error": context.errors().iter().filter(|each| each.severity() == dm::Severity::Error).count(),
+            "hint": all_errors.iter().filter(|each| each.severity() == dm::Severity::Hint).count(),
+            "info": all_errors.iter().filter(|each| each.severity() == dm::Severity::Info).count(),
+            "warning": all_errors.iter().filter(|each| each.severity() == dm::Severity::Warning).count(),
+            "error": all_errors.iter().filter(|each| each.severity() == dm::Severity::Error).count(),
         }}).unwrap();
     }
 
+    if let Some(path) = baseline {
+        let path = std::path::Path::new(&path);
+        let scopes = dreamchecker::baseline::Scopes::new(&context, &tree);
+        let reported = all_errors.iter().filter(|each| each.severity() <= dm::Severity::Info);
+
+        if write_baseline {
+            let baseline = dreamchecker::baseline::Baseline::from_errors(&scopes, reported);
+            if let Err(e) = baseline.write(path) {
+                eprintln!("error writing baseline {}: {}", path.display(), e);
+                std::process::exit(2);
+            }
+            status!("Wrote {} diagnostics to {}", baseline.len(), path.display());
+            std::process::exit(0);
+        }
+
+        let baseline = match dreamchecker::baseline::Baseline::read(path) {
+            Ok(baseline) => baseline,
+            Err(e) => {
+                eprintln!("error reading baseline {}: {}", path.display(), e);
+                std::process::exit(2);
+            }
+        };
+        let comparison = baseline.compare(&scopes, reported);
+        let stderr = std::io::stderr();
+        let mut stderr = stderr.lock();
+        for error in comparison.new.iter() {
+            let _ = context.pretty_print_error_nocolor(&mut stderr, error);
+        }
+        status!("Found {} diagnostics not in baseline {}", comparison.new.len(), path.display());
+        if !comparison.gone.is_empty() {
+            status!("{} baseline entries no longer occur:", comparison.gone.iter().map(|&(_, count)| count).sum::<usize>());
+            for (fingerprint, count) in comparison.gone.iter() {
+                if *count > 1 {
+                    status!("    {} (x{})", fingerprint, count);
+                } else {
+                    status!("    {}", fingerprint);
+                }
+            }
+        }
+        std::process::exit(if comparison.new.is_empty() { 0 } else { 1 });
+    }
+
     std::process::exit(if errors > 0 { 1 } else { 0 });
 }
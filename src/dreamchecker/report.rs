@@ -0,0 +1,142 @@
+//! Machine-readable reports of the diagnostics in a `Context`.
+//!
+//! Two formats are supported: a plain JSON array with one object per
+//! diagnostic, and [SARIF 2.1.0] for code scanning services.
+//!
+//! [SARIF 2.1.0]: https://docs.oasis-open.org/sarif/sarif/v2.1.0/sarif-v2.1.0.html
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use serde_json::{json, Map, Value};
+
+use dm::{Context, DMError, Location, Severity};
+
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+const INFORMATION_URI: &str = "https://github.com/SpaceManiac/SpacemanDMM";
+
+/// Every diagnostic in the context at or above `min_severity`, as a JSON array.
+pub fn json_report(context: &Context, min_severity: Severity) -> Value {
+    let errors = context.errors();
+    let diagnostics = errors.iter()
+        .filter(|error| error.severity() <= min_severity)
+        .map(|error| json_diagnostic(context, error))
+        .collect();
+    Value::Array(diagnostics)
+}
+
+fn json_diagnostic(context: &Context, error: &DMError) -> Value {
+    let mut object = json_location(context, error.location());
+    object.insert("severity".to_owned(), json!(error.severity().to_string()));
+    object.insert("errortype".to_owned(), json!(error.errortype()));
+    object.insert("component".to_owned(), json!(error.component().name()));
+    object.insert("description".to_owned(), json!(error.description()));
+    object.insert("notes".to_owned(), Value::Array(error.notes().iter()
+        .map(|note| {
+            let mut object = json_location(context, note.location());
+            object.insert("description".to_owned(), json!(note.description()));
+            Value::Object(object)
+        })
+        .collect()));
+    Value::Object(object)
+}
+
+fn json_location(context: &Context, location: Location) -> Map<String, Value> {
+    let mut object = Map::new();
+    object.insert("file".to_owned(), json!(context.file_path(location.file).display().to_string()));
+    object.insert("line".to_owned(), json!(location.line));
+    object.insert("column".to_owned(), json!(location.column));
+    object
+}
+
+/// Every diagnostic in the context at or above `min_severity`, as a SARIF
+/// 2.1.0 log with a single run.
+pub fn sarif_report(context: &Context, min_severity: Severity) -> Value {
+    let all_errors = context.errors();
+    let errors: Vec<&DMError> = all_errors.iter()
+        .filter(|error| error.severity() <= min_severity)
+        .collect();
+
+    let rules: Vec<&str> = errors.iter()
+        .filter_map(|error| error.errortype())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+
+    let results: Vec<Value> = errors.iter().map(|error| {
+        let mut result = Map::new();
+        if let Some(errortype) = error.errortype() {
+            result.insert("ruleId".to_owned(), json!(errortype));
+            result.insert("ruleIndex".to_owned(), json!(rules.iter().position(|&rule| rule == errortype)));
+        }
+        result.insert("level".to_owned(), json!(sarif_level(error.severity())));
+        result.insert("message".to_owned(), json!({ "text": error.description() }));
+        result.insert("locations".to_owned(), json!([{
+            "physicalLocation": sarif_location(context, error.location()),
+        }]));
+        if !error.notes().is_empty() {
+            result.insert("relatedLocations".to_owned(), Value::Array(error.notes().iter()
+                .enumerate()
+                .map(|(id, note)| json!({
+                    "id": id,
+                    "physicalLocation": sarif_location(context, note.location()),
+                    "message": { "text": note.description() },
+                }))
+                .collect()));
+        }
+        Value::Object(result)
+    }).collect();
+
+    json!({
+        "$schema": SARIF_SCHEMA,
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "dreamchecker",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": INFORMATION_URI,
+                    "rules": rules.iter().map(|rule| json!({ "id": rule })).collect::<Vec<_>>(),
+                },
+            },
+            "results": results,
+        }],
+    })
+}
+
+fn sarif_level(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Info => "note",
+        Severity::Hint => "none",
+    }
+}
+
+fn sarif_location(context: &Context, location: Location) -> Value {
+    let mut physical = json!({
+        "artifactLocation": { "uri": file_uri(&context.file_path(location.file)) },
+    });
+    // builtins and other synthetic locations have no line
+    if location.line > 0 {
+        physical["region"] = json!({
+            "startLine": location.line,
+            "startColumn": location.column.max(1),
+        });
+    }
+    physical
+}
+
+/// A relative URI reference for a path, with `/` separators and anything
+/// else outside the unreserved set percent-encoded.
+fn file_uri(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut uri = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' |
+            b'-' | b'.' | b'_' | b'~' | b'/' => uri.push(byte as char),
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
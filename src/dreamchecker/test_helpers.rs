@@ -1,14 +1,35 @@
 use dm::objtree::Code;
 use dm::Context;
 use std::borrow::Cow;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-use crate::{check_var_defs, AnalyzeObjectTree};
+use crate::{check_var_defs, AnalyzeObjectTree, Unparsed};
 
 pub const NO_ERRORS: &[(u32, u16, &str)] = &[];
 
 pub fn parse_a_file_for_test<S: Into<Cow<'static, str>>>(buffer: S) -> Context {
+    check_file_in_context(Context::default(), buffer, &[])
+}
+
+/// Parse and check a file with the given `SpacemanDMM.toml` contents.
+pub fn parse_a_file_with_config<S: Into<Cow<'static, str>>>(buffer: S, config: &str) -> Context {
+    parse_a_file_with_maps(buffer, config, &[])
+}
+
+/// Parse and check a file with the given `SpacemanDMM.toml` contents and maps.
+pub fn parse_a_file_with_maps<S: Into<Cow<'static, str>>>(buffer: S, config: &str, maps: &[&Path]) -> Context {
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+    let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+    let path = std::env::temp_dir().join(format!("dreamchecker-test-{}-{}.toml", std::process::id(), id));
+    std::fs::write(&path, config).expect("error writing test config");
     let context = Context::default();
+    context.force_config(&path);
+    std::fs::remove_file(&path).expect("error removing test config");
+    check_file_in_context(context, buffer, maps)
+}
 
+fn check_file_in_context<S: Into<Cow<'static, str>>>(context: Context, buffer: S, maps: &[&Path]) -> Context {
     let pp = dm::preprocessor::Preprocessor::from_buffer(&context, "unit_tests.rs".into(), buffer);
 
     let indents = dm::indents::IndentProcessor::new(&context, pp);
@@ -29,6 +50,7 @@ pub fn parse_a_file_for_test<S: Into<Cow<'static, str>>>(buffer: S) -> Context {
         }
     });
 
+    let mut unparsed = Unparsed::default();
     tree.root().recurse(&mut |ty| {
         for proc in ty.iter_self_procs() {
             match proc.get().code {
@@ -37,12 +59,11 @@ pub fn parse_a_file_for_test<S: Into<Cow<'static, str>>>(buffer: S) -> Context {
                 }
                 Code::Invalid(_) => {}
                 Code::Builtin => {}
-                Code::Disabled => {
-                    panic!("proc parsing was enabled, but also disabled. this is a bug")
-                }
+                Code::Disabled | Code::Deferred(_) => unparsed.add(proc.location),
             }
         }
     });
+    unparsed.report(&context);
 
     analyzer.check_proc_call_tree();
 
@@ -53,6 +74,8 @@ pub fn parse_a_file_for_test<S: Into<Cow<'static, str>>>(buffer: S) -> Context {
     });
     analyzer.finish_check_kwargs();
 
+    analyzer.check_unused(maps);
+
     context
 }
 
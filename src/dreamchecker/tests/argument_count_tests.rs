@@ -0,0 +1,80 @@
+extern crate dreamchecker as dc;
+extern crate dreammaker as dm;
+
+use dc::test_helpers::*;
+
+pub const TOO_MANY_ARGUMENTS_ERRORS: &[(u32, u16, &str)] = &[
+    (8, 5, "too many arguments to /proc/one, expected at most 1 but got 2"),
+    (12, 5, "too many arguments to /datum/proc/act, expected at most 2 but got 3"),
+];
+
+#[test]
+fn too_many_arguments() {
+    let code = r##"
+/proc/one(a)
+/proc/any(...)
+/proc/uses_args()
+    return length(args)
+/datum/proc/act(a)
+/datum/child/act(a, b)
+/datum/proc/test()
+    one(1, 2)
+    any(1, 2, 3)
+    uses_args(1)
+    act(1, 2)
+    act(1, 2, 3)
+    one(arglist(list(1, 2)))
+"##.trim();
+    check_errors_match(code, TOO_MANY_ARGUMENTS_ERRORS);
+}
+
+#[test]
+fn too_many_arguments_pragma() {
+    let code = r##"
+#pragma TooManyArguments ignore
+/proc/one(a)
+/proc/test()
+    one(1, 2)
+"##.trim();
+    check_errors_match(code, &[]);
+}
+
+const UNPASSED_CODE: &str = r##"
+/proc/greet(name, greeting = "hi", _unused)
+/proc/ignored(x)
+/datum/proc/act(a)
+/datum/child/act(a, b)
+/datum/proc/forward(a, b)
+/datum/child/forward(a, b)
+    ..()
+/proc/kw(a, b)
+/datum/proc/test()
+    greet("bob")
+    act(1)
+    forward(1)
+    kw(b = 1)
+"##;
+
+fn unpassed(context: &dm::Context) -> Vec<(u32, String)> {
+    context.errors().iter()
+        .filter(|e| e.errortype() == Some("unpassed_argument"))
+        .map(|e| (e.location().line, e.description().to_owned()))
+        .collect()
+}
+
+#[test]
+fn unpassed_arguments() {
+    let context = parse_a_file_with_config(UNPASSED_CODE.trim(), "[diagnostics]\nunpassed_argument = \"warning\"\n");
+    let mut found = unpassed(&context);
+    found.sort();
+    assert_eq!(found, vec![
+        (4, "argument \"b\" of /datum/child/proc/act is never passed".to_owned()),
+        (8, "argument \"a\" of /proc/kw is never passed".to_owned()),
+    ]);
+}
+
+#[test]
+fn unpassed_arguments_off_by_default() {
+    let context = parse_a_file_for_test(UNPASSED_CODE.trim());
+    assert!(unpassed(&context).is_empty());
+}
@@ -0,0 +1,119 @@
+extern crate dreamchecker as dc;
+extern crate dreammaker as dm;
+
+use dc::baseline::{Baseline, Scopes};
+use dm::{Context, DMError, Location};
+use dm::objtree::ObjectTree;
+
+const CODE: &str = r##"
+/mob
+    var/health = 10
+    proc/test()
+        world << health
+        world << "done"
+
+/obj/proc/other()
+    return
+"##;
+
+fn parse(code: &'static str) -> (Context, ObjectTree) {
+    let context = Context::default();
+    let pp = dm::preprocessor::Preprocessor::from_buffer(&context, "baseline.dm".into(), code);
+    let indents = dm::indents::IndentProcessor::new(&context, pp);
+    let mut parser = dm::parser::Parser::new(&context, indents);
+    parser.enable_procs();
+    let tree = parser.parse_object_tree();
+    (context, tree)
+}
+
+/// A diagnostic some lines into the body of a proc.
+fn error_in(tree: &ObjectTree, ty: &str, proc: &str, offset: u32, desc: &str) -> DMError {
+    let location = tree.find(ty).unwrap().get_proc(proc).unwrap().get().location;
+    DMError::new(Location { line: location.line + offset, column: 9, ..location }, desc)
+        .with_errortype("example")
+}
+
+fn errors(tree: &ObjectTree) -> Vec<DMError> {
+    vec![
+        error_in(tree, "/mob", "test", 1, "health may be 10 or 200"),
+        error_in(tree, "/obj", "other", 1, "pointless return"),
+    ]
+}
+
+#[test]
+fn fingerprints() {
+    let (context, tree) = parse(CODE.trim());
+    let scopes = Scopes::new(&context, &tree);
+    let errors = errors(&tree);
+
+    let fingerprint = scopes.fingerprint(&errors[0]);
+    assert_eq!(fingerprint.errortype, "example");
+    assert_eq!(fingerprint.scope, "/mob/proc/test");
+    assert_eq!(fingerprint.message, "health may be # or #");
+    assert_eq!(fingerprint.to_string(), "example\t/mob/proc/test\thealth may be # or #");
+    assert_eq!(fingerprint.to_string().parse(), Ok(fingerprint));
+
+    assert_eq!(scopes.fingerprint(&errors[1]).scope, "/obj/proc/other");
+}
+
+#[test]
+fn moved_code_matches() {
+    let (context, tree) = parse(CODE.trim());
+    let baseline = Baseline::from_errors(&Scopes::new(&context, &tree), errors(&tree).iter());
+
+    let (context, tree) = parse(concat!("// a new comment\n\n/datum\n", r##"
+/mob
+    var/health = 10
+    proc/test()
+        world << health
+        world << "done"
+
+/obj/proc/other()
+    return
+"##));
+    let scopes = Scopes::new(&context, &tree);
+    let mut moved = errors(&tree);
+    let comparison = baseline.compare(&scopes, moved.iter());
+    assert!(comparison.new.is_empty());
+    assert!(comparison.gone.is_empty());
+
+    // one new diagnostic, and one which went away
+    moved.pop();
+    moved.push(error_in(&tree, "/mob", "test", 2, "something else"));
+    let comparison = baseline.compare(&scopes, moved.iter());
+    assert_eq!(comparison.new.len(), 1);
+    assert_eq!(comparison.new[0].description(), "something else");
+    assert_eq!(comparison.gone.len(), 1);
+    assert_eq!(comparison.gone[0].0.scope, "/obj/proc/other");
+    assert_eq!(comparison.gone[0].1, 1);
+}
+
+#[test]
+fn duplicates_are_counted() {
+    let (context, tree) = parse(CODE.trim());
+    let scopes = Scopes::new(&context, &tree);
+    let once = error_in(&tree, "/mob", "test", 1, "repeated");
+    let twice = error_in(&tree, "/mob", "test", 2, "repeated");
+    let baseline = Baseline::from_errors(&scopes, vec![&once]);
+    assert_eq!(baseline.len(), 1);
+
+    let comparison = baseline.compare(&scopes, vec![&once, &twice]);
+    assert_eq!(comparison.new.len(), 1);
+    assert!(comparison.gone.is_empty());
+}
+
+#[test]
+fn write_and_read() {
+    let (context, tree) = parse(CODE.trim());
+    let scopes = Scopes::new(&context, &tree);
+    let baseline = Baseline::from_errors(&scopes, errors(&tree).iter());
+
+    let path = std::env::temp_dir().join(format!("dreamchecker-baseline-{}.txt", std::process::id()));
+    baseline.write(&path).unwrap();
+    let contents = std::fs::read_to_string(&path).unwrap();
+    let read = Baseline::read(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(contents.lines().next(), Some("# dreamchecker baseline v1"));
+    assert_eq!(read.unwrap(), baseline);
+}
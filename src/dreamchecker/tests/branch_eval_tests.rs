@@ -40,8 +40,7 @@ pub const IF_ARMS_ERRORS: &[(u32, u16, &str)] = &[
     (2, 7, "control flow condition is a static term"),
     (2, 7, "if condition is always true"),
     (4, 12, "unreachable if block, preceeding if/elseif condition(s) are always true"),
-    // TODO: fix location reporting on this
-    (2, 5, "unreachable else block, preceeding if/elseif condition(s) are always true"),
+    (7, 9, "unreachable else block, preceeding if/elseif condition(s) are always true"),
 ];
 
 #[test]
@@ -72,3 +71,48 @@ fn do_while() {
 "##.trim();
     check_errors_match(code, DO_WHILE_ERRORS);
 }
+
+pub const ALWAYS_FALSE_ERRORS: &[(u32, u16, &str)] = &[
+    (2, 7, "control flow condition is a static term"),
+    (2, 7, "if condition is always false"),
+    (3, 9, "unreachable if block, condition is always false"),
+    (5, 9, "unreachable while block, condition is always false"),
+];
+
+#[test]
+fn always_false() {
+    let code = r##"
+/proc/test()
+    if(0)
+        world << "never"
+    while(0)
+        world << "never"
+"##.trim();
+    check_errors_match(code, ALWAYS_FALSE_ERRORS);
+}
+
+pub const AFTER_JUMP_ERRORS: &[(u32, u16, &str)] = &[
+    (4, 9, "possible unreachable code here"),
+    (7, 9, "possible unreachable code here"),
+    (10, 9, "possible unreachable code here"),
+    (12, 5, "possible unreachable code here"),
+];
+
+#[test]
+fn after_jump() {
+    let code = r##"
+/datum/proc/test()
+    while(prob(50))
+        break
+        world << "never"
+    while(prob(50))
+        continue
+        world << "never"
+    if(prob(50))
+        del src
+        world << "never"
+    CRASH("oops")
+    world << "never"
+"##.trim();
+    check_errors_match(code, AFTER_JUMP_ERRORS);
+}
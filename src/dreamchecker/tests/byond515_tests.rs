@@ -0,0 +1,93 @@
+
+extern crate dreamchecker as dc;
+
+use dc::test_helpers::*;
+
+const TARGET_512: &str = "[environment]\nbyond_version = \"512.1488\"\n";
+const TARGET_514: &str = "[environment]\nbyond_version = \"514.1589\"\n";
+
+#[test]
+fn byond515_syntax() {
+    let code = r##"
+var/global/counter = 0
+/proc/helper()
+    return 1
+/obj/var/charge = 5
+/obj/proc/fire() as num
+    var/list/L = list("a" = 1)
+    for(var/k, v in L)
+        counter += v
+        world.log << k
+    var/x = L?[1]
+    x = charge %% 2
+    x %%= 3
+    x = /obj::charge
+    x = ::counter + ::helper()
+    x = nameof(charge)
+    x = __TYPE__
+    x = __PROC__
+    return x
+"##.trim();
+    check_errors_match(code, NO_ERRORS);
+}
+
+pub const SCOPE_ERRORS: &[(u32, u16, &str)] = &[
+    (2, 17, "undefined field: \"missing\" on /obj"),
+    (3, 9, "undefined global var: \"missing\""),
+];
+
+#[test]
+fn byond515_scope_errors() {
+    let code = r##"
+/proc/test()
+    var/x = /obj::missing
+    x = ::missing
+    return x
+"##.trim();
+    check_errors_match(code, SCOPE_ERRORS);
+}
+
+#[test]
+fn newer_builtins_name_the_version() {
+    let code = r##"
+/proc/test(regex/R)
+    var/x = arctan(1)
+    x = R.Find_char("a")
+    return x
+"##.trim();
+    let context = parse_a_file_with_config(code, TARGET_512);
+    let errors: Vec<_> = context.errors().iter()
+        .map(|error| (error.errortype(), error.description().to_owned()))
+        .collect();
+    assert_eq!(errors, vec![
+        (Some("byond_version"), "\"arctan\" requires BYOND 513, but the target version is 512.1488".to_owned()),
+        (Some("byond_version"), "\"Find_char\" requires BYOND 513, but the target version is 512.1488".to_owned()),
+    ]);
+}
+
+#[test]
+fn newer_vars_and_macros_name_the_version() {
+    let code = r##"
+/proc/test()
+    var/x = __PROC__
+    x = PROFILE_START
+    return x
+"##.trim();
+    let context = parse_a_file_with_config(code, TARGET_514);
+    let descriptions: Vec<_> = context.errors().iter()
+        .map(|error| error.description().to_owned())
+        .collect();
+    assert_eq!(descriptions, vec![
+        "\"__PROC__\" requires BYOND 515, but the target version is 514.1589".to_owned(),
+    ]);
+
+    // the 513 macros are missing from 512, but still expand
+    let context = parse_a_file_with_config(code, TARGET_512);
+    let descriptions: Vec<_> = context.errors().iter()
+        .map(|error| error.description().to_owned())
+        .collect();
+    assert_eq!(descriptions, vec![
+        "\"PROFILE_START\" requires BYOND 513, but the target version is 512.1488".to_owned(),
+        "\"__PROC__\" requires BYOND 515, but the target version is 512.1488".to_owned(),
+    ]);
+}
@@ -0,0 +1,100 @@
+extern crate dreamchecker as dc;
+extern crate dreammaker as dm;
+
+use dc::test_helpers::{parse_a_file_for_test, parse_a_file_with_maps};
+
+const CODE: &str = r##"
+/proc/used_global()
+/proc/unused_global()
+    return
+/proc/by_path()
+/proc/by_name()
+/datum/var/read_var = 1
+/datum/var/dead_var = 2
+/datum/var/map_var
+/datum/var/_private_var
+/datum/var/overridden_var
+/datum/child
+    overridden_var = 3
+/datum/proc/base()
+/datum/child/base()
+/datum/New()
+    used_global()
+    world << read_var
+    var/f = /proc/by_path
+    call(src, "by_name")()
+    return f
+/mob/verb/say_hi()
+    usr << "hi"
+/proc/init_global()
+    return 1
+/obj/proc/init_method()
+    return 2
+/obj/var/initialized = init_global() + init_method()
+/obj/New()
+    world << initialized
+"##;
+
+const CONFIG: &str = r#"
+[diagnostics]
+unused_proc = "hint"
+unused_var = "hint"
+"#;
+
+const MAP: &str = r#"
+"a" = (/obj{map_var = 1; name = "a {sign}; dir = 4"},/turf,/area)
+
+(1,1,1) = {"
+a
+"}
+"#;
+
+fn unused(context: &dm::Context) -> Vec<(u32, &'static str, String)> {
+    let mut found: Vec<_> = context.errors().iter()
+        .filter(|e| e.errortype() == Some("unused_proc") || e.errortype() == Some("unused_var"))
+        .map(|e| (e.location().line, e.errortype().unwrap(), e.description().to_owned()))
+        .collect();
+    found.sort();
+    found
+}
+
+#[test]
+fn unused_procs_and_vars() {
+    let map = std::env::temp_dir().join(format!("dreamchecker-test-{}.dmm", std::process::id()));
+    std::fs::write(&map, MAP).expect("error writing test map");
+    let context = parse_a_file_with_maps(CODE.trim(), CONFIG, &[map.as_path()]);
+    std::fs::remove_file(&map).expect("error removing test map");
+
+    assert_eq!(unused(&context), vec![
+        (2, "unused_proc", "unused proc: /proc/unused_global".to_owned()),
+        (7, "unused_var", "unused var: \"dead_var\" on /datum".to_owned()),
+    ]);
+}
+
+#[test]
+fn unused_vars_without_maps() {
+    let context = parse_a_file_with_maps(CODE.trim(), CONFIG, &[]);
+    assert_eq!(unused(&context), vec![
+        (2, "unused_proc", "unused proc: /proc/unused_global".to_owned()),
+        (7, "unused_var", "unused var: \"dead_var\" on /datum".to_owned()),
+        (8, "unused_var", "unused var: \"map_var\" on /datum".to_owned()),
+    ]);
+}
+
+#[test]
+fn unreadable_map_is_reported() {
+    let map = std::env::temp_dir().join(format!("dreamchecker-test-{}-missing.dmm", std::process::id()));
+    let context = parse_a_file_with_maps(CODE.trim(), CONFIG, &[map.as_path()]);
+    let errors = context.errors();
+    let error = errors.iter()
+        .find(|e| e.description() == "error reading map")
+        .expect("no error for the unreadable map");
+    assert_eq!(context.file_path(error.location().file), map);
+    assert_eq!(error.severity(), dm::Severity::Error);
+}
+
+#[test]
+fn unused_off_by_default() {
+    let context = parse_a_file_for_test(CODE.trim());
+    assert!(unused(&context).is_empty());
+}
@@ -1,7 +1,7 @@
 
 extern crate dreamchecker as dc;
 
-use dc::test_helpers::check_errors_match;
+use dc::test_helpers::{check_errors_match, parse_a_file_for_test};
 
 pub const LOCAL_SCOPE_ERRORS: &[(u32, u16, &str)] = &[
     (7, 9, "undefined var: \"bar\""),
@@ -28,3 +28,23 @@ fn local_scope() {
 "##.trim();
     check_errors_match(code, LOCAL_SCOPE_ERRORS);
 }
+
+#[test]
+fn recovered_declaration() {
+    let code = r##"
+/proc/test()
+    var/a = 1 +
+    a++
+    if(prob(50))
+        var/b, c = 2 *
+        b = c
+    return a
+"##.trim();
+    let context = parse_a_file_for_test(code);
+    let errors = context.errors();
+    // only the two parse errors, and no undefined vars after them
+    assert_eq!(errors.len(), 2);
+    for error in errors.iter() {
+        assert!(!error.description().starts_with("undefined var"), "{}", error);
+    }
+}
@@ -0,0 +1,63 @@
+extern crate dreamchecker as dc;
+extern crate dreammaker as dm;
+
+use dc::test_helpers::{parse_a_file_for_test, parse_a_file_with_config};
+
+const CODE: &str = r##"
+/obj/var/thing = 1
+/proc/test(list/L)
+    var/obj/a
+    a.thing = 1
+    var/obj/b = locate(/obj)
+    b.thing = 1
+    var/obj/c = locate(/obj)
+    if(!c)
+        return
+    c.thing = 1
+    var/obj/d = L["key"]
+    if(d)
+        d.thing = 1
+    d.thing = 1
+    d.thing = 2
+    var/obj/e = L["key"]
+    e?.thing = 1
+    if(e && e.thing)
+        return
+    var/obj/f
+    if(prob(50))
+        f = new
+    f.thing = 1
+    var/obj/g = new
+    g.thing = 1
+    for(var/obj/h in L)
+        h.thing = 1
+"##;
+
+const CONFIG: &str = r#"
+[diagnostics]
+possible_null_access = "warning"
+"#;
+
+fn null_accesses(context: &dm::Context) -> Vec<(u32, String)> {
+    context.errors().iter()
+        .filter(|e| e.errortype() == Some("possible_null_access"))
+        .map(|e| (e.location().line, e.description().to_owned()))
+        .collect()
+}
+
+#[test]
+fn null_access() {
+    let context = parse_a_file_with_config(CODE.trim(), CONFIG);
+    assert_eq!(null_accesses(&context), vec![
+        (4, "local var \"a\" is null here".to_owned()),
+        (6, "local var \"b\" may be null here".to_owned()),
+        (14, "local var \"d\" may be null here".to_owned()),
+        (23, "local var \"f\" may be null here".to_owned()),
+    ]);
+}
+
+#[test]
+fn null_access_off_by_default() {
+    let context = parse_a_file_for_test(CODE.trim());
+    assert!(null_accesses(&context).is_empty());
+}
@@ -0,0 +1,61 @@
+extern crate dreamchecker as dc;
+extern crate dreammaker as dm;
+
+use dc::report::{json_report, sarif_report};
+use dc::test_helpers::parse_a_file_for_test;
+
+const CODE: &str = r##"
+/mob/proc/test()
+    set SpacemanDMM_should_call_parent = TRUE
+
+/mob/subtype/test()
+    return
+"##;
+
+#[test]
+fn json_diagnostics() {
+    let context = parse_a_file_for_test(CODE.trim());
+    let report = json_report(&context, dm::Severity::Info);
+    let diagnostics = report.as_array().unwrap();
+    assert_eq!(diagnostics.len(), 1);
+
+    let diagnostic = &diagnostics[0];
+    assert_eq!(diagnostic["file"], "unit_tests.rs");
+    assert_eq!(diagnostic["line"], 4);
+    assert_eq!(diagnostic["column"], 18);
+    assert_eq!(diagnostic["severity"], "error");
+    assert_eq!(diagnostic["errortype"], "must_call_parent");
+    assert_eq!(diagnostic["component"], "dreamchecker");
+    assert_eq!(diagnostic["description"], "proc never calls parent, required by /mob/proc/test");
+
+    let notes = diagnostic["notes"].as_array().unwrap();
+    assert_eq!(notes.len(), 1);
+    assert_eq!(notes[0]["line"], 2);
+    assert_eq!(notes[0]["description"], "required by this must_call_parent annotation");
+}
+
+#[test]
+fn sarif_diagnostics() {
+    let context = parse_a_file_for_test(CODE.trim());
+    let report = sarif_report(&context, dm::Severity::Info);
+    assert_eq!(report["version"], "2.1.0");
+
+    let run = &report["runs"][0];
+    assert_eq!(run["tool"]["driver"]["name"], "dreamchecker");
+    assert_eq!(run["tool"]["driver"]["rules"][0]["id"], "must_call_parent");
+
+    let result = &run["results"][0];
+    assert_eq!(result["ruleId"], "must_call_parent");
+    assert_eq!(result["ruleIndex"], 0);
+    assert_eq!(result["level"], "error");
+    assert_eq!(result["message"]["text"], "proc never calls parent, required by /mob/proc/test");
+
+    let location = &result["locations"][0]["physicalLocation"];
+    assert_eq!(location["artifactLocation"]["uri"], "unit_tests.rs");
+    assert_eq!(location["region"]["startLine"], 4);
+    assert_eq!(location["region"]["startColumn"], 18);
+
+    let related = &result["relatedLocations"][0];
+    assert_eq!(related["physicalLocation"]["region"]["startLine"], 2);
+    assert_eq!(related["message"]["text"], "required by this must_call_parent annotation");
+}
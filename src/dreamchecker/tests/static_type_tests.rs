@@ -46,3 +46,26 @@ fn return_type() {
 "##.trim();
     check_errors_match(code, RETURN_TYPE_ERRORS);
 }
+
+pub const ISTYPE_NARROWING_ERRORS: &[(u32, u16, &str)] = &[
+    (12, 10, "undefined field: \"force\" on /atom"),
+];
+
+#[test]
+fn istype_narrowing() {
+    let code = r##"
+/obj/item/var/force = 1
+/obj/item/proc/attack()
+/mob/var/health = 100
+/proc/test(atom/thing, atom/other, atom/third)
+    if(istype(thing, /obj/item))
+        thing.force = 2
+    if(!istype(other, /obj/item))
+        return
+    other.attack()
+    if(ismob(third) && third.health)
+        return
+    thing.force = 3
+"##.trim();
+    check_errors_match(code, ISTYPE_NARROWING_ERRORS);
+}
@@ -0,0 +1,66 @@
+extern crate dreamchecker as dc;
+
+use dc::test_helpers::{check_errors_match, parse_a_file_with_config, NO_ERRORS};
+
+#[test]
+fn suppressed_parent_call() {
+    let code = r##"
+/mob/proc/test()
+    set SpacemanDMM_should_call_parent = TRUE
+
+// dreamchecker-ignore: must_call_parent
+/mob/subtype/test()
+    return
+"##.trim();
+    check_errors_match(code, NO_ERRORS);
+}
+
+#[test]
+fn other_errortypes_not_suppressed() {
+    let code = r##"
+/mob/proc/test()
+    set SpacemanDMM_should_call_parent = TRUE
+
+/mob/subtype/test() // dreamchecker-ignore: must_not_override
+    return
+"##.trim();
+    check_errors_match(code, &[
+        (4, 18, "proc never calls parent, required by /mob/proc/test"),
+    ]);
+}
+
+fn unused_suppressions(code: &'static str, config: &str) -> Vec<u32> {
+    let context = parse_a_file_with_config(code, config);
+    context.register_unused_suppressions();
+    context.errors().iter()
+        .filter(|error| error.errortype() == Some("unused_suppression"))
+        .map(|error| error.location().line)
+        .collect()
+}
+
+#[test]
+fn suppressions_of_filtered_errortypes_are_used() {
+    let code = r##"
+/mob/proc/test()
+    set SpacemanDMM_should_call_parent = TRUE
+
+// dreamchecker-ignore: must_call_parent
+/mob/subtype/test()
+    return
+"##.trim();
+    // disabled by the configuration
+    assert_eq!(unused_suppressions(code, "[diagnostics]\nmust_call_parent = \"off\"\n"), Vec::<u32>::new());
+    // below the level at which errors are registered
+    assert_eq!(unused_suppressions(code, "[display]\nerror_level = \"error\"\n[diagnostics]\nmust_call_parent = \"warning\"\n"), Vec::<u32>::new());
+}
+
+#[test]
+fn suppressions_of_skipped_checks_are_not_unused() {
+    let code = r##"
+/proc/test()
+    var/x = 1 // dreamchecker-ignore: unused_local
+    return 2 // dreamchecker-ignore: must_call_parent
+"##.trim();
+    // unused_local is off by default, so its check never ran
+    assert_eq!(unused_suppressions(code, ""), vec![3]);
+}
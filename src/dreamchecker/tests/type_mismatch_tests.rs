@@ -0,0 +1,68 @@
+extern crate dreamchecker as dc;
+extern crate dreammaker as dm;
+
+use dc::test_helpers::{parse_a_file_for_test, parse_a_file_with_config};
+
+const CODE: &str = r##"
+/obj/item
+/proc/take_mob(mob/M)
+/proc/make_obj()
+    set SpacemanDMM_return_type = /obj
+    return new /mob
+/proc/test(atom/A)
+    var/obj/item/I = new /mob
+    var/obj/O = A
+    var/mob/P = new
+    I = new /turf
+    O = new /obj/item
+    take_mob(new /turf)
+    take_mob(M = new /turf)
+    take_mob(P)
+    take_mob(null)
+"##;
+
+const CONFIG: &str = r#"
+[diagnostics]
+local_type_mismatch = "warning"
+argument_type_mismatch = "warning"
+return_type_mismatch = "warning"
+"#;
+
+fn mismatches(context: &dm::Context) -> Vec<(u32, &'static str, String)> {
+    let mut found: Vec<_> = context.errors().iter()
+        .filter_map(|e| match e.errortype() {
+            Some(kind @ "local_type_mismatch") |
+            Some(kind @ "argument_type_mismatch") |
+            Some(kind @ "return_type_mismatch") => Some((e.location().line, kind, e.description().to_owned())),
+            _ => None,
+        })
+        .collect();
+    found.sort();
+    found
+}
+
+#[test]
+fn type_mismatch() {
+    let context = parse_a_file_with_config(CODE.trim(), CONFIG);
+    assert_eq!(mismatches(&context), vec![
+        (5, "return_type_mismatch", "returning /mob from /proc/make_obj, which has return type /obj".to_owned()),
+        (7, "local_type_mismatch", "initializing local var \"I\" of type /obj/item with /mob".to_owned()),
+        (10, "local_type_mismatch", "assigning /turf to local var \"I\" of type /obj/item".to_owned()),
+        (12, "argument_type_mismatch", "passing /turf to argument \"M\" of /proc/take_mob, which expects /mob".to_owned()),
+        (13, "argument_type_mismatch", "passing /turf to argument \"M\" of /proc/take_mob, which expects /mob".to_owned()),
+    ]);
+}
+
+#[test]
+fn type_mismatch_one_kind() {
+    let config = "[diagnostics]\nargument_type_mismatch = \"warning\"\n";
+    let context = parse_a_file_with_config(CODE.trim(), config);
+    assert!(mismatches(&context).iter().all(|&(_, kind, _)| kind == "argument_type_mismatch"));
+    assert_eq!(mismatches(&context).len(), 2);
+}
+
+#[test]
+fn type_mismatch_off_by_default() {
+    let context = parse_a_file_for_test(CODE.trim());
+    assert!(mismatches(&context).is_empty());
+}
@@ -0,0 +1,32 @@
+extern crate dreamchecker as dc;
+extern crate dreammaker as dm;
+
+use dm::indents::IndentProcessor;
+use dm::parser::Parser;
+use dm::preprocessor::Preprocessor;
+
+const CODE: &str = "/proc/a()\n\treturn 1\n/proc/b()\n\treturn 2\n";
+
+fn unparsed(defer: bool) -> Vec<String> {
+    let context = dm::Context::default();
+    let pp = Preprocessor::from_buffer(&context, "unparsed.dm".into(), CODE);
+    let mut parser = Parser::new(&context, IndentProcessor::new(&context, pp));
+    if defer {
+        parser.defer_procs();
+    }
+    let tree = parser.parse_object_tree();
+    dc::run(&context, &tree);
+    context.errors().iter()
+        .filter(|error| error.description().contains("never parsed"))
+        .map(|error| error.description().to_owned())
+        .collect()
+}
+
+#[test]
+fn unparsed_bodies_reported_once() {
+    let expected = vec!["2 proc bodies were never parsed, so they were not checked".to_owned()];
+    // deferred without calling parse_proc_bodies
+    assert_eq!(unparsed(true), expected);
+    // proc parsing never enabled
+    assert_eq!(unparsed(false), expected);
+}
@@ -0,0 +1,78 @@
+extern crate dreamchecker as dc;
+extern crate dreammaker as dm;
+
+use dc::test_helpers::{parse_a_file_for_test, parse_a_file_with_config};
+
+const CODE: &str = r##"
+/proc/test(a, b, _c)
+    var/used = a
+    var/unused = 1
+    var/_ignored = 2
+    var/assigned
+    assigned = 5
+    world << used
+
+/datum/proc/base(x)
+/datum/proc/other(y)
+    return
+/datum/child/base(x)
+    world << "child"
+/datum/child/other(y)
+    world << "child"
+"##;
+
+const CONFIG: &str = r#"
+[diagnostics]
+unused_local = "hint"
+unused_argument = "warning"
+"#;
+
+fn unused(context: &dm::Context) -> Vec<(u32, &'static str, dm::Severity, String)> {
+    let mut found: Vec<_> = context.errors().iter()
+        .filter(|e| e.errortype().map_or(false, |errortype| errortype.starts_with("unused_")))
+        .map(|e| (e.location().line, e.errortype().unwrap(), e.severity(), e.description().to_owned()))
+        .collect();
+    found.sort();
+    found
+}
+
+#[test]
+fn unused_locals_and_arguments() {
+    let context = parse_a_file_with_config(CODE.trim(), CONFIG);
+    assert_eq!(unused(&context), vec![
+        (1, "unused_argument", dm::Severity::Warning, "unused argument: \"b\"".to_owned()),
+        (3, "unused_local", dm::Severity::Hint, "unused local var: \"unused\"".to_owned()),
+        (5, "unused_local", dm::Severity::Hint, "unused local var: \"assigned\"".to_owned()),
+    ]);
+}
+
+#[test]
+fn arguments_read_through_args() {
+    let code = r##"
+/proc/forward(a, b)
+    world << args.len
+"##.trim();
+    let context = parse_a_file_with_config(code, CONFIG);
+    assert!(unused(&context).is_empty());
+}
+
+#[test]
+fn unused_off_by_default() {
+    let context = parse_a_file_for_test(CODE.trim());
+    assert!(unused(&context).is_empty());
+}
+
+#[test]
+fn unused_locals_enabled_by_pragma() {
+    let code = r##"
+/proc/first()
+    var/a = 1
+#pragma UnusedVar warning
+/proc/second()
+    var/b = 1
+"##.trim();
+    let context = parse_a_file_for_test(code);
+    assert_eq!(unused(&context), vec![
+        (5, "unused_local", dm::Severity::Warning, "unused local var: \"b\"".to_owned()),
+    ]);
+}
@@ -0,0 +1,267 @@
+//! Whole-program detection of procs, type vars, and arguments which are never
+//! used.
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use dm::ast::*;
+use dm::objtree::{Code, ProcRef, TypeRef};
+use dm::{DMError, Location, Severity};
+
+use crate::{base_proc, error, AnalyzeObjectTree, ReadNames};
+
+impl<'o> AnalyzeObjectTree<'o> {
+    /// Whether any of the whole-program unused checks is enabled.
+    pub(crate) fn wants_unused_checks(&self) -> bool {
+        ["unused_proc", "unused_var", "unpassed_argument"].iter().any(|each| self.context.opted_in(each))
+    }
+
+    /// Report procs which are never called or referenced, type vars which
+    /// are never read, and arguments which are never passed, if the
+    /// `unused_proc`, `unused_var`, or `unpassed_argument` diagnostics are
+    /// enabled. Vars set in the given maps count as read.
+    pub fn check_unused(&self, maps: &[&Path]) {
+        if self.context.opted_in("unused_proc") {
+            self.check_unused_procs();
+        }
+        if self.context.opted_in("unused_var") {
+            let mut map_vars = HashSet::new();
+            for map in maps {
+                match std::fs::read(map) {
+                    Ok(bytes) => edited_vars(&dm::lexer::from_utf8_or_latin1(bytes), &mut map_vars),
+                    Err(e) => {
+                        let file = self.context.register_file(map);
+                        DMError::new(Location { file, line: 1, column: 1 }, "error reading map")
+                            .with_cause(e)
+                            .register(self.context);
+                    }
+                }
+            }
+            self.check_unused_vars(&map_vars);
+        }
+        if self.context.opted_in("unpassed_argument") {
+            self.check_unpassed_arguments();
+        }
+    }
+
+    fn check_unused_procs(&self) {
+        // Calls resolve to the public-facing definition on a type, so compare
+        // against that rather than the exact definition.
+        let public = |proc: ProcRef<'o>| proc.ty().get_proc(proc.name());
+        // Var initializers run outside of any proc, so their calls never
+        // enter the call tree. While walking the tree, also note each proc
+        // name which is overridden somewhere below each type.
+        let mut initializers = InitializerCalls {
+            ty: self.objtree.root(),
+            procs: HashSet::new(),
+            names: HashSet::new(),
+        };
+        let mut overridden: HashSet<(TypeRef<'o>, &'o str)> = HashSet::new();
+        self.objtree.root().recurse(&mut |ty| {
+            initializers.ty = ty;
+            for var in ty.get().vars.values() {
+                if let Some(expr) = var.value.expression.as_ref() {
+                    initializers.visit_expression(expr);
+                }
+            }
+            for name in ty.get().procs.keys() {
+                let mut parent = ty.parent_path();
+                while let Some(ancestor) = parent {
+                    // Whoever inserted this pair already walked to the root.
+                    if !overridden.insert((ancestor, name.as_str())) {
+                        break;
+                    }
+                    parent = ancestor.parent_path();
+                }
+            }
+        });
+
+        let used: HashSet<ProcRef<'o>> = self.call_tree.values()
+            .flat_map(|calls| calls.iter().map(|&(proc, _)| proc))
+            .chain(self.referenced_procs.iter().cloned())
+            .chain(initializers.procs.iter().cloned())
+            .filter_map(public)
+            .collect();
+
+        self.objtree.root().recurse(&mut |ty| {
+            for (name, type_proc) in ty.get().procs.iter() {
+                guard!(let Some(decl) = type_proc.declaration.as_ref() else { continue });
+                // Builtins and verbs are entered by BYOND or by players.
+                if decl.location.is_builtins() || decl.kind == ProcDeclKind::Verb {
+                    continue;
+                }
+                // Redeclaring a parent's proc is reported elsewhere.
+                if ty.parent_type_without_root().and_then(|parent| parent.get_proc(name)).is_some() {
+                    continue;
+                }
+                guard!(let Some(proc) = ty.get_proc(name) else { continue });
+                if used.contains(&proc)
+                    || self.dynamic_proc_names.contains(&name[..])
+                    || initializers.names.contains(&name[..])
+                    || overridden.contains(&(ty, &name[..]))
+                {
+                    continue;
+                }
+                error(decl.location, format!("unused proc: {}", proc))
+                    .set_severity(Severity::Hint)
+                    .with_errortype("unused_proc")
+                    .register(self.context);
+            }
+        });
+    }
+
+    fn check_unused_vars(&self, map_vars: &HashSet<String>) {
+        // Which type a var is read on is rarely known, so match by name.
+        let mut read = ReadNames::default();
+        self.objtree.root().recurse(&mut |ty| {
+            for proc in ty.iter_self_procs() {
+                for param in proc.get().parameters.iter() {
+                    read.visit_parameter(param);
+                }
+                if let Code::Present(ref code) = proc.get().code {
+                    read.visit_block(code);
+                }
+            }
+            for (name, var) in ty.get().vars.iter() {
+                if var.declaration.is_none() {
+                    // Overridden on a subtype.
+                    read.fields.insert(name);
+                }
+                if let Some(ref expr) = var.value.expression {
+                    read.visit_expression(expr);
+                }
+            }
+        });
+
+        self.objtree.root().recurse(&mut |ty| {
+            for (name, var) in ty.get().vars.iter() {
+                guard!(let Some(decl) = var.declaration.as_ref() else { continue });
+                if decl.location.is_builtins() || name.starts_with('_') {
+                    continue;
+                }
+                if read.names.contains(&name[..]) || read.fields.contains(&name[..]) || map_vars.contains(name) {
+                    continue;
+                }
+                error(decl.location, format!("unused var: {:?} on {}", name, ty))
+                    .set_severity(Severity::Hint)
+                    .with_errortype("unused_var")
+                    .register(self.context);
+            }
+        });
+    }
+
+    fn check_unpassed_arguments(&self) {
+        // Procs referenced by path may be called with anything.
+        let referenced: HashSet<ProcRef<'o>> = self.referenced_procs.iter().map(|&proc| base_proc(proc)).collect();
+
+        self.objtree.root().recurse(&mut |ty| {
+            for proc in ty.iter_self_procs() {
+                guard!(let Some(decl) = proc.get_declaration() else { continue });
+                // Builtins and verbs are called by BYOND or by players.
+                if decl.location.is_builtins() || decl.kind == ProcDeclKind::Verb {
+                    continue;
+                }
+                let base = base_proc(proc);
+                // Procs which are never called are reported by `unused_proc`.
+                guard!(let Some(passed) = self.passed_args.get(&base) else { continue });
+                if passed.all || referenced.contains(&base) || self.dynamic_proc_names.contains(proc.name()) {
+                    continue;
+                }
+                for (idx, param) in proc.parameters.iter().enumerate() {
+                    if param.default.is_some() || param.name == "..." || param.name.starts_with('_') {
+                        continue;
+                    }
+                    if idx < passed.positional || passed.keywords.contains(param.name.as_str()) {
+                        continue;
+                    }
+                    error(param.location, format!("argument {:?} of {} is never passed", param.name, proc))
+                        .set_severity(Severity::Warning)
+                        .with_errortype("unpassed_argument")
+                        .register(self.context);
+                }
+            }
+        });
+    }
+}
+
+/// Collects the procs called by var initializers, which run as part of
+/// creating an object rather than from any proc.
+struct InitializerCalls<'o> {
+    ty: TypeRef<'o>,
+    procs: HashSet<ProcRef<'o>>,
+    /// Procs called on some other value, matched by name.
+    names: HashSet<&'o str>,
+}
+
+impl<'o> Visitor<'o> for InitializerCalls<'o> {
+    fn visit_term(&mut self, location: Location, term: &'o Term) {
+        match term {
+            Term::Call(name, _) => if let Some(proc) = self.ty.get_proc(name) {
+                self.procs.insert(proc);
+            },
+            Term::GlobalCall(name, _) => if let Some(proc) = self.ty.tree().root().get_proc(name) {
+                self.procs.insert(proc);
+            },
+            _ => {},
+        }
+        walk_term(self, location, term)
+    }
+
+    fn visit_follow(&mut self, location: Location, follow: &'o Follow) {
+        if let Follow::Call(_, name, _) = follow {
+            self.names.insert(name);
+        }
+        walk_follow(self, location, follow)
+    }
+}
+
+/// Collect the names of vars edited by the prefabs in a `.dmm` map, such as
+/// `name` and `dir` in `/obj/sign{name = "x"; dir = 4}`.
+fn edited_vars(map: &str, names: &mut HashSet<String>) {
+    let mut chars = map.chars().peekable();
+    let mut depth = 0;
+    // Whether a var name may begin here, after `{` or `;`.
+    let mut at_name = false;
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' => {
+                while let Some(ch) = chars.next() {
+                    match ch {
+                        '\\' => { chars.next(); },
+                        '"' => break,
+                        _ => {},
+                    }
+                }
+                at_name = false;
+            }
+            '{' => {
+                depth += 1;
+                at_name = true;
+            }
+            '}' => {
+                depth -= 1;
+                at_name = false;
+            }
+            ';' => at_name = depth > 0,
+            ch if ch.is_whitespace() => {},
+            ch if at_name && (ch.is_alphabetic() || ch == '_') => {
+                let mut name = ch.to_string();
+                while let Some(&ch) = chars.peek() {
+                    if !ch.is_alphanumeric() && ch != '_' {
+                        break;
+                    }
+                    name.push(ch);
+                    chars.next();
+                }
+                while chars.peek().map_or(false, |ch| ch.is_whitespace()) {
+                    chars.next();
+                }
+                if chars.peek() == Some(&'=') {
+                    names.insert(name);
+                }
+                at_name = false;
+            }
+            _ => at_name = false,
+        }
+    }
+}
@@ -4,12 +4,13 @@
 use std::fmt;
 use std::iter::FromIterator;
 use phf::phf_map;
+use serde::{Serialize, Deserialize};
 
 use linked_hash_map::LinkedHashMap;
 
 use crate::error::Location;
 
-#[derive(Copy, Clone, Eq, Debug)]
+#[derive(Copy, Clone, Eq, Debug, Serialize, Deserialize)]
 pub struct Spanned<T> {
     // TODO: add a Span type and use it here
     pub location: Location,
@@ -30,7 +31,7 @@ impl<T> Spanned<T> {
 }
 
 /// The unary operators, both prefix and postfix.
-#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub enum UnaryOp {
     Neg,
     Not,
@@ -85,7 +86,7 @@ impl<'a, T: fmt::Display + ?Sized> fmt::Display for Around<'a, T> {
 /// The DM path operators.
 ///
 /// Which path operator is used typically only matters at the start of a path.
-#[derive(Copy, Clone, Hash, PartialEq, Eq, Debug)]
+#[derive(Copy, Clone, Hash, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub enum PathOp {
     /// `/` for absolute pathing.
     Slash,
@@ -140,7 +141,7 @@ impl<'a> fmt::Display for FormatTypePath<'a> {
 }
 
 /// The binary operators.
-#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub enum BinaryOp {
     Add,
     Sub,
@@ -148,6 +149,7 @@ pub enum BinaryOp {
     Div,
     Pow,
     Mod,
+    FloatMod,
     Eq,
     NotEq,
     Less,
@@ -177,6 +179,7 @@ impl fmt::Display for BinaryOp {
             Div => "/",
             Pow => "**",
             Mod => "%",
+            FloatMod => "%%",
             Eq => "==",
             NotEq => "!=",
             Less => "<",
@@ -199,7 +202,7 @@ impl fmt::Display for BinaryOp {
 }
 
 /// The assignment operators, including augmented assignment.
-#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub enum AssignOp {
     Assign,
     AddAssign,
@@ -207,6 +210,7 @@ pub enum AssignOp {
     MulAssign,
     DivAssign,
     ModAssign,
+    FloatModAssign,
     BitAndAssign,
     BitOrAssign,
     BitXorAssign,
@@ -224,6 +228,7 @@ impl fmt::Display for AssignOp {
             MulAssign => "*=",
             DivAssign => "/=",
             ModAssign => "%=",
+            FloatModAssign => "%%=",
             BitAndAssign => "&=",
             BitXorAssign => "^=",
             BitOrAssign => "|=",
@@ -234,7 +239,7 @@ impl fmt::Display for AssignOp {
 }
 
 /// The ternary operator, represented uniformly for convenience.
-#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub enum TernaryOp {
     Conditional,
 }
@@ -275,7 +280,7 @@ augmented! {
 }
 
 /// A typepath optionally followed by a set of variables.
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct Prefab {
     pub path: TypePath,
     pub vars: LinkedHashMap<String, Expression>,
@@ -312,7 +317,7 @@ impl<'a, E: fmt::Display> fmt::Display for FormatVars<'a, E> {
 }
 
 /// The different forms of the `new` command.
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub enum NewType {
     /// Implicit type, taken from context.
     Implicit,
@@ -326,7 +331,7 @@ pub enum NewType {
 }
 
 /// The structure of an expression, a tree of terms and operators.
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub enum Expression {
     /// An expression containing a term directly. The term is evaluated first,
     /// then its follows, then its unary operators in reverse order.
@@ -491,7 +496,7 @@ impl From<Term> for Expression {
 }
 
 /// The structure of a term, the basic building block of the AST.
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub enum Term {
     // Terms with no recursive contents ---------------------------------------
     /// The literal `null`.
@@ -502,6 +507,8 @@ pub enum Term {
     Float(f32),
     /// An identifier.
     Ident(String),
+    /// A `::` identifier, which always refers to a global var.
+    GlobalIdent(String),
     /// A string literal.
     String(String),
     /// A resource literal.
@@ -520,6 +527,8 @@ pub enum Term {
     // Function calls with recursive contents ---------------------------------
     /// An unscoped function call.
     Call(String, Vec<Expression>),
+    /// A `::` function call, which always refers to a global proc.
+    GlobalCall(String, Vec<Expression>),
     /// A `.()` call.
     SelfCall(Vec<Expression>),
     /// A `..()` call. If arguments is empty, the proc's arguments are passed.
@@ -626,7 +635,7 @@ impl From<Expression> for Term {
 }
 
 /// The possible kinds of index operators, for both fields and methods.
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum IndexKind {
     /// `a.b`
     Dot,
@@ -636,6 +645,8 @@ pub enum IndexKind {
     SafeDot,
     /// `a?:b`
     SafeColon,
+    /// `a::b`
+    Scope,
 }
 
 impl IndexKind {
@@ -645,6 +656,7 @@ impl IndexKind {
             IndexKind::Colon => ":",
             IndexKind::SafeDot => "?.",
             IndexKind::SafeColon => "?:",
+            IndexKind::Scope => "::",
         }
     }
 }
@@ -655,11 +667,39 @@ impl fmt::Display for IndexKind {
     }
 }
 
+/// The possible kinds of list index operators.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ListAccessKind {
+    /// `a[b]`
+    Normal,
+    /// `a?[b]`
+    Safe,
+}
+
+impl ListAccessKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            ListAccessKind::Normal => "[",
+            ListAccessKind::Safe => "?[",
+        }
+    }
+}
+
+impl fmt::Display for ListAccessKind {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(self.name())
+    }
+}
+
 /// An expression part which is applied to a term or another follow.
-#[derive(Debug, Clone, PartialEq)]
+///
+/// With `IndexKind::Scope`, a field is the initial value of a var on the
+/// value's type, and a call with no arguments is a reference to a proc on
+/// that type rather than a call to it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Follow {
     /// Index the value by an expression.
-    Index(Box<Expression>),
+    Index(ListAccessKind, Box<Expression>),
     /// Access a field of the value.
     Field(IndexKind, String),
     /// Call a method of the value.
@@ -667,10 +707,10 @@ pub enum Follow {
 }
 
 /// Like a `Follow` but supports index or fields only.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum IndexOrField {
     /// Index the value by an expression.
-    Index(Box<Expression>),
+    Index(ListAccessKind, Box<Expression>),
     /// Access a field of the value.
     Field(IndexKind, String),
 }
@@ -678,7 +718,7 @@ pub enum IndexOrField {
 impl From<IndexOrField> for Follow {
     fn from(input: IndexOrField) -> Follow {
         match input {
-            IndexOrField::Index(expr) => Follow::Index(expr),
+            IndexOrField::Index(kind, expr) => Follow::Index(kind, expr),
             IndexOrField::Field(kind, name) => Follow::Field(kind, name),
         }
     }
@@ -689,7 +729,7 @@ impl From<IndexOrField> for Follow {
 /// DM requires referencing proc paths to include whether the target is
 /// declared as a proc or verb, even though the two modes are functionally
 /// identical in many other respects.
-#[derive(Debug, Clone, PartialEq, Eq, Copy, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Copy, Hash, Serialize, Deserialize)]
 pub enum ProcDeclKind {
     Proc,
     Verb,
@@ -726,7 +766,7 @@ impl fmt::Display for ProcDeclKind {
 }
 
 /// A parameter declaration in the header of a proc.
-#[derive(Debug, Clone, PartialEq, Default)]
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
 pub struct Parameter {
     pub var_type: VarType,
     pub name: String,
@@ -746,6 +786,24 @@ impl fmt::Display for Parameter {
     }
 }
 
+/// The return type annotation in the header of a proc: `proc/foo() as /obj`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ProcReturnType {
+    /// An input type such as `num` or `text|null`.
+    InputType(InputType),
+    /// A type path such as `/obj/item`.
+    TypePath(TreePath),
+}
+
+impl fmt::Display for ProcReturnType {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProcReturnType::InputType(input_type) => write!(fmt, "{}", input_type),
+            ProcReturnType::TypePath(path) => write!(fmt, "{}", FormatTreePath(path)),
+        }
+    }
+}
+
 macro_rules! type_table {
     ($(#[$attr:meta])* pub struct $name:ident; $($txt:expr, $i:ident, $val:expr;)*) => {
         bitflags! {
@@ -808,8 +866,20 @@ type_table! {
     "color",        COLOR,        1 << 17;
 }
 
+impl Serialize for InputType {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.bits().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for InputType {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(InputType::from_bits_truncate(u32::deserialize(deserializer)?))
+    }
+}
+
 /// A type which may be ascribed to a `var`.
-#[derive(Debug, Clone, PartialEq, Default)]
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
 pub struct VarType {
     pub is_static: bool,
     pub is_const: bool,
@@ -907,7 +977,7 @@ impl fmt::Display for VarType {
 }
 
 /// Suffixes which may appear after a variable's name in its declaration.
-#[derive(Debug, Clone, PartialEq, Default)]
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
 pub struct VarSuffix {
     // var/L[], var/L[10]
     pub list: Vec<Option<Expression>>,
@@ -937,7 +1007,7 @@ impl VarSuffix {
 pub type Block = Vec<Spanned<Statement>>;
 
 /// A statement in a proc body.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Statement {
     Expr(Expression),
     Return(Option<Expression>),
@@ -969,6 +1039,13 @@ pub enum Statement {
         in_list: Option<Expression>,
         block: Block,
     },
+    ForKeyValue {
+        var_type: Option<VarType>,
+        key: String,
+        value: String,
+        in_list: Expression,
+        block: Block,
+    },
     ForRange {
         var_type: Option<VarType>,
         name: String,
@@ -1007,16 +1084,22 @@ pub enum Statement {
     },
     Del(Expression),
     Crash(Expression),
+    /// A statement which failed to parse. The error has already been
+    /// reported, and parsing resumed at the next statement.
+    ///
+    /// Holds any locals the statement declared before the error, without
+    /// their values, so that later uses of them still resolve.
+    Error(Vec<VarStatement>),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct VarStatement {
     pub var_type: VarType,
     pub name: String,
     pub value: Option<Expression>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum SettingMode {
     /// As in `set name = "Use"`.
     Assign,
@@ -1040,7 +1123,7 @@ impl fmt::Display for SettingMode {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Case {
     Exact(Expression),
     Range(Expression, Expression),
@@ -1085,3 +1168,618 @@ pub static VALID_FILTER_FLAGS: phf::Map<&'static str, (&str, bool, bool, &[&str]
     "ripple" => ("flags", false, true, &[ "WAVE_BOUNDED" ]),
     "wave" => ("flags", false, true, &[ "WAVE_SIDEWAYS", "WAVE_BOUNDED" ]),
 };
+
+// ----------------------------------------------------------------------------
+// Visitors
+
+/// A read-only traversal of the AST.
+///
+/// Every method defaults to the matching `walk_*` function, which visits the
+/// node's children. Override a method to inspect a node, and call the `walk_*`
+/// function from the override to keep descending into it.
+pub trait Visitor<'ast> {
+    fn visit_block(&mut self, block: &'ast [Spanned<Statement>]) {
+        walk_block(self, block)
+    }
+
+    fn visit_statement(&mut self, location: Location, statement: &'ast Statement) {
+        walk_statement(self, location, statement)
+    }
+
+    fn visit_var_statement(&mut self, location: Location, var: &'ast VarStatement) {
+        walk_var_statement(self, location, var)
+    }
+
+    /// A variable declared by a `for` loop header other than a `var`
+    /// statement, such as `O` in `for(var/obj/O in world)`.
+    fn visit_loop_var(&mut self, _location: Location, _var_type: Option<&'ast VarType>, _name: &'ast str) {}
+
+    /// A parameter of a `catch` clause, such as `var/exception/e`.
+    fn visit_catch_param(&mut self, _location: Location, _param: &'ast TreePath) {}
+
+    fn visit_case(&mut self, case: &'ast Case) {
+        walk_case(self, case)
+    }
+
+    fn visit_expression(&mut self, expression: &'ast Expression) {
+        walk_expression(self, expression)
+    }
+
+    fn visit_term(&mut self, location: Location, term: &'ast Term) {
+        walk_term(self, location, term)
+    }
+
+    fn visit_follow(&mut self, location: Location, follow: &'ast Follow) {
+        walk_follow(self, location, follow)
+    }
+
+    fn visit_index_or_field(&mut self, index_or_field: &'ast IndexOrField) {
+        walk_index_or_field(self, index_or_field)
+    }
+
+    fn visit_new_type(&mut self, new_type: &'ast NewType) {
+        walk_new_type(self, new_type)
+    }
+
+    fn visit_prefab(&mut self, prefab: &'ast Prefab) {
+        walk_prefab(self, prefab)
+    }
+
+    fn visit_parameter(&mut self, parameter: &'ast Parameter) {
+        walk_parameter(self, parameter)
+    }
+}
+
+pub fn walk_block<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, block: &'ast [Spanned<Statement>]) {
+    for statement in block {
+        visitor.visit_statement(statement.location, &statement.elem);
+    }
+}
+
+pub fn walk_statement<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, location: Location, statement: &'ast Statement) {
+    match statement {
+        Statement::Expr(expr) |
+        Statement::Throw(expr) |
+        Statement::Del(expr) |
+        Statement::Crash(expr) => visitor.visit_expression(expr),
+        Statement::Return(value) => {
+            if let Some(value) = value {
+                visitor.visit_expression(value);
+            }
+        }
+        Statement::While { condition, block } => {
+            visitor.visit_expression(condition);
+            visitor.visit_block(block);
+        }
+        Statement::DoWhile { block, condition } => {
+            visitor.visit_block(block);
+            visitor.visit_expression(condition);
+        }
+        Statement::If { arms, else_arm } => {
+            for (condition, block) in arms {
+                visitor.visit_expression(&condition.elem);
+                visitor.visit_block(block);
+            }
+            if let Some(else_arm) = else_arm {
+                visitor.visit_block(else_arm);
+            }
+        }
+        Statement::ForLoop { init, test, inc, block } => {
+            if let Some(init) = init {
+                visitor.visit_statement(location, init);
+            }
+            if let Some(test) = test {
+                visitor.visit_expression(test);
+            }
+            if let Some(inc) = inc {
+                visitor.visit_statement(location, inc);
+            }
+            visitor.visit_block(block);
+        }
+        Statement::ForList { var_type, name, in_list, block, .. } => {
+            visitor.visit_loop_var(location, var_type.as_ref(), name);
+            if let Some(in_list) = in_list {
+                visitor.visit_expression(in_list);
+            }
+            visitor.visit_block(block);
+        }
+        Statement::ForKeyValue { var_type, key, value, in_list, block } => {
+            // only the key takes the declared type
+            visitor.visit_loop_var(location, var_type.as_ref(), key);
+            visitor.visit_loop_var(location, None, value);
+            visitor.visit_expression(in_list);
+            visitor.visit_block(block);
+        }
+        Statement::ForRange { var_type, name, start, end, step, block } => {
+            visitor.visit_loop_var(location, var_type.as_ref(), name);
+            visitor.visit_expression(start);
+            visitor.visit_expression(end);
+            if let Some(step) = step {
+                visitor.visit_expression(step);
+            }
+            visitor.visit_block(block);
+        }
+        Statement::Var(var) => visitor.visit_var_statement(location, var),
+        Statement::Vars(vars) | Statement::Error(vars) => {
+            for var in vars {
+                visitor.visit_var_statement(location, var);
+            }
+        }
+        Statement::Setting { value, .. } => visitor.visit_expression(value),
+        Statement::Spawn { delay, block } => {
+            if let Some(delay) = delay {
+                visitor.visit_expression(delay);
+            }
+            visitor.visit_block(block);
+        }
+        Statement::Switch { input, cases, default } => {
+            visitor.visit_expression(input);
+            for (case_list, block) in cases {
+                for case in case_list {
+                    visitor.visit_case(case);
+                }
+                visitor.visit_block(block);
+            }
+            if let Some(default) = default {
+                visitor.visit_block(default);
+            }
+        }
+        Statement::TryCatch { try_block, catch_params, catch_block } => {
+            visitor.visit_block(try_block);
+            for param in catch_params {
+                visitor.visit_catch_param(location, param);
+            }
+            visitor.visit_block(catch_block);
+        }
+        Statement::Label { block, .. } => visitor.visit_block(block),
+        Statement::Continue(_) |
+        Statement::Break(_) |
+        Statement::Goto(_) => {}
+    }
+}
+
+pub fn walk_var_statement<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, _location: Location, var: &'ast VarStatement) {
+    if let Some(value) = &var.value {
+        visitor.visit_expression(value);
+    }
+}
+
+pub fn walk_case<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, case: &'ast Case) {
+    match case {
+        Case::Exact(expr) => visitor.visit_expression(expr),
+        Case::Range(start, end) => {
+            visitor.visit_expression(start);
+            visitor.visit_expression(end);
+        }
+    }
+}
+
+pub fn walk_expression<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, expression: &'ast Expression) {
+    match expression {
+        Expression::Base { term, follow, .. } => {
+            visitor.visit_term(term.location, &term.elem);
+            for each in follow {
+                visitor.visit_follow(each.location, &each.elem);
+            }
+        }
+        Expression::BinaryOp { lhs, rhs, .. } |
+        Expression::AssignOp { lhs, rhs, .. } => {
+            visitor.visit_expression(lhs);
+            visitor.visit_expression(rhs);
+        }
+        Expression::TernaryOp { cond, if_, else_ } => {
+            visitor.visit_expression(cond);
+            visitor.visit_expression(if_);
+            visitor.visit_expression(else_);
+        }
+    }
+}
+
+pub fn walk_term<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, _location: Location, term: &'ast Term) {
+    match term {
+        Term::Null |
+        Term::Int(_) |
+        Term::Float(_) |
+        Term::Ident(_) |
+        Term::GlobalIdent(_) |
+        Term::String(_) |
+        Term::Resource(_) |
+        Term::As(_) => {}
+        Term::Expr(expr) => visitor.visit_expression(expr),
+        Term::Prefab(prefab) => visitor.visit_prefab(prefab),
+        Term::InterpString(_, parts) => {
+            for (expr, _) in parts {
+                if let Some(expr) = expr {
+                    visitor.visit_expression(expr);
+                }
+            }
+        }
+        Term::Call(_, args) |
+        Term::GlobalCall(_, args) |
+        Term::SelfCall(args) |
+        Term::ParentCall(args) |
+        Term::List(args) => {
+            for arg in args {
+                visitor.visit_expression(arg);
+            }
+        }
+        Term::New { type_, args } => {
+            visitor.visit_new_type(type_);
+            for arg in args.iter().flatten() {
+                visitor.visit_expression(arg);
+            }
+        }
+        Term::Input { args, in_list, .. } |
+        Term::Locate { args, in_list } => {
+            for arg in args {
+                visitor.visit_expression(arg);
+            }
+            if let Some(in_list) = in_list {
+                visitor.visit_expression(in_list);
+            }
+        }
+        Term::Pick(choices) => {
+            for (weight, value) in choices {
+                if let Some(weight) = weight {
+                    visitor.visit_expression(weight);
+                }
+                visitor.visit_expression(value);
+            }
+        }
+        Term::DynamicCall(lhs_args, rhs_args) => {
+            for arg in lhs_args.iter().chain(rhs_args.iter()) {
+                visitor.visit_expression(arg);
+            }
+        }
+    }
+}
+
+pub fn walk_follow<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, _location: Location, follow: &'ast Follow) {
+    match follow {
+        Follow::Index(_, expr) => visitor.visit_expression(expr),
+        Follow::Field(_, _) => {}
+        Follow::Call(_, _, args) => {
+            for arg in args {
+                visitor.visit_expression(arg);
+            }
+        }
+    }
+}
+
+pub fn walk_index_or_field<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, index_or_field: &'ast IndexOrField) {
+    match index_or_field {
+        IndexOrField::Index(_, expr) => visitor.visit_expression(expr),
+        IndexOrField::Field(_, _) => {}
+    }
+}
+
+pub fn walk_new_type<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, new_type: &'ast NewType) {
+    match new_type {
+        NewType::Implicit => {}
+        NewType::Prefab(prefab) => visitor.visit_prefab(prefab),
+        NewType::MiniExpr { fields, .. } => {
+            for field in fields {
+                visitor.visit_index_or_field(field);
+            }
+        }
+    }
+}
+
+pub fn walk_prefab<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, prefab: &'ast Prefab) {
+    for value in prefab.vars.values() {
+        visitor.visit_expression(value);
+    }
+}
+
+pub fn walk_parameter<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, parameter: &'ast Parameter) {
+    if let Some(default) = &parameter.default {
+        visitor.visit_expression(default);
+    }
+    if let Some(in_list) = &parameter.in_list {
+        visitor.visit_expression(in_list);
+    }
+}
+
+/// A traversal of the AST which may modify it in place.
+///
+/// Like `Visitor`, every method defaults to the matching `walk_*_mut`
+/// function. Blocks are passed as `Vec`s so statements may be added or
+/// removed.
+pub trait VisitorMut {
+    fn visit_block_mut(&mut self, block: &mut Block) {
+        walk_block_mut(self, block)
+    }
+
+    fn visit_statement_mut(&mut self, location: Location, statement: &mut Statement) {
+        walk_statement_mut(self, location, statement)
+    }
+
+    fn visit_var_statement_mut(&mut self, location: Location, var: &mut VarStatement) {
+        walk_var_statement_mut(self, location, var)
+    }
+
+    fn visit_loop_var_mut(&mut self, _location: Location, _var_type: &mut Option<VarType>, _name: &mut String) {}
+
+    fn visit_catch_param_mut(&mut self, _location: Location, _param: &mut TreePath) {}
+
+    fn visit_case_mut(&mut self, case: &mut Case) {
+        walk_case_mut(self, case)
+    }
+
+    fn visit_expression_mut(&mut self, expression: &mut Expression) {
+        walk_expression_mut(self, expression)
+    }
+
+    fn visit_term_mut(&mut self, location: Location, term: &mut Term) {
+        walk_term_mut(self, location, term)
+    }
+
+    fn visit_follow_mut(&mut self, location: Location, follow: &mut Follow) {
+        walk_follow_mut(self, location, follow)
+    }
+
+    fn visit_index_or_field_mut(&mut self, index_or_field: &mut IndexOrField) {
+        walk_index_or_field_mut(self, index_or_field)
+    }
+
+    fn visit_new_type_mut(&mut self, new_type: &mut NewType) {
+        walk_new_type_mut(self, new_type)
+    }
+
+    fn visit_prefab_mut(&mut self, prefab: &mut Prefab) {
+        walk_prefab_mut(self, prefab)
+    }
+
+    fn visit_parameter_mut(&mut self, parameter: &mut Parameter) {
+        walk_parameter_mut(self, parameter)
+    }
+}
+
+pub fn walk_block_mut<V: VisitorMut + ?Sized>(visitor: &mut V, block: &mut Block) {
+    for statement in block.iter_mut() {
+        visitor.visit_statement_mut(statement.location, &mut statement.elem);
+    }
+}
+
+pub fn walk_statement_mut<V: VisitorMut + ?Sized>(visitor: &mut V, location: Location, statement: &mut Statement) {
+    match statement {
+        Statement::Expr(expr) |
+        Statement::Throw(expr) |
+        Statement::Del(expr) |
+        Statement::Crash(expr) => visitor.visit_expression_mut(expr),
+        Statement::Return(value) => {
+            if let Some(value) = value {
+                visitor.visit_expression_mut(value);
+            }
+        }
+        Statement::While { condition, block } => {
+            visitor.visit_expression_mut(condition);
+            visitor.visit_block_mut(block);
+        }
+        Statement::DoWhile { block, condition } => {
+            visitor.visit_block_mut(block);
+            visitor.visit_expression_mut(condition);
+        }
+        Statement::If { arms, else_arm } => {
+            for (condition, block) in arms {
+                visitor.visit_expression_mut(&mut condition.elem);
+                visitor.visit_block_mut(block);
+            }
+            if let Some(else_arm) = else_arm {
+                visitor.visit_block_mut(else_arm);
+            }
+        }
+        Statement::ForLoop { init, test, inc, block } => {
+            if let Some(init) = init {
+                visitor.visit_statement_mut(location, init);
+            }
+            if let Some(test) = test {
+                visitor.visit_expression_mut(test);
+            }
+            if let Some(inc) = inc {
+                visitor.visit_statement_mut(location, inc);
+            }
+            visitor.visit_block_mut(block);
+        }
+        Statement::ForList { var_type, name, in_list, block, .. } => {
+            visitor.visit_loop_var_mut(location, var_type, name);
+            if let Some(in_list) = in_list {
+                visitor.visit_expression_mut(in_list);
+            }
+            visitor.visit_block_mut(block);
+        }
+        Statement::ForKeyValue { var_type, key, value, in_list, block } => {
+            visitor.visit_loop_var_mut(location, var_type, key);
+            visitor.visit_loop_var_mut(location, &mut None, value);
+            visitor.visit_expression_mut(in_list);
+            visitor.visit_block_mut(block);
+        }
+        Statement::ForRange { var_type, name, start, end, step, block } => {
+            visitor.visit_loop_var_mut(location, var_type, name);
+            visitor.visit_expression_mut(start);
+            visitor.visit_expression_mut(end);
+            if let Some(step) = step {
+                visitor.visit_expression_mut(step);
+            }
+            visitor.visit_block_mut(block);
+        }
+        Statement::Var(var) => visitor.visit_var_statement_mut(location, var),
+        Statement::Vars(vars) | Statement::Error(vars) => {
+            for var in vars {
+                visitor.visit_var_statement_mut(location, var);
+            }
+        }
+        Statement::Setting { value, .. } => visitor.visit_expression_mut(value),
+        Statement::Spawn { delay, block } => {
+            if let Some(delay) = delay {
+                visitor.visit_expression_mut(delay);
+            }
+            visitor.visit_block_mut(block);
+        }
+        Statement::Switch { input, cases, default } => {
+            visitor.visit_expression_mut(input);
+            for (case_list, block) in cases {
+                for case in case_list {
+                    visitor.visit_case_mut(case);
+                }
+                visitor.visit_block_mut(block);
+            }
+            if let Some(default) = default {
+                visitor.visit_block_mut(default);
+            }
+        }
+        Statement::TryCatch { try_block, catch_params, catch_block } => {
+            visitor.visit_block_mut(try_block);
+            for param in catch_params {
+                visitor.visit_catch_param_mut(location, param);
+            }
+            visitor.visit_block_mut(catch_block);
+        }
+        Statement::Label { block, .. } => visitor.visit_block_mut(block),
+        Statement::Continue(_) |
+        Statement::Break(_) |
+        Statement::Goto(_) => {}
+    }
+}
+
+pub fn walk_var_statement_mut<V: VisitorMut + ?Sized>(visitor: &mut V, _location: Location, var: &mut VarStatement) {
+    if let Some(value) = &mut var.value {
+        visitor.visit_expression_mut(value);
+    }
+}
+
+pub fn walk_case_mut<V: VisitorMut + ?Sized>(visitor: &mut V, case: &mut Case) {
+    match case {
+        Case::Exact(expr) => visitor.visit_expression_mut(expr),
+        Case::Range(start, end) => {
+            visitor.visit_expression_mut(start);
+            visitor.visit_expression_mut(end);
+        }
+    }
+}
+
+pub fn walk_expression_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expression: &mut Expression) {
+    match expression {
+        Expression::Base { term, follow, .. } => {
+            visitor.visit_term_mut(term.location, &mut term.elem);
+            for each in follow {
+                visitor.visit_follow_mut(each.location, &mut each.elem);
+            }
+        }
+        Expression::BinaryOp { lhs, rhs, .. } |
+        Expression::AssignOp { lhs, rhs, .. } => {
+            visitor.visit_expression_mut(lhs);
+            visitor.visit_expression_mut(rhs);
+        }
+        Expression::TernaryOp { cond, if_, else_ } => {
+            visitor.visit_expression_mut(cond);
+            visitor.visit_expression_mut(if_);
+            visitor.visit_expression_mut(else_);
+        }
+    }
+}
+
+pub fn walk_term_mut<V: VisitorMut + ?Sized>(visitor: &mut V, _location: Location, term: &mut Term) {
+    match term {
+        Term::Null |
+        Term::Int(_) |
+        Term::Float(_) |
+        Term::Ident(_) |
+        Term::GlobalIdent(_) |
+        Term::String(_) |
+        Term::Resource(_) |
+        Term::As(_) => {}
+        Term::Expr(expr) => visitor.visit_expression_mut(expr),
+        Term::Prefab(prefab) => visitor.visit_prefab_mut(prefab),
+        Term::InterpString(_, parts) => {
+            for (expr, _) in parts {
+                if let Some(expr) = expr {
+                    visitor.visit_expression_mut(expr);
+                }
+            }
+        }
+        Term::Call(_, args) |
+        Term::GlobalCall(_, args) |
+        Term::SelfCall(args) |
+        Term::ParentCall(args) |
+        Term::List(args) => {
+            for arg in args {
+                visitor.visit_expression_mut(arg);
+            }
+        }
+        Term::New { type_, args } => {
+            visitor.visit_new_type_mut(type_);
+            for arg in args.iter_mut().flatten() {
+                visitor.visit_expression_mut(arg);
+            }
+        }
+        Term::Input { args, in_list, .. } |
+        Term::Locate { args, in_list } => {
+            for arg in args {
+                visitor.visit_expression_mut(arg);
+            }
+            if let Some(in_list) = in_list {
+                visitor.visit_expression_mut(in_list);
+            }
+        }
+        Term::Pick(choices) => {
+            for (weight, value) in choices {
+                if let Some(weight) = weight {
+                    visitor.visit_expression_mut(weight);
+                }
+                visitor.visit_expression_mut(value);
+            }
+        }
+        Term::DynamicCall(lhs_args, rhs_args) => {
+            for arg in lhs_args.iter_mut().chain(rhs_args.iter_mut()) {
+                visitor.visit_expression_mut(arg);
+            }
+        }
+    }
+}
+
+pub fn walk_follow_mut<V: VisitorMut + ?Sized>(visitor: &mut V, _location: Location, follow: &mut Follow) {
+    match follow {
+        Follow::Index(_, expr) => visitor.visit_expression_mut(expr),
+        Follow::Field(_, _) => {}
+        Follow::Call(_, _, args) => {
+            for arg in args {
+                visitor.visit_expression_mut(arg);
+            }
+        }
+    }
+}
+
+pub fn walk_index_or_field_mut<V: VisitorMut + ?Sized>(visitor: &mut V, index_or_field: &mut IndexOrField) {
+    match index_or_field {
+        IndexOrField::Index(_, expr) => visitor.visit_expression_mut(expr),
+        IndexOrField::Field(_, _) => {}
+    }
+}
+
+pub fn walk_new_type_mut<V: VisitorMut + ?Sized>(visitor: &mut V, new_type: &mut NewType) {
+    match new_type {
+        NewType::Implicit => {}
+        NewType::Prefab(prefab) => visitor.visit_prefab_mut(prefab),
+        NewType::MiniExpr { fields, .. } => {
+            for field in fields {
+                visitor.visit_index_or_field_mut(field);
+            }
+        }
+    }
+}
+
+pub fn walk_prefab_mut<V: VisitorMut + ?Sized>(visitor: &mut V, prefab: &mut Prefab) {
+    for (_, value) in prefab.vars.iter_mut() {
+        visitor.visit_expression_mut(value);
+    }
+}
+
+pub fn walk_parameter_mut<V: VisitorMut + ?Sized>(visitor: &mut V, parameter: &mut Parameter) {
+    if let Some(default) = &mut parameter.default {
+        visitor.visit_expression_mut(default);
+    }
+    if let Some(in_list) = &mut parameter.in_list {
+        visitor.visit_expression_mut(in_list);
+    }
+}
@@ -4,15 +4,38 @@ use super::objtree::*;
 use super::ast::*;
 use super::{Location, DMError};
 use super::preprocessor::{DefineMap, Define};
+use super::config::ByondVersion;
 
 const DM_VERSION: i32 = 513;
 const DM_BUILD: i32 = 1508;
 
+/// Whether builtins introduced in the given version should be registered
+/// when targeting `version`. No target means everything known.
+fn available(version: Option<ByondVersion>, major: u32, build: u32) -> bool {
+    version.map_or(true, |v| v.supports(major, build))
+}
+
+/// The version which introduced some builtins, if the target predates it.
+fn missing_since(version: Option<ByondVersion>, major: u32, build: u32) -> Option<ByondVersion> {
+    if available(version, major, build) {
+        None
+    } else {
+        Some(ByondVersion::new(major, build))
+    }
+}
+
 /// Register BYOND builtin macros to the given define map.
-pub fn default_defines(defines: &mut DefineMap) {
+///
+/// If a target version is given, `DM_VERSION` and `DM_BUILD` reflect it and
+/// macros introduced after it are omitted.
+pub fn default_defines(defines: &mut DefineMap, version: Option<ByondVersion>) {
     use super::lexer::*;
     use super::lexer::Token::*;
     let location = Location::builtins();
+    let (dm_version, dm_build) = match version {
+        Some(v) => (v.major as i32, v.build as i32),
+        None => (DM_VERSION, DM_BUILD),
+    };
 
     // #define EXCEPTION(value) new /exception(value)
     defines.insert("EXCEPTION".to_owned(), (location, Define::Function {
@@ -59,18 +82,25 @@ pub fn default_defines(defines: &mut DefineMap) {
     }));
 
     // constants
+    // Set while listing macros newer than the target version, which are
+    // kept apart so that they are undefined but uses of them still expand
+    // and say what they require.
+    let mut newer: Option<ByondVersion> = None;
     macro_rules! c {
         ($($i:ident = $($x:expr),*;)*) => {
             $(
-                assert!(defines.insert(
-                    stringify!($i).into(), (location, Define::Constant { subst: vec![$($x),*], docs: Default::default() })
-                ).is_none(), stringify!($i));
+                let define = Define::Constant { subst: vec![$($x),*], docs: Default::default() };
+                if let Some(since) = newer {
+                    defines.insert_newer(stringify!($i).into(), since, define);
+                } else {
+                    assert!(defines.insert(stringify!($i).into(), (location, define)).is_none(), stringify!($i));
+                }
             )*
         }
     }
     c! {
-        DM_VERSION = Int(DM_VERSION);
-        DM_BUILD = Int(DM_BUILD);
+        DM_VERSION = Int(dm_version);
+        DM_BUILD = Int(dm_build);
         SPACEMAN_DMM = Int(1);
 
         // eye and sight
@@ -133,7 +163,6 @@ pub fn default_defines(defines: &mut DefineMap) {
         QUAD_EASING = Int(7);
         EASE_IN = Int(64);
         EASE_OUT = Int(128);
-        JUMP_EASING = Int(256); // 513
 
         // animation flags
         ANIMATION_END_NOW = Int(1);
@@ -156,8 +185,11 @@ pub fn default_defines(defines: &mut DefineMap) {
         DATABASE_ROW_COLUMN_NAMES = Int(16);
         DATABASE_ROW_COLUMN_VALUE = Int(17);
         DATABASE_ROW_LIST = Int(18);
+    }
 
-        // 513 stuff
+    newer = missing_since(version, 513, 0);
+    c! {
+        JUMP_EASING = Int(256);
 
         // vis_flags
         VIS_INHERIT_ICON = Int(1);
@@ -180,12 +212,21 @@ pub fn default_defines(defines: &mut DefineMap) {
 }
 
 /// Register BYOND builtins into the specified object tree.
-pub fn register_builtins(tree: &mut ObjectTree) -> Result<(), DMError> {
+///
+/// If a target version is given, builtins introduced after it are omitted.
+pub fn register_builtins(tree: &mut ObjectTree, version: Option<ByondVersion>) -> Result<(), DMError> {
+    // Set while listing builtins newer than the target version, which are
+    // only recorded so that uses of them can say what they require.
+    let mut newer: Option<ByondVersion> = None;
     macro_rules! entries {
         ($($($elem:ident)/ * $(($($arg:ident $(= $ignored:expr)*),*))* $(= $val:expr)*;)*) => {
             $(loop {
                 #![allow(unreachable_code)]
                 let elems = [$(stringify!($elem)),*];
+                if let Some(since) = newer {
+                    tree.add_newer_builtin(&elems, since);
+                    break;
+                }
                 $(
                     tree.add_builtin_var(&elems, $val)?;
                     break;
@@ -950,8 +991,10 @@ pub fn register_builtins(tree: &mut ObjectTree) -> Result<(), DMError> {
         savefile/proc/ImportText(/* path=cd, file */);
         savefile/proc/Lock(timeout);
         savefile/proc/Unlock();
+    };
 
-        // 513 stuff
+    newer = missing_since(version, 513, 0);
+    entries! {
         proc/arctan(A,B);
         proc/clamp(NumberOrList,Low,High);
         proc/islist(List);
@@ -984,5 +1027,12 @@ pub fn register_builtins(tree: &mut ObjectTree) -> Result<(), DMError> {
         regex/proc/Replace_char(text, rep, start, end);
     };
 
+    newer = missing_since(version, 515, 0);
+    entries! {
+        var/const/__PROC__;  // special form, the current proc
+        var/const/__TYPE__;  // special form, the current type
+        proc/nameof(Var);  // special form
+    };
+
     Ok(())
 }
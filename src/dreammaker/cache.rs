@@ -0,0 +1,183 @@
+//! On-disk caching of parsed object trees.
+//!
+//! A cache records a hash of every file which was read while parsing, and is
+//! only used if all of those files are unchanged. Diagnostics produced while
+//! parsing are stored alongside the tree and registered again when it is
+//! loaded, as are the warning levels set by `#pragma`, any suppression
+//! comments, and the macro expansions recorded for diagnostics.
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Serialize, Deserialize};
+
+use super::{Context, DMError, Location, MacroExpansion};
+use super::objtree::ObjectTree;
+use super::pragma::Pragmas;
+use super::suppression::Suppressions;
+use super::preprocessor::Preprocessor;
+use super::indents::IndentProcessor;
+use super::parser::Parser;
+
+/// Identifies a cache file.
+const MAGIC: &[u8; 8] = b"DMCACHE\0";
+
+/// Incremented whenever the serialized representation changes.
+const FORMAT_VERSION: u32 = 7;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Header {
+    /// The version of this crate which wrote the cache.
+    crate_version: String,
+    /// Whether proc bodies were parsed.
+    procs: bool,
+    /// The configured target BYOND version, which decides the builtins.
+    byond_version: Option<(u32, u32)>,
+    /// The configured diagnostic settings, which the stored diagnostics have
+    /// already been filtered by.
+    diagnostics: String,
+    /// Every file in the context's file list, with a hash of its contents.
+    files: Vec<(PathBuf, Option<u64>)>,
+}
+
+/// Parse an environment, reusing the object tree stored in `cache` if none
+/// of its files have changed since it was written, and updating the cache
+/// otherwise.
+///
+/// As with `Context::parse_environment`, will only return failure on an
+/// `io::Error` reading the environment.
+pub fn parse_environment(context: &Context, dme: &Path, cache: &Path, procs: bool) -> io::Result<ObjectTree> {
+    if let Some(objtree) = load(context, dme, cache, procs) {
+        return Ok(objtree);
+    }
+
+    let mut preprocessor = Preprocessor::new(context, dme.to_owned())?;
+    preprocessor.enable_macro_provenance();
+    let mut parser = Parser::new(context, IndentProcessor::new(context, preprocessor));
+    if procs {
+        parser.defer_procs();
+    }
+    let mut objtree = parser.parse_object_tree();
+    objtree.parse_proc_bodies(context);
+
+    // A cache which can't be written only means parsing again next time.
+    let _ = save(context, dme, cache, procs, &objtree);
+    Ok(objtree)
+}
+
+/// Load a cached object tree if it is up to date, registering the
+/// diagnostics stored alongside it.
+///
+/// The context must not have any files registered yet, as the locations in
+/// the cache refer to files in the order they were originally registered.
+pub fn load(context: &Context, dme: &Path, cache: &Path, procs: bool) -> Option<ObjectTree> {
+    let mut reader = BufReader::new(File::open(cache).ok()?);
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic).ok()?;
+    let version: u32 = bincode::deserialize_from(&mut reader).ok()?;
+    if &magic != MAGIC || version != FORMAT_VERSION {
+        return None;
+    }
+
+    let header: Header = bincode::deserialize_from(&mut reader).ok()?;
+    if header.crate_version != env!("CARGO_PKG_VERSION") || (procs && !header.procs) {
+        return None;
+    }
+    if header.byond_version != target_version(context) || header.diagnostics != context.config().diagnostics_key() {
+        return None;
+    }
+    if !context.clone_file_list().paths().is_empty() {
+        return None;
+    }
+    match header.files.first() {
+        Some((path, _)) if path == dme => {}
+        _ => return None,
+    }
+    for (path, hash) in header.files.iter() {
+        if hash_file(&resolve(dme, path)) != *hash {
+            return None;
+        }
+    }
+
+    let objtree: ObjectTree = bincode::deserialize_from(&mut reader).ok()?;
+    let pragmas: Pragmas = bincode::deserialize_from(&mut reader).ok()?;
+    let suppressions: Suppressions = bincode::deserialize_from(&mut reader).ok()?;
+    let expansions: BTreeMap<Location, Vec<MacroExpansion>> = bincode::deserialize_from(&mut reader).ok()?;
+    let errors: Vec<DMError> = bincode::deserialize_from(&mut reader).ok()?;
+    for (path, _) in header.files.iter() {
+        context.register_file(path);
+    }
+    context.set_pragmas(pragmas);
+    context.set_suppressions(suppressions);
+    context.set_expansions(expansions);
+    for error in errors {
+        context.register_error(error);
+    }
+    Some(objtree)
+}
+
+/// Write an object tree and the diagnostics registered so far to a cache.
+pub fn save(context: &Context, dme: &Path, cache: &Path, procs: bool, objtree: &ObjectTree) -> io::Result<()> {
+    let files = context.clone_file_list().paths().into_iter()
+        .map(|path| {
+            let hash = hash_file(&resolve(dme, &path));
+            (path, hash)
+        })
+        .collect();
+    let header = Header {
+        crate_version: env!("CARGO_PKG_VERSION").to_owned(),
+        procs,
+        byond_version: target_version(context),
+        diagnostics: context.config().diagnostics_key(),
+        files,
+    };
+
+    // Write to a temporary file first so that an interrupted write does not
+    // leave a truncated cache behind.
+    let temp = cache.with_extension("tmp");
+    {
+        let mut writer = BufWriter::new(File::create(&temp)?);
+        writer.write_all(MAGIC)?;
+        bincode::serialize_into(&mut writer, &FORMAT_VERSION).map_err(to_io_error)?;
+        bincode::serialize_into(&mut writer, &header).map_err(to_io_error)?;
+        bincode::serialize_into(&mut writer, objtree).map_err(to_io_error)?;
+        bincode::serialize_into(&mut writer, &*context.pragmas()).map_err(to_io_error)?;
+        bincode::serialize_into(&mut writer, &*context.suppressions()).map_err(to_io_error)?;
+        bincode::serialize_into(&mut writer, &*context.expansions()).map_err(to_io_error)?;
+        bincode::serialize_into(&mut writer, &context.errors()).map_err(to_io_error)?;
+        writer.flush()?;
+    }
+    fs::rename(&temp, cache)
+}
+
+fn target_version(context: &Context) -> Option<(u32, u32)> {
+    context.config().byond_version().map(|v| (v.major, v.build))
+}
+
+/// Files other than the `.dme` itself are registered relative to it.
+fn resolve(dme: &Path, path: &Path) -> PathBuf {
+    if path == dme {
+        return path.to_owned();
+    }
+    match dme.parent() {
+        Some(parent) => parent.join(path),
+        None => path.to_owned(),
+    }
+}
+
+/// Hash a file's contents with 64-bit FNV-1a, which unlike the standard
+/// library's hasher is stable across Rust versions.
+fn hash_file(path: &Path) -> Option<u64> {
+    let contents = fs::read(path).ok()?;
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for &byte in contents.iter() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    Some(hash)
+}
+
+fn to_io_error(error: bincode::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, error)
+}
@@ -4,8 +4,10 @@ use std::fs::File;
 use std::io::Read;
 use std::path::Path;
 use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
 
-use serde::Deserialize;
+use serde::{Serialize, Deserialize, Deserializer};
 
 use crate::error::Severity;
 use crate::DMError;
@@ -19,6 +21,8 @@ pub struct Config {
     diagnostics: HashMap<String, WarningLevel>,
     pub code_standards: CodeStandards,
     pub dmdoc: DMDoc,
+    pub format: Format,
+    pub environment: Environment,
 }
 
 /// General error display options
@@ -48,8 +52,90 @@ pub struct DMDoc {
     pub use_typepath_names: bool,
 }
 
+/// Formatter config options
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Format {
+    /// Indent with this many spaces, or with tabs if zero.
+    pub indent_spaces: usize,
+    pub space_around_operators: bool,
+    pub space_after_comma: bool,
+    pub max_blank_lines: usize,
+}
+
+impl Default for Format {
+    fn default() -> Format {
+        Format {
+            indent_spaces: 0,
+            space_around_operators: true,
+            space_after_comma: true,
+            max_blank_lines: 2,
+        }
+    }
+}
+
+/// Target environment options
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct Environment {
+    /// The BYOND version the project is compiled with. When set, builtins
+    /// introduced after it are not registered and newer syntax is flagged.
+    pub byond_version: Option<ByondVersion>,
+}
+
+/// A BYOND version, written as `major.build` (such as `514.1589`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ByondVersion {
+    pub major: u32,
+    pub build: u32,
+}
+
+impl ByondVersion {
+    pub const fn new(major: u32, build: u32) -> ByondVersion {
+        ByondVersion { major, build }
+    }
+
+    /// Whether this version is at least the given one, comparing builds
+    /// within the same major version.
+    pub fn supports(self, major: u32, build: u32) -> bool {
+        (self.major, self.build) >= (major, build)
+    }
+}
+
+impl fmt::Display for ByondVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.build == 0 {
+            // a requirement of any build of a major version
+            write!(f, "{}", self.major)
+        } else {
+            write!(f, "{}.{}", self.major, self.build)
+        }
+    }
+}
+
+impl FromStr for ByondVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ByondVersion, String> {
+        let mut parts = s.trim().splitn(2, '.');
+        let major = parts.next().unwrap_or("");
+        let build = parts.next().unwrap_or("0");
+        match (major.parse(), build.parse()) {
+            (Ok(major), Ok(build)) => Ok(ByondVersion { major, build }),
+            _ => Err(format!("invalid BYOND version {:?}, expected e.g. \"514.1589\"", s)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ByondVersion {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<ByondVersion, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        text.parse().map_err(serde::de::Error::custom)
+    }
+}
+
 /// Severity overrides from configuration
-#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
 #[serde(rename_all(deserialize = "lowercase"))]
 pub enum WarningLevel {
     #[serde(alias = "errors")]
@@ -76,6 +162,11 @@ impl Config {
         Ok(toml::from_str(&config_toml)?)
     }
 
+    /// The configured target BYOND version, if any.
+    pub fn byond_version(&self) -> Option<ByondVersion> {
+        self.environment.byond_version
+    }
+
     fn config_warninglevel(&self, error: &DMError) -> Option<&WarningLevel> {
         if let Some(errortype) = error.errortype() {
             return self.diagnostics.get(errortype)
@@ -89,14 +180,41 @@ impl Config {
     /// [`Severity`]: ../enum.Severity.html
     /// [`None`]: ../../std/option/enum.Option.html#variant.None
     pub fn set_configured_severity(&self, error: DMError) -> Option<DMError> {
-        Some(match self.config_warninglevel(&error) {
-            Some(WarningLevel::Error) => error.set_severity(Severity::Error),
-            Some(WarningLevel::Warning) => error.set_severity(Severity::Warning),
-            Some(WarningLevel::Info) => error.set_severity(Severity::Info),
-            Some(WarningLevel::Hint) => error.set_severity(Severity::Hint),
-            Some(WarningLevel::Disabled) => return None,
-            Some(WarningLevel::Unset) | None => error,
-        })
+        match self.config_warninglevel(&error) {
+            Some(&level) => level.apply(error),
+            None => Some(error),
+        }
+    }
+
+    /// Whether the configuration sets a level for this error's errortype.
+    pub fn overrides(&self, error: &DMError) -> bool {
+        match self.config_warninglevel(error) {
+            Some(WarningLevel::Unset) | None => false,
+            Some(_) => true,
+        }
+    }
+
+    /// Whether the configuration enables an errortype which is off by default.
+    pub fn opted_in(&self, errortype: &str) -> bool {
+        match self.diagnostics.get(errortype) {
+            Some(WarningLevel::Disabled) | Some(WarningLevel::Unset) | None => false,
+            Some(_) => true,
+        }
+    }
+
+    /// The settings which decide which diagnostics are kept and with what
+    /// severity, in a stable order, so that diagnostics stored by a cache can
+    /// be matched with the configuration they were produced under.
+    pub fn diagnostics_key(&self) -> String {
+        let mut diagnostics: Vec<_> = self.diagnostics.iter().collect();
+        diagnostics.sort_by(|a, b| a.0.cmp(b.0));
+        format!(
+            "{:?} {:?} {} {}",
+            self.display.error_level,
+            diagnostics,
+            self.code_standards.disallow_relative_proc_definitions,
+            self.code_standards.disallow_relative_type_definitions,
+        )
     }
 
     /// Test the error against the configured error level threshold
@@ -106,6 +224,18 @@ impl Config {
 }
 
 impl WarningLevel {
+    /// Return the error with this level's severity, or `None` if disabled.
+    pub fn apply(self, error: DMError) -> Option<DMError> {
+        Some(match self {
+            WarningLevel::Error => error.set_severity(Severity::Error),
+            WarningLevel::Warning => error.set_severity(Severity::Warning),
+            WarningLevel::Info => error.set_severity(Severity::Info),
+            WarningLevel::Hint => error.set_severity(Severity::Hint),
+            WarningLevel::Disabled => return None,
+            WarningLevel::Unset => error,
+        })
+    }
+
     fn applies_to(self, severity: Severity) -> bool {
         match self {
             WarningLevel::Disabled => false,
@@ -5,6 +5,7 @@ use std::path::Path;
 
 use linked_hash_map::LinkedHashMap;
 use ordered_float::OrderedFloat;
+use serde::{Serialize, Deserialize};
 
 use super::{DMError, Location, HasLocation, Context};
 use super::objtree::*;
@@ -14,7 +15,7 @@ use super::preprocessor::DefineMap;
 /// An absolute typepath and optional variables.
 ///
 /// The path may involve `/proc` or `/verb` references.
-#[derive(Clone, Hash, Eq, PartialEq, Debug)]
+#[derive(Clone, Hash, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub struct Pop {
     pub path: TreePath,
     pub vars: LinkedHashMap<String, Constant>,
@@ -39,7 +40,7 @@ impl fmt::Display for Pop {
 ///
 /// This is intended to represent the degree to which constants are evaluated
 /// before being displayed in DreamMaker.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Constant {
     /// The literal `null`.
     Null(Option<TreePath>),
@@ -105,15 +106,17 @@ impl std::cmp::PartialEq for Constant {
 impl std::cmp::Eq for Constant {}
 
 /// The constant functions which are represented as-is.
-#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Serialize, Deserialize)]
 pub enum ConstFn {
-    /// The `icon()` type constructor.
+    /// The `icon()` type constructor. Positional arguments are stored and
+    /// displayed by name, as in `icon(icon = 'a.dmi', icon_state = "x")`.
     Icon,
     /// The `matrix()` type constructor.
     Matrix,
     /// The `newlist()` function, which combines `new` mapped over a `list`.
     Newlist,
-    /// The `sound()` type constructor.
+    /// The `sound()` type constructor, with its arguments named like
+    /// `icon()`'s.
     Sound,
     /// The `filter()` type constructor.
     Filter,
@@ -138,6 +141,11 @@ impl Constant {
         Constant::String(s.into())
     }
 
+    /// A `matrix()` with the components `a` through `f`.
+    pub fn matrix(components: [f32; 6]) -> Constant {
+        Constant::Call(ConstFn::Matrix, components.iter().map(|&c| (Constant::Float(c), None)).collect())
+    }
+
     // ------------------------------------------------------------------------
     // Conversions
 
@@ -186,6 +194,34 @@ impl Constant {
         match *self {
             Constant::String(ref s) |
             Constant::Resource(ref s) => Some(s),
+            Constant::Call(ConstFn::File, ref args) => args.first().and_then(|(file, _)| file.as_path_str()),
+            Constant::Call(ConstFn::Icon, _) => self.call_argument("icon").and_then(Constant::as_path_str),
+            Constant::Call(ConstFn::Sound, _) => self.call_argument("file").and_then(Constant::as_path_str),
+            _ => None,
+        }
+    }
+
+    /// Look up a named argument of an `icon()`, `sound()`, or `filter()` call.
+    pub fn call_argument(&self, name: &str) -> Option<&Constant> {
+        match *self {
+            Constant::Call(_, ref args) => argument(args, name),
+            _ => None,
+        }
+    }
+
+    /// The six components `a` through `f` of a folded `matrix()`.
+    pub fn as_matrix(&self) -> Option<[f32; 6]> {
+        match *self {
+            Constant::Call(ConstFn::Matrix, ref args) if args.len() == 6 => {
+                let mut matrix = [0.; 6];
+                for (each, (value, assoc)) in matrix.iter_mut().zip(args.iter()) {
+                    if assoc.is_some() {
+                        return None;
+                    }
+                    *each = value.to_float()?;
+                }
+                Some(matrix)
+            }
             _ => None,
         }
     }
@@ -357,9 +393,10 @@ impl fmt::Display for Constant {
                         write!(f, ", ")?;
                     }
                     first = false;
-                    write!(f, "{}", key)?;
-                    if let Some(val) = val {
-                        write!(f, " = {}", val)?;
+                    match (key, val) {
+                        (Constant::String(name), Some(val)) if is_ident(name) => write!(f, "{} = {}", name, val)?,
+                        (key, Some(val)) => write!(f, "{} = {}", key, val)?,
+                        (key, None) => write!(f, "{}", key)?,
                     }
                 }
                 write!(f, ")")
@@ -532,6 +569,153 @@ fn constant_ident_lookup(
     Ok(ConstLookup::Found(type_hint, value))
 }
 
+/// DM's `%%` operator, whose result has the sign of the divisor.
+fn float_mod(lhs: f32, rhs: f32) -> f32 {
+    lhs - rhs * (lhs / rhs).floor()
+}
+
+/// The elements of a list, or a single non-list value, for list operators.
+fn list_items(constant: Constant) -> Vec<(Constant, Option<Constant>)> {
+    match constant {
+        Constant::List(list) => list,
+        other => vec![(other, None)],
+    }
+}
+
+/// The name given by `nameof()` for an expression: the last identifier in it.
+fn name_of(expr: &Expression) -> Option<&str> {
+    match expr {
+        Expression::Base { term, follow, .. } => match follow.last().map(|f| &f.elem) {
+            Some(Follow::Field(_, name)) | Some(Follow::Call(_, name, _)) => Some(name),
+            Some(Follow::Index(..)) => None,
+            None => match &term.elem {
+                Term::Ident(name) |
+                Term::GlobalIdent(name) |
+                Term::Call(name, _) |
+                Term::GlobalCall(name, _) => Some(name),
+                Term::Prefab(prefab) if prefab.vars.is_empty() => prefab.path.last().map(|(_, name)| &**name),
+                Term::Expr(expr) => name_of(expr),
+                _ => None,
+            },
+        },
+        _ => None,
+    }
+}
+
+fn argument<'a>(args: &'a [(Constant, Option<Constant>)], name: &str) -> Option<&'a Constant> {
+    args.iter()
+        .find(|(key, _)| key.eq_string(name))
+        .and_then(|(_, value)| value.as_ref())
+}
+
+fn is_ident(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().map_or(false, |c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// A numeric result, kept as an `Int` when it is one.
+fn number(value: f32) -> Constant {
+    if value.fract() == 0. && value.abs() < 16777216. {
+        Constant::Int(value as i32)
+    } else {
+        Constant::Float(value)
+    }
+}
+
+/// The text macros whose output depends on the embedded value before them.
+const VALUE_MACROS: &[&str] = &[
+    "the", "The", "a", "an", "A", "An", "s", "th", "roman", "Roman", "icon", "ref",
+    "he", "He", "she", "She", "his", "His", "him", "himself", "herself", "hers", "Hers",
+];
+
+fn has_value_macro(text: &str) -> bool {
+    let mut rest = text;
+    while let Some(pos) = rest.find('\\') {
+        let after = &rest[pos + 1..];
+        let word_len = after.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(after.len());
+        if VALUE_MACROS.contains(&&after[..word_len]) {
+            return true;
+        }
+        // skip the escaped character so that "\\the" is not a macro
+        rest = &after[after.chars().next().map_or(0, char::len_utf8)..];
+    }
+    false
+}
+
+/// Escape a character for storage in a raw string constant.
+fn push_escaped(out: &mut String, ch: char) {
+    match ch {
+        '"' | '\\' | '[' | ']' => {
+            out.push('\\');
+            out.push(ch);
+        }
+        '\n' => out.push_str("\\n"),
+        '\t' => out.push_str("\\t"),
+        _ => out.push(ch),
+    }
+}
+
+/// DM's `text2num()`: the longest numeric prefix, ignoring leading spaces.
+fn text_to_num(text: &str, radix: u32) -> Option<f32> {
+    let text = text.trim_start();
+    if radix != 10 {
+        let negative = text.starts_with('-');
+        let digits = if negative { &text[1..] } else { text };
+        let end = digits.find(|c: char| !c.is_digit(radix)).unwrap_or(digits.len());
+        let value = i64::from_str_radix(&digits[..end], radix).ok()? as f32;
+        return Some(if negative { -value } else { value });
+    }
+    let mut end = text.len();
+    while end > 0 {
+        if let Ok(value) = text[..end].parse::<f32>() {
+            if value.is_finite() {
+                return Some(value);
+            }
+        }
+        end -= text[..end].chars().next_back().map_or(1, char::len_utf8);
+    }
+    None
+}
+
+const MATRIX_IDENTITY: [f32; 6] = [1., 0., 0., 0., 1., 0.];
+
+// The MATRIX_* mode constants from the builtins.
+const MATRIX_COPY: i32 = 0;
+const MATRIX_MULTIPLY: i32 = 1;
+const MATRIX_ADD: i32 = 2;
+const MATRIX_SUBTRACT: i32 = 3;
+const MATRIX_INVERT: i32 = 4;
+const MATRIX_ROTATE: i32 = 5;
+const MATRIX_SCALE: i32 = 6;
+const MATRIX_TRANSLATE: i32 = 7;
+const MATRIX_MODIFY: i32 = 128;
+
+/// Apply `lhs` and then `rhs`, as `lhs * rhs` does in DM.
+fn matrix_multiply(lhs: [f32; 6], rhs: [f32; 6]) -> [f32; 6] {
+    let [a, b, c, d, e, f] = lhs;
+    let [ra, rb, rc, rd, re, rf] = rhs;
+    [
+        ra * a + rb * d, ra * b + rb * e, ra * c + rb * f + rc,
+        rd * a + re * d, rd * b + re * e, rd * c + re * f + rf,
+    ]
+}
+
+fn matrix_invert(matrix: [f32; 6]) -> Option<[f32; 6]> {
+    let [a, b, c, d, e, f] = matrix;
+    let det = a * e - b * d;
+    if det == 0. {
+        return None;
+    }
+    Some([e / det, -b / det, (b * f - c * e) / det, -d / det, a / det, (c * d - a * f) / det])
+}
+
+/// A clockwise rotation by `angle` degrees, as `Turn()` does.
+fn matrix_rotation(angle: f32) -> [f32; 6] {
+    let (sin, cos) = angle.to_radians().sin_cos();
+    [cos, sin, 0., -sin, cos, 0.]
+}
+
 struct ConstantFolder<'a> {
     tree: Option<&'a mut ObjectTree>,
     defines: Option<&'a DefineMap>,
@@ -579,7 +763,6 @@ impl<'a> ConstantFolder<'a> {
     }
 
     /// list of expressions, keyword arguments disallowed
-    #[allow(dead_code)]
     fn expr_vec(&mut self, v: Vec<Expression>) -> Result<Vec<Constant>, DMError> {
         let mut out = Vec::new();
         for each in v {
@@ -611,6 +794,21 @@ impl<'a> ConstantFolder<'a> {
         Ok(out)
     }
 
+    /// arguments keyed by name, with positional arguments named by `names`
+    fn named_arguments(&mut self, v: Vec<Expression>, names: &[&str]) -> Result<Vec<(Constant, Option<Constant>)>, DMError> {
+        let mut out = Vec::new();
+        for (i, (key, value)) in self.arguments(v)?.into_iter().enumerate() {
+            match value {
+                Some(value) => out.push((key, Some(value))),
+                None => match names.get(i) {
+                    Some(name) => out.push((Constant::string(*name), Some(key))),
+                    None => return Err(self.error(format!("too many positional arguments, expected at most {}", names.len()))),
+                },
+            }
+        }
+        Ok(out)
+    }
+
     fn follow(&mut self, term: Constant, follow: Follow) -> Result<Constant, DMError> {
         match (term, follow) {
             // Meant to handle the GLOB.SCI_FREQ case:
@@ -630,6 +828,30 @@ impl<'a> ConstantFolder<'a> {
                     None => Err(self.error(format!("unknown typepath {}", full_path))),
                 }
             }
+            // The initial value of a var on a type: /obj/item::name
+            (Constant::Prefab(ref pop), Follow::Field(IndexKind::Scope, ref field_name)) if pop.vars.is_empty() => {
+                let full_path = FormatTreePath(&pop.path).to_string();
+                match self.tree.as_mut().and_then(|t| t.types.get(&full_path)) {
+                    Some(&idx) => self.recursive_lookup(idx, field_name, false),
+                    None => Err(self.error(format!("unknown typepath {}", full_path))),
+                }
+            }
+            // Chained matrix procs: matrix().Turn(90).Scale(2)
+            (Constant::Call(ConstFn::Matrix, args), Follow::Call(_, method, method_args)) => {
+                let matrix = self.matrix_value(&Constant::Call(ConstFn::Matrix, args))?;
+                let mode = match &*method {
+                    "Turn" => MATRIX_ROTATE,
+                    "Scale" => MATRIX_SCALE,
+                    "Translate" => MATRIX_TRANSLATE,
+                    "Multiply" => MATRIX_MULTIPLY,
+                    "Add" => MATRIX_ADD,
+                    "Subtract" => MATRIX_SUBTRACT,
+                    "Invert" => MATRIX_INVERT,
+                    _ => return Err(self.error(format!("non-constant matrix proc: {}", method))),
+                };
+                let operands = self.expr_vec(method_args)?;
+                Ok(Constant::matrix(self.matrix_op(matrix, mode, &operands)?))
+            }
             (term, follow) => Err(self.error(format!("non-constant expression follower: {} {:?}", term, follow))),
         }
     }
@@ -644,6 +866,8 @@ impl<'a> ConstantFolder<'a> {
             (UnaryOp::Not, Int(i)) => Int(if i != 0 { 0 } else { 1 }),
             // float ops
             (UnaryOp::Neg, Float(i)) => Float(-i),
+            // any constant is either truthy or falsy
+            (UnaryOp::Not, term) => Constant::from(!term.to_bool()),
             // unsupported
             (op, term) => return Err(self.error(format!("non-constant unary operation: {}", op.around(&term)))),
         })
@@ -672,6 +896,8 @@ impl<'a> ConstantFolder<'a> {
         numeric!(LessEq <=);
         numeric!(Greater >);
         numeric!(GreaterEq >=);
+        numeric!(Eq ==);
+        numeric!(NotEq !=);
         match (op, lhs, rhs) {
             (BinaryOp::Pow, Int(lhs), Int(rhs)) => {
                 use std::convert::TryFrom;
@@ -685,6 +911,10 @@ impl<'a> ConstantFolder<'a> {
             (BinaryOp::Pow, Int(lhs), Float(rhs)) => return Ok(Constant::from((lhs as f32).powf(rhs))),
             (BinaryOp::Pow, Float(lhs), Int(rhs)) => return Ok(Constant::from(lhs.powi(rhs))),
             (BinaryOp::Pow, Float(lhs), Float(rhs)) => return Ok(Constant::from(lhs.powf(rhs))),
+            (BinaryOp::FloatMod, Int(lhs), Int(rhs)) => return Ok(Constant::from(float_mod(lhs as f32, rhs as f32))),
+            (BinaryOp::FloatMod, Int(lhs), Float(rhs)) => return Ok(Constant::from(float_mod(lhs as f32, rhs))),
+            (BinaryOp::FloatMod, Float(lhs), Int(rhs)) => return Ok(Constant::from(float_mod(lhs, rhs as f32))),
+            (BinaryOp::FloatMod, Float(lhs), Float(rhs)) => return Ok(Constant::from(float_mod(lhs, rhs))),
             (_, lhs_, rhs_) => {
                 lhs = lhs_;
                 rhs = rhs_;
@@ -705,8 +935,45 @@ impl<'a> ConstantFolder<'a> {
         integer!(LShift <<);
         integer!(RShift >>);
 
+        if let Some(matrix) = lhs.as_matrix() {
+            let mode = match op {
+                BinaryOp::Mul => Some(MATRIX_MULTIPLY),
+                BinaryOp::Add => Some(MATRIX_ADD),
+                BinaryOp::Sub => Some(MATRIX_SUBTRACT),
+                _ => None,
+            };
+            if let Some(mode) = mode {
+                return Ok(Constant::matrix(self.matrix_op(matrix, mode, &[rhs])?));
+            }
+        }
+
         match (op, lhs, rhs) {
             (BinaryOp::Add, String(lhs), String(rhs)) => Ok(String(lhs + &rhs)),
+            (BinaryOp::Add, List(mut lhs), rhs) => {
+                lhs.extend(list_items(rhs));
+                Ok(List(lhs))
+            },
+            (BinaryOp::Sub, List(mut lhs), rhs) => {
+                // like List.Remove(), each removes the last matching element
+                for (item, _) in list_items(rhs) {
+                    if let Some(pos) = lhs.iter().rposition(|(key, _)| *key == item) {
+                        lhs.remove(pos);
+                    }
+                }
+                Ok(List(lhs))
+            },
+            (BinaryOp::BitOr, List(mut lhs), rhs) => {
+                for (item, assoc) in list_items(rhs) {
+                    if !lhs.iter().any(|(key, _)| *key == item) {
+                        lhs.push((item, assoc));
+                    }
+                }
+                Ok(List(lhs))
+            },
+            (BinaryOp::BitAnd, List(lhs), rhs) => {
+                let rhs = list_items(rhs);
+                Ok(List(lhs.into_iter().filter(|(key, _)| rhs.iter().any(|(item, _)| item == key)).collect()))
+            },
             (BinaryOp::Eq, lhs, rhs) => Ok(Constant::from(lhs == rhs)),
             (BinaryOp::NotEq, lhs, rhs) => Ok(Constant::from(lhs != rhs)),
             (BinaryOp::And, lhs, rhs) => Ok(if lhs.to_bool() { rhs } else { lhs }),
@@ -731,33 +998,36 @@ impl<'a> ConstantFolder<'a> {
             },
             Term::List(vec) => Constant::List(self.arguments(vec)?),
             Term::Call(ident, args) => match &*ident {
-                // constructors which remain as they are
-                "matrix" => Constant::Call(ConstFn::Matrix, self.arguments(args)?),
-                "newlist" => Constant::Call(ConstFn::Newlist, self.arguments(args)?),
-                "icon" => Constant::Call(ConstFn::Icon, self.arguments(args)?),
-                "sound" => Constant::Call(ConstFn::Sound, self.arguments(args)?),
+                // constructors which remain as calls, with their arguments named
+                "icon" => Constant::Call(ConstFn::Icon, self.named_arguments(args, &["icon", "icon_state", "dir", "frame", "moving"])?),
+                "sound" => Constant::Call(ConstFn::Sound, self.named_arguments(args, &["file", "repeat", "wait", "channel", "volume"])?),
+                "filter" => self.filter(args)?,
                 "file" => Constant::Call(ConstFn::File, self.arguments(args)?),
-                // constant-evaluatable functions
-                "sin" => self.trig_op(args, f32::sin)?,
-                "cos" => self.trig_op(args, f32::cos)?,
-                "arcsin" => self.trig_op(args, f32::asin)?,
-                "arccos" => self.trig_op(args, f32::acos)?,
-                "rgb" => {
-                    use std::fmt::Write;
-                    if args.len() != 3 && args.len() != 4 {
-                        return Err(self.error(format!("malformed rgb() call, must have 3 or 4 arguments and instead has {}", args.len())));
+                // constructors which fold to concrete values
+                "matrix" => self.matrix(args)?,
+                "newlist" => self.newlist(args)?,
+                // constant-evaluatable functions; DM's trig functions take
+                // and return degrees, not radians
+                "sin" => self.trig_op(args, |x| x.to_radians().sin())?,
+                "cos" => self.trig_op(args, |x| x.to_radians().cos())?,
+                "tan" => self.trig_op(args, |x| x.to_radians().tan())?,
+                "arcsin" => self.trig_op(args, |x| x.asin().to_degrees())?,
+                "arccos" => self.trig_op(args, |x| x.acos().to_degrees())?,
+                "arctan" => self.trig_op(args, |x| x.atan().to_degrees())?,
+                "rgb" => self.rgb(args)?,
+                "abs" | "sqrt" | "round" | "min" | "max" | "length" | "text2num" | "num2text" |
+                "ascii2text" | "text2ascii" | "uppertext" | "lowertext" | "copytext" => {
+                    let args = self.expr_vec(args)?;
+                    self.builtin(&ident, args)?
+                },
+                "nameof" => {
+                    if args.len() != 1 {
+                        return Err(self.error(format!("malformed nameof() call, must have 1 argument and instead has {}", args.len())));
                     }
-                    let mut result = String::with_capacity(7);
-                    result.push_str("#");
-                    for each in args {
-                        if let Some(i) = self.expr(each, None)?.to_int() {
-                            let clamped = std::cmp::max(::std::cmp::min(i, 255), 0);
-                            let _ = write!(result, "{:02x}", clamped);
-                        } else {
-                            return Err(self.error("malformed rgb() call, argument wasn't an int"));
-                        }
+                    match name_of(&args[0]) {
+                        Some(name) => Constant::String(name.to_owned()),
+                        None => return Err(self.error("malformed nameof() call, argument given isn't a var or proc")),
                     }
-                    Constant::String(result)
                 },
                 "defined" if self.defines.is_some() => {
                     let defines = self.defines.unwrap();  // annoying, but keeps the match clean
@@ -779,8 +1049,17 @@ impl<'a> ConstantFolder<'a> {
                 _ => return Err(self.error(format!("non-constant function call: {}", ident))),
             },
             Term::Prefab(prefab) => Constant::Prefab(self.prefab(prefab)?),
+            Term::Ident(ref ident) if ident == "__TYPE__" && self.tree.is_some() => {
+                let path = &self.tree.as_ref().unwrap().graph.node_weight(self.ty).unwrap().path;
+                Constant::Prefab(Pop {
+                    path: path.split('/').filter(|s| !s.is_empty()).map(ToOwned::to_owned).collect(),
+                    vars: Default::default(),
+                })
+            },
             Term::Ident(ident) => self.ident(ident, false)?,
+            Term::GlobalIdent(ident) => self.recursive_lookup(NodeIndex::new(0), &ident, false)?,
             Term::String(v) => Constant::String(v),
+            Term::InterpString(first, parts) => self.interp_string(first, parts)?,
             Term::Resource(v) => Constant::Resource(v),
             Term::Int(v) => Constant::Int(v),
             Term::Float(v) => Constant::from(v),
@@ -799,6 +1078,256 @@ impl<'a> ConstantFolder<'a> {
         }
     }
 
+    fn interp_string(&mut self, first: String, parts: Vec<(Option<Expression>, String)>) -> Result<Constant, DMError> {
+        if has_value_macro(&first) || parts.iter().any(|(_, text)| has_value_macro(text)) {
+            return Err(self.error("non-constant text macro in embedded string"));
+        }
+        let mut result = first;
+        for (expr, text) in parts {
+            let expr = match expr {
+                Some(expr) => expr,
+                None => return Err(self.error("non-constant embedded expression")),
+            };
+            match self.expr(expr, None)? {
+                Constant::Null(_) => {}
+                Constant::String(s) | Constant::Resource(s) => result.push_str(&s),
+                Constant::Int(i) => result.push_str(&crate::lexer::FormatFloat(i as f32).to_string()),
+                Constant::Float(f) => result.push_str(&crate::lexer::FormatFloat(f).to_string()),
+                Constant::Prefab(ref pop) if pop.vars.is_empty() => result.push_str(&FormatTreePath(&pop.path).to_string()),
+                other => return Err(self.error(format!("non-constant embedded value: {}", other))),
+            }
+            result.push_str(&text);
+        }
+        Ok(Constant::String(result))
+    }
+
+    fn rgb(&mut self, args: Vec<Expression>) -> Result<Constant, DMError> {
+        use std::fmt::Write;
+        if args.len() < 3 || args.len() > 5 {
+            return Err(self.error(format!("malformed rgb() call, must have 3 to 5 arguments and instead has {}", args.len())));
+        }
+        let args = self.named_arguments(args, &["r", "g", "b", "a", "space"])?;
+        if argument(&args, "space").map_or(false, Constant::to_bool) {
+            return Err(self.error("non-constant rgb() call, only the RGB color space is supported"));
+        }
+        let mut result = String::with_capacity(9);
+        result.push('#');
+        for &name in &["r", "g", "b", "a"] {
+            match argument(&args, name) {
+                None | Some(Constant::Null(_)) if name == "a" => {}
+                Some(value) => if let Some(i) = value.to_int() {
+                    let clamped = std::cmp::max(::std::cmp::min(i, 255), 0);
+                    let _ = write!(result, "{:02x}", clamped);
+                } else {
+                    return Err(self.error("malformed rgb() call, argument wasn't an int"));
+                },
+                None => return Err(self.error(format!("malformed rgb() call, missing argument {}", name))),
+            }
+        }
+        Ok(Constant::String(result))
+    }
+
+    fn filter(&mut self, args: Vec<Expression>) -> Result<Constant, DMError> {
+        let args = self.named_arguments(args, &[])?;
+        let kind = match argument(&args, "type").and_then(Constant::as_str) {
+            Some(kind) => kind,
+            None => return Err(self.error("malformed filter() call, missing type")),
+        };
+        let valid = match VALID_FILTER_TYPES.get(kind) {
+            Some(valid) => valid,
+            None => return Err(self.error(format!("malformed filter() call, unknown type {:?}", kind))),
+        };
+        for (key, _) in args.iter() {
+            if !(key.eq_string("type") || key.as_str().map_or(false, |key| valid.contains(&key))) {
+                return Err(self.error(format!("malformed filter() call, {} filters have no {}", kind, key)));
+            }
+        }
+        Ok(Constant::Call(ConstFn::Filter, args))
+    }
+
+    fn newlist(&mut self, args: Vec<Expression>) -> Result<Constant, DMError> {
+        let mut list = Vec::new();
+        for each in self.expr_vec(args)? {
+            match each {
+                Constant::Prefab(pop) => list.push((Constant::New { type_: Some(pop), args: None }, None)),
+                other => return Err(self.error(format!("malformed newlist() call, {} is not a type", other))),
+            }
+        }
+        Ok(Constant::List(list))
+    }
+
+    fn matrix(&mut self, args: Vec<Expression>) -> Result<Constant, DMError> {
+        let args = self.expr_vec(args)?;
+        if args.len() == 6 {
+            let mut matrix = [0.; 6];
+            for (each, arg) in matrix.iter_mut().zip(args.iter()) {
+                *each = self.number_arg(arg)?;
+            }
+            return Ok(Constant::matrix(matrix));
+        }
+        let matrix = match args.split_last() {
+            None => MATRIX_IDENTITY,
+            Some((only, [])) => self.matrix_value(only)?,
+            Some((mode, rest)) => {
+                let mode = match mode.to_int() {
+                    Some(mode) => mode,
+                    None => return Err(self.error("malformed matrix() call, last argument must be a MATRIX_* mode")),
+                };
+                match rest.split_first() {
+                    Some((first, operands)) if first.as_matrix().is_some() => {
+                        self.matrix_op(first.as_matrix().unwrap(), mode, operands)?
+                    }
+                    _ => self.matrix_op(MATRIX_IDENTITY, mode, rest)?,
+                }
+            }
+        };
+        Ok(Constant::matrix(matrix))
+    }
+
+    fn matrix_value(&self, constant: &Constant) -> Result<[f32; 6], DMError> {
+        match constant.as_matrix() {
+            Some(matrix) => Ok(matrix),
+            None => Err(self.error(format!("non-constant matrix: {}", constant))),
+        }
+    }
+
+    /// Perform a `MATRIX_*` operation, as `matrix()` and the `/matrix` procs do.
+    fn matrix_op(&self, matrix: [f32; 6], mode: i32, operands: &[Constant]) -> Result<[f32; 6], DMError> {
+        let elementwise = |other: [f32; 6], op: fn(f32, f32) -> f32| {
+            let mut result = matrix;
+            for (each, other) in result.iter_mut().zip(other.iter()) {
+                *each = op(*each, *other);
+            }
+            result
+        };
+        Ok(match (mode & !MATRIX_MODIFY, operands) {
+            (MATRIX_COPY, []) => matrix,
+            (MATRIX_MULTIPLY, [other]) => match other.as_matrix() {
+                Some(other) => matrix_multiply(matrix, other),
+                None => {
+                    let factor = self.number_arg(other)?;
+                    let mut result = matrix;
+                    for each in result.iter_mut() {
+                        *each *= factor;
+                    }
+                    result
+                }
+            },
+            (MATRIX_ADD, [other]) => elementwise(self.matrix_value(other)?, |a, b| a + b),
+            (MATRIX_SUBTRACT, [other]) => elementwise(self.matrix_value(other)?, |a, b| a - b),
+            (MATRIX_INVERT, []) => match matrix_invert(matrix) {
+                Some(inverse) => inverse,
+                None => return Err(self.error("matrix cannot be inverted")),
+            },
+            (MATRIX_ROTATE, [angle]) => matrix_multiply(matrix, matrix_rotation(self.number_arg(angle)?)),
+            (MATRIX_SCALE, [x]) | (MATRIX_TRANSLATE, [x]) => {
+                let x = self.number_arg(x)?;
+                return self.matrix_op(matrix, mode, &[Constant::Float(x), Constant::Float(x)]);
+            }
+            (MATRIX_SCALE, [x, y]) => matrix_multiply(matrix, [self.number_arg(x)?, 0., 0., 0., self.number_arg(y)?, 0.]),
+            (MATRIX_TRANSLATE, [x, y]) => matrix_multiply(matrix, [1., 0., self.number_arg(x)?, 0., 1., self.number_arg(y)?]),
+            _ => return Err(self.error(format!("non-constant matrix operation {} with {} operands", mode, operands.len()))),
+        })
+    }
+
+    fn number_arg(&self, constant: &Constant) -> Result<f32, DMError> {
+        match constant.to_float() {
+            Some(value) => Ok(value),
+            None => Err(self.error(format!("expected a number, found {}", constant))),
+        }
+    }
+
+    fn text_arg<'c>(&self, constant: &'c Constant) -> Result<&'c str, DMError> {
+        match constant.as_str() {
+            // escapes are kept raw, so they would throw off lengths and positions
+            Some(text) if !text.contains('\\') => Ok(text),
+            _ => Err(self.error(format!("expected plain text, found {}", constant))),
+        }
+    }
+
+    /// Builtin procs on text and numbers which can be folded.
+    fn builtin(&mut self, name: &str, args: Vec<Constant>) -> Result<Constant, DMError> {
+        use self::Constant::*;
+
+        Ok(match (name, &args[..]) {
+            ("abs", [Int(i)]) => Int(i.abs()),
+            ("abs", [n]) => number(self.number_arg(n)?.abs()),
+            ("sqrt", [n]) => number(self.number_arg(n)?.sqrt()),
+            // one-argument round() is floor()
+            ("round", [n]) => number(self.number_arg(n)?.floor()),
+            ("round", [n, to]) => {
+                let to = self.number_arg(to)?;
+                number((self.number_arg(n)? / to + 0.5).floor() * to)
+            },
+            ("min", _) | ("max", _) => {
+                let items: Vec<Constant> = match &args[..] {
+                    [List(list)] => list.iter().map(|(key, _)| key.clone()).collect(),
+                    _ => args.clone(),
+                };
+                let mut best: Option<(f32, Constant)> = None;
+                for item in items {
+                    let value = self.number_arg(&item)?;
+                    let better = match best {
+                        Some((current, _)) => if name == "max" { value > current } else { value < current },
+                        None => true,
+                    };
+                    if better {
+                        best = Some((value, item));
+                    }
+                }
+                best.map_or(Null(None), |(_, item)| item)
+            },
+            ("length", [Null(_)]) => Int(0),
+            ("length", [List(list)]) => Int(list.len() as i32),
+            ("length", [text]) => Int(self.text_arg(text)?.len() as i32),
+            ("text2num", [Null(_)]) => Null(None),
+            ("text2num", [n @ Int(_)]) | ("text2num", [n @ Float(_)]) => n.clone(),
+            ("text2num", [text]) => text_to_num(self.text_arg(text)?, 10).map_or(Null(None), number),
+            ("text2num", [text, radix]) => match radix.to_int() {
+                Some(radix) if radix >= 2 && radix <= 36 => text_to_num(self.text_arg(text)?, radix as u32).map_or(Null(None), number),
+                _ => return Err(self.error(format!("text2num() radix must be between 2 and 36, found {}", radix))),
+            },
+            ("num2text", [n]) => String(crate::lexer::FormatFloat(self.number_arg(n)?).to_string()),
+            ("ascii2text", [n]) => match n.to_int().and_then(|n| std::char::from_u32(n as u32)) {
+                Some(ch) => {
+                    let mut text = std::string::String::new();
+                    push_escaped(&mut text, ch);
+                    String(text)
+                }
+                None => return Err(self.error(format!("ascii2text() of invalid character {}", n))),
+            },
+            ("text2ascii", [text]) => Int(self.text_arg(text)?.chars().next().map_or(0, |ch| ch as i32)),
+            ("text2ascii", [text, pos]) => {
+                let text = self.text_arg(text)?;
+                let pos = self.number_arg(pos)? as usize;
+                Int(text.get(pos.saturating_sub(1)..).and_then(|rest| rest.chars().next()).map_or(0, |ch| ch as i32))
+            },
+            ("uppertext", [text]) => String(self.text_arg(text)?.to_uppercase()),
+            ("lowertext", [text]) => String(self.text_arg(text)?.to_lowercase()),
+            ("copytext", [text, rest @ ..]) if rest.len() <= 2 => {
+                let text = self.text_arg(text)?;
+                let len = text.len() as i32;
+                // positions are 1-based, and 0 or negative counts from the end
+                let index = |pos: i32| if pos <= 0 { len + pos } else { pos - 1 };
+                let start = match rest.get(0) {
+                    Some(start) => index(self.number_arg(start)? as i32),
+                    None => 0,
+                };
+                let end = match rest.get(1) {
+                    Some(end) => index(self.number_arg(end)? as i32),
+                    None => len,
+                };
+                let start = start.max(0).min(len) as usize;
+                let end = end.max(0).min(len) as usize;
+                match text.get(start..end.max(start)) {
+                    Some(slice) => String(slice.to_owned()),
+                    None => return Err(self.error("copytext() splits a character")),
+                }
+            },
+            _ => return Err(self.error(format!("malformed {}() call with {} arguments", name, args.len()))),
+        })
+    }
+
     fn prefab(&mut self, prefab: Prefab) -> Result<Pop, DMError> {
         let vars = self.vars(prefab.vars)?;
 
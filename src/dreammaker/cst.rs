@@ -0,0 +1,543 @@
+//! A lossless concrete syntax tree.
+//!
+//! Unlike the main parsing pipeline, which discards whitespace and comments
+//! as it goes, this keeps every byte of the input. Whitespace and comments are
+//! kept as trivia, and the braces and semicolons which `IndentProcessor`
+//! derives from indentation are kept as zero-width synthetic tokens. Printing
+//! a tree reproduces the original source exactly.
+use std::cell::Cell;
+use std::fmt;
+
+use super::{Context, FileId, Location};
+use super::lexer::{Lexer, LocatedToken, Token, Punctuation, from_utf8_or_latin1_borrowed};
+use super::indents::IndentProcessor;
+
+/// A half-open range of byte offsets into a file.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    #[inline]
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Check whether this span contains the given byte offset.
+    #[inline]
+    pub fn contains(&self, offset: usize) -> bool {
+        self.start <= offset && offset < self.end
+    }
+}
+
+/// The kinds of insignificant input kept alongside tokens.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TriviaKind {
+    /// Spaces, tabs, and carriage returns, including indentation.
+    Whitespace,
+    /// A single line feed.
+    Newline,
+    /// A non-documentation line or block comment.
+    Comment,
+    /// A backslash which joins the following line to this one.
+    LineContinuation,
+    /// Bytes the lexer rejected as illegal.
+    Skipped,
+}
+
+/// What a leaf of the tree represents.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LeafKind {
+    /// A token which appears in the source.
+    Token(Token),
+    /// A zero-width token inserted by the indentation processor.
+    Synthetic(Token),
+    /// Whitespace, comments, and other input with no effect on parsing.
+    Trivia(TriviaKind),
+}
+
+/// A single token or piece of trivia along with its exact source text.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Leaf {
+    pub kind: LeafKind,
+    pub location: Location,
+    pub span: Span,
+    pub text: String,
+}
+
+impl Leaf {
+    /// The token this leaf represents to the parser, if any.
+    pub fn token(&self) -> Option<&Token> {
+        match self.kind {
+            LeafKind::Token(ref token) |
+            LeafKind::Synthetic(ref token) => Some(token),
+            LeafKind::Trivia(_) => None,
+        }
+    }
+
+    pub fn is_trivia(&self) -> bool {
+        match self.kind {
+            LeafKind::Trivia(_) => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_synthetic(&self) -> bool {
+        match self.kind {
+            LeafKind::Synthetic(_) => true,
+            _ => false,
+        }
+    }
+}
+
+/// The kinds of interior nodes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NodeKind {
+    /// An entire file.
+    File,
+    /// A sequence of tokens terminated by a semicolon, possibly containing
+    /// blocks.
+    Statement,
+    /// A braced block, whether the braces are real or synthetic.
+    Block,
+    /// A parenthesized group.
+    Parens,
+    /// A bracketed group.
+    Brackets,
+}
+
+/// A child of a node.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Element {
+    Node(Node),
+    Leaf(Leaf),
+}
+
+impl Element {
+    pub fn span(&self) -> Span {
+        match *self {
+            Element::Node(ref node) => node.span,
+            Element::Leaf(ref leaf) => leaf.span,
+        }
+    }
+
+    pub fn location(&self) -> Location {
+        match *self {
+            Element::Node(ref node) => node.location,
+            Element::Leaf(ref leaf) => leaf.location,
+        }
+    }
+}
+
+impl fmt::Display for Element {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Element::Node(ref node) => fmt::Display::fmt(node, f),
+            Element::Leaf(ref leaf) => f.write_str(&leaf.text),
+        }
+    }
+}
+
+/// An interior node of the tree, covering all of its children.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Node {
+    pub kind: NodeKind,
+    pub location: Location,
+    pub span: Span,
+    pub children: Vec<Element>,
+}
+
+impl Node {
+    fn new(kind: NodeKind, location: Location, start: usize) -> Node {
+        Node {
+            kind,
+            location,
+            span: Span::new(start, start),
+            children: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, element: Element) {
+        if self.children.is_empty() {
+            self.location = element.location();
+            self.span.start = element.span().start;
+        }
+        self.span.end = element.span().end;
+        self.children.push(element);
+    }
+
+    /// Iterate over every leaf beneath this node, in source order.
+    pub fn leaves(&self) -> Leaves {
+        Leaves { stack: vec![self.children.iter()] }
+    }
+
+    /// Iterate over the leaves which are significant to the parser.
+    pub fn tokens<'a>(&'a self) -> impl Iterator<Item=&'a Leaf> + 'a {
+        self.leaves().filter(|leaf| !leaf.is_trivia())
+    }
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for leaf in self.leaves() {
+            f.write_str(&leaf.text)?;
+        }
+        Ok(())
+    }
+}
+
+/// An iterator over the leaves of a node.
+pub struct Leaves<'a> {
+    stack: Vec<std::slice::Iter<'a, Element>>,
+}
+
+impl<'a> Iterator for Leaves<'a> {
+    type Item = &'a Leaf;
+
+    fn next(&mut self) -> Option<&'a Leaf> {
+        loop {
+            match self.stack.last_mut()?.next() {
+                Some(Element::Leaf(leaf)) => return Some(leaf),
+                Some(Element::Node(node)) => self.stack.push(node.children.iter()),
+                None => { self.stack.pop(); }
+            }
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Construction
+
+/// Parse a file's source into a lossless tree.
+///
+/// Errors are registered to the context as usual, but the resulting tree
+/// always prints back to exactly `source`.
+pub fn parse(context: &Context, file: FileId, source: &[u8]) -> Node {
+    let lines = LineMap::new(file, source);
+
+    // Lex, remembering where each token came from.
+    let mut lexer = Lexer::new(context, file, source.iter().map(|&b| Ok(b)));
+    let mut raw: Vec<(LocatedToken, Span)> = Vec::new();
+    while let Some(token) = Iterator::next(&mut lexer) {
+        raw.push((token, lexer.last_span()));
+    }
+
+    // Run the tokens through the indentation processor, noting which input
+    // token was most recently consumed whenever it produces output. Output
+    // is produced before more input is consumed, so every token it inserts
+    // is attributed to the input token it precedes, or to none at the end.
+    let consumed = Cell::new(None);
+    let mut index = 0;
+    let inner = std::iter::from_fn(|| {
+        let next = raw.get(index);
+        consumed.set(next.map(|_| index));
+        index += 1;
+        next.map(|(token, _)| token.clone())
+    });
+    let mut inserted: Vec<Vec<Token>> = vec![Vec::new(); raw.len()];
+    let mut trailing = Vec::new();
+    let mut produced: Vec<(Option<usize>, Token)> = Vec::new();
+    for token in IndentProcessor::new(context, inner) {
+        produced.push((consumed.get(), token.token));
+    }
+    // Every non-whitespace input token is passed through as the last output
+    // attributed to it; the rest were inserted.
+    let mut i = 0;
+    while i < produced.len() {
+        let attributed = produced[i].0;
+        let mut j = i;
+        while j < produced.len() && produced[j].0 == attributed {
+            j += 1;
+        }
+        match attributed {
+            Some(idx) => {
+                let passthrough = !raw[idx].0.token.is_whitespace();
+                let end = if passthrough { j - 1 } else { j };
+                inserted[idx].extend(produced[i..end].iter().map(|(_, t)| t.clone()));
+            }
+            None => trailing.extend(produced[i..j].iter().map(|(_, t)| t.clone())),
+        }
+        i = j;
+    }
+
+    // Build the tree, filling gaps between tokens with trivia.
+    let mut builder = Builder::new(file);
+    let mut pos = 0;
+    for (idx, (token, span)) in raw.iter().enumerate() {
+        if span.start > pos {
+            builder.trivia(&lines, source, Span::new(pos, span.start));
+        }
+        for synthetic in inserted[idx].drain(..) {
+            builder.leaf(Leaf {
+                kind: LeafKind::Synthetic(synthetic),
+                location: token.location,
+                span: Span::new(span.start, span.start),
+                text: String::new(),
+            });
+        }
+        if span.is_empty() {
+            // The lexer's final newline does not appear in the source.
+            continue;
+        }
+        let text = from_utf8_or_latin1_borrowed(&source[span.start..span.end]).into_owned();
+        let kind = match token.token {
+            Token::Punct(Punctuation::Newline) => LeafKind::Trivia(TriviaKind::Newline),
+            Token::Punct(Punctuation::Tab) |
+            Token::Punct(Punctuation::Space) => LeafKind::Trivia(TriviaKind::Whitespace),
+            ref other => LeafKind::Token(other.clone()),
+        };
+        builder.leaf(Leaf { kind, location: token.location, span: *span, text });
+        pos = span.end;
+    }
+    if source.len() > pos {
+        builder.trivia(&lines, source, Span::new(pos, source.len()));
+    }
+    for synthetic in trailing {
+        builder.leaf(Leaf {
+            kind: LeafKind::Synthetic(synthetic),
+            location: lines.location(source.len()),
+            span: Span::new(source.len(), source.len()),
+            text: String::new(),
+        });
+    }
+    builder.finish()
+}
+
+/// Maps byte offsets to locations the same way the lexer counts them.
+struct LineMap {
+    file: FileId,
+    line_starts: Vec<usize>,
+}
+
+impl LineMap {
+    fn new(file: FileId, source: &[u8]) -> LineMap {
+        let mut line_starts = vec![0];
+        line_starts.extend(source.iter().enumerate().filter(|&(_, &b)| b == b'\n').map(|(i, _)| i + 1));
+        LineMap { file, line_starts }
+    }
+
+    fn location(&self, offset: usize) -> Location {
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next) => next - 1,
+        };
+        Location {
+            file: self.file,
+            line: line as u32 + 1,
+            column: (offset - self.line_starts[line]) as u16 + 1,
+        }
+    }
+}
+
+/// Split a run of skipped input into individual pieces of trivia.
+fn split_trivia(source: &[u8], span: Span) -> Vec<(TriviaKind, Span)> {
+    let mut output: Vec<(TriviaKind, Span)> = Vec::new();
+    let mut i = span.start;
+    while i < span.end {
+        let start = i;
+        let kind = match source[i] {
+            b' ' | b'\t' | b'\r' => {
+                while i < span.end && (source[i] == b' ' || source[i] == b'\t' || source[i] == b'\r') {
+                    i += 1;
+                }
+                TriviaKind::Whitespace
+            }
+            b'\n' => {
+                i += 1;
+                TriviaKind::Newline
+            }
+            b'\\' => {
+                i += 1;
+                TriviaKind::LineContinuation
+            }
+            b'/' if source.get(i + 1) == Some(&b'/') => {
+                // Line comments may be continued with a backslash.
+                let mut backslash = false;
+                i += 2;
+                while i < span.end {
+                    match source[i] {
+                        b'\r' => {}
+                        b'\n' if !backslash => break,
+                        b'\\' => backslash = !backslash,
+                        _ => backslash = false,
+                    }
+                    i += 1;
+                }
+                TriviaKind::Comment
+            }
+            b'/' if source.get(i + 1) == Some(&b'*') => {
+                let mut depth = 0;
+                while i < span.end {
+                    if source[i..].starts_with(b"/*") {
+                        depth += 1;
+                        i += 2;
+                    } else if source[i..].starts_with(b"*/") {
+                        depth -= 1;
+                        i += 2;
+                        if depth == 0 {
+                            break;
+                        }
+                    } else {
+                        i += 1;
+                    }
+                }
+                i = i.min(span.end);
+                TriviaKind::Comment
+            }
+            _ => {
+                i += 1;
+                TriviaKind::Skipped
+            }
+        };
+        match output.last_mut() {
+            Some((TriviaKind::Skipped, prev)) if kind == TriviaKind::Skipped => prev.end = i,
+            _ => output.push((kind, Span::new(start, i))),
+        }
+    }
+    output
+}
+
+struct Builder {
+    stack: Vec<Node>,
+    // Whether the innermost statement just had a block closed inside it.
+    after_block: bool,
+}
+
+impl Builder {
+    fn new(file: FileId) -> Builder {
+        let location = Location { file, line: 1, column: 1 };
+        Builder {
+            stack: vec![Node::new(NodeKind::File, location, 0)],
+            after_block: false,
+        }
+    }
+
+    fn top(&mut self) -> &mut Node {
+        self.stack.last_mut().expect("cst builder stack empty")
+    }
+
+    fn open(&mut self, kind: NodeKind, location: Location, start: usize) {
+        self.stack.push(Node::new(kind, location, start));
+    }
+
+    fn close(&mut self) {
+        let node = self.stack.pop().expect("cst builder stack empty");
+        self.top().push(Element::Node(node));
+    }
+
+    fn close_statement(&mut self) {
+        if self.top().kind == NodeKind::Statement {
+            self.close();
+        }
+        self.after_block = false;
+    }
+
+    fn trivia(&mut self, lines: &LineMap, source: &[u8], span: Span) {
+        for (kind, span) in split_trivia(source, span) {
+            self.leaf(Leaf {
+                kind: LeafKind::Trivia(kind),
+                location: lines.location(span.start),
+                span,
+                text: from_utf8_or_latin1_borrowed(&source[span.start..span.end]).into_owned(),
+            });
+        }
+    }
+
+    fn leaf(&mut self, leaf: Leaf) {
+        use self::Punctuation::*;
+
+        let punct = match leaf.token() {
+            None => {
+                self.top().push(Element::Leaf(leaf));
+                return;
+            }
+            Some(Token::Punct(p)) => Some(*p),
+            Some(_) => None,
+        };
+
+        // A statement ends after a block unless the block is followed by
+        // a keyword which continues it, such as `else`.
+        if self.after_block {
+            let continues = match leaf.token() {
+                Some(Token::Ident(ident, _)) => ident == "else" || ident == "catch" || ident == "while",
+                Some(Token::Punct(Semicolon)) => true,
+                _ => false,
+            };
+            self.after_block = false;
+            if !continues {
+                self.close_statement();
+            }
+        }
+
+        match punct {
+            Some(Semicolon) => {
+                self.top().push(Element::Leaf(leaf));
+                self.close_statement();
+                return;
+            }
+            Some(RBrace) => {
+                if let Some(block) = self.stack.iter().rposition(|n| n.kind == NodeKind::Block) {
+                    while self.stack.len() > block + 1 {
+                        self.close();
+                    }
+                    self.top().push(Element::Leaf(leaf));
+                    self.close();
+                    self.after_block = self.top().kind == NodeKind::Statement;
+                } else {
+                    self.top().push(Element::Leaf(leaf));
+                }
+                return;
+            }
+            Some(RParen) | Some(RBracket) => {
+                let kind = if punct == Some(RParen) { NodeKind::Parens } else { NodeKind::Brackets };
+                self.top().push(Element::Leaf(leaf));
+                if self.top().kind == kind {
+                    self.close();
+                }
+                return;
+            }
+            _ => {}
+        }
+
+        // Anything else directly inside a file or block starts a statement.
+        let kind = self.top().kind;
+        if kind == NodeKind::File || kind == NodeKind::Block {
+            self.open(NodeKind::Statement, leaf.location, leaf.span.start);
+        }
+
+        match punct {
+            Some(LBrace) => {
+                self.open(NodeKind::Block, leaf.location, leaf.span.start);
+                self.top().push(Element::Leaf(leaf));
+            }
+            Some(LParen) => {
+                self.open(NodeKind::Parens, leaf.location, leaf.span.start);
+                self.top().push(Element::Leaf(leaf));
+            }
+            Some(LBracket) | Some(SafeLBracket) => {
+                self.open(NodeKind::Brackets, leaf.location, leaf.span.start);
+                self.top().push(Element::Leaf(leaf));
+            }
+            _ => self.top().push(Element::Leaf(leaf)),
+        }
+    }
+
+    fn finish(mut self) -> Node {
+        while self.stack.len() > 1 {
+            self.close();
+        }
+        self.stack.pop().expect("cst builder stack empty")
+    }
+}
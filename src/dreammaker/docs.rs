@@ -2,8 +2,10 @@
 
 use std::fmt;
 
+use serde::{Serialize, Deserialize};
+
 /// A collection of documentation comments targeting the same item.
-#[derive(Default, Clone, Debug, PartialEq)]
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct DocCollection {
     elems: Vec<DocComment>,
 }
@@ -59,7 +61,7 @@ impl DocCollection {
 }
 
 /// A documentation comment.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct DocComment {
     pub kind: CommentKind,
     pub target: DocTarget,
@@ -94,7 +96,7 @@ impl fmt::Display for DocComment {
 }
 
 /// The possible documentation comment kinds.
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub enum CommentKind {
     /// A block `/** */` comment.
     Block,
@@ -196,7 +198,7 @@ fn is_empty(text: &str, ignore_char: char) -> bool {
 }
 
 /// The possible items that a documentation comment may target.
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub enum DocTarget {
     /// Starting with `*` or `/`, referring to the following item.
     FollowingItem,
@@ -2,15 +2,21 @@
 
 use std::{fmt, error, io};
 use std::path::{PathBuf, Path};
-use std::cell::{RefCell, Ref, RefMut};
-use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::borrow::Cow;
+use std::cmp::Ordering;
+
+use serde::{Serialize, Deserialize, Serializer, Deserializer};
 
 use termcolor::{ColorSpec, Color};
 
-use crate::config::Config;
+use crate::config::{Config, WarningLevel};
+use crate::pragma::Pragmas;
+use crate::suppression::Suppressions;
 
 /// An identifier referring to a loaded file.
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
 pub struct FileId(u16);
 
 const FILEID_BUILTINS: FileId = FileId(0x0000);
@@ -25,45 +31,88 @@ impl Default for FileId {
 }
 
 /// A registry mapping between file names and file IDs.
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default)]
 pub struct FileList {
+    inner: RwLock<FileListInner>,
+}
+
+#[derive(Debug, Default, Clone)]
+struct FileListInner {
     /// The list of loaded files.
-    files: RefCell<Vec<PathBuf>>,
+    files: Vec<PathBuf>,
     /// Reverse mapping from paths to file numbers.
-    reverse_files: RefCell<HashMap<PathBuf, FileId>>,
+    reverse_files: HashMap<PathBuf, FileId>,
 }
 
 /// A diagnostics context, tracking loaded files and any observed errors.
+///
+/// A `Context` may be shared between threads. Errors registered concurrently
+/// are kept in the order they arrive; call `sort_errors` to put them in a
+/// deterministic order.
 #[derive(Debug, Default)]
 pub struct Context {
     files: FileList,
     /// A list of errors, warnings, and other diagnostics generated.
-    errors: RefCell<Vec<DMError>>,
+    errors: Mutex<Vec<DMError>>,
     /// Warning config
-    config: RefCell<Config>,
+    config: RwLock<Config>,
     print_severity: Option<Severity>,
+    /// Warning levels set by `#pragma` directives.
+    pragmas: RwLock<Pragmas>,
+    /// Errortypes which are off by default and turned on only by `#pragma`.
+    pragma_opt_ins: RwLock<HashSet<String>>,
+    /// Errortypes which are off by default and were not turned on, so their
+    /// checks never ran.
+    skipped_opt_ins: RwLock<HashSet<String>>,
+    /// Diagnostics suppressed by comments.
+    suppressions: RwLock<Suppressions>,
+    /// The macros whose expansion produced the tokens at each location, as
+    /// recorded when `Preprocessor::enable_macro_provenance` is used.
+    expansions: RwLock<BTreeMap<Location, Vec<MacroExpansion>>>,
+}
+
+/// A macro whose expansion produced a token, as recorded when
+/// `Preprocessor::enable_macro_provenance` is used.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MacroExpansion {
+    /// The name of the macro.
+    pub name: String,
+    /// The location of the macro's `#define`.
+    pub definition: Location,
+}
+
+impl Clone for FileList {
+    fn clone(&self) -> FileList {
+        FileList {
+            inner: RwLock::new(read(&self.inner).clone()),
+        }
+    }
 }
 
 impl FileList {
     /// Add a new file to the context and return its index.
     pub fn register(&self, path: &Path) -> FileId {
-        if let Some(id) = self.reverse_files.borrow().get(path).cloned() {
+        if let Some(id) = self.get_id(path) {
+            return id;
+        }
+        let mut inner = write(&self.inner);
+        // another thread may have registered it in the meantime
+        if let Some(id) = inner.reverse_files.get(path).cloned() {
             return id;
         }
-        let mut files = self.files.borrow_mut();
-        if files.len() > FILEID_MAX.0 as usize {
+        if inner.files.len() > FILEID_MAX.0 as usize {
             panic!("file limit of {} exceeded", FILEID_MAX.0);
         }
-        let len = files.len() as u16;
-        files.push(path.to_owned());
+        let len = inner.files.len() as u16;
+        inner.files.push(path.to_owned());
         let id = FileId(len + FILEID_MIN.0);
-        self.reverse_files.borrow_mut().insert(path.to_owned(), id);
+        inner.reverse_files.insert(path.to_owned(), id);
         id
     }
 
     /// Look up a file's ID by its path, without inserting it.
     pub fn get_id(&self, path: &Path) -> Option<FileId> {
-        self.reverse_files.borrow().get(path).cloned()
+        read(&self.inner).reverse_files.get(path).cloned()
     }
 
     /// Look up a file path by its index returned from `register_file`.
@@ -72,13 +121,18 @@ impl FileList {
             return "(builtins)".into();
         }
         let idx = (file.0 - FILEID_MIN.0) as usize;
-        let files = self.files.borrow();
+        let files = &read(&self.inner).files;
         if idx > files.len() {
             "(unknown)".into()
         } else {
             files[idx].to_owned()
         }
     }
+
+    /// Get the paths of all registered files, in order of registration.
+    pub fn paths(&self) -> Vec<PathBuf> {
+        read(&self.inner).files.clone()
+    }
 }
 
 impl Context {
@@ -110,7 +164,7 @@ impl Context {
 
     pub fn force_config(&self, toml: &Path) {
         match Config::read_toml(toml) {
-            Ok(config) => *self.config.borrow_mut() = config,
+            Ok(config) => *write(&self.config) = config,
             Err(io_error) => {
                 let file = self.register_file(toml);
                 let (line, column) = io_error.line_col().unwrap_or((1, 1));
@@ -128,8 +182,8 @@ impl Context {
         }
     }
 
-    pub fn config(&self) -> Ref<Config> {
-        self.config.borrow()
+    pub fn config(&self) -> RwLockReadGuard<Config> {
+        read(&self.config)
     }
 
     /// Set a severity at and above which errors will be printed immediately.
@@ -142,13 +196,24 @@ impl Context {
 
     /// Push an error or other diagnostic to the context.
     pub fn register_error(&self, error: DMError) {
-        guard!(let Some(error) = self.config.borrow().set_configured_severity(error) else {
+        // ignore errors suppressed by a comment, before any configuration
+        // applies, so that the comment counts as used either way
+        if read(&self.suppressions).suppresses(&error) {
+            return
+        }
+        let error = match self.pragma_level(&error) {
+            Some(level) if !self.config().overrides(&error) => level.apply(error),
+            None if self.needs_pragma(&error) => return,
+            _ => self.config().set_configured_severity(error),
+        };
+        guard!(let Some(error) = error else {
             return // errortype is disabled
         });
         // ignore errors with severity above configured level
-        if !self.config.borrow().registerable_error(&error) {
+        if !self.config().registerable_error(&error) {
             return
         }
+        let error = self.with_recorded_expansion(error);
         if let Some(print_severity) = self.print_severity {
             if error.severity() <= print_severity {
                 let stderr = termcolor::StandardStream::stderr(termcolor::ColorChoice::Auto);
@@ -156,18 +221,198 @@ impl Context {
                     .expect("error writing to stderr");
             }
         }
-        self.errors.borrow_mut().push(error);
+        lock(&self.errors).push(error);
+    }
+
+    /// Whether an error's errortype is off by default and turned on only by
+    /// `#pragma`, so that it is kept only where a `#pragma` reaches.
+    fn needs_pragma(&self, error: &DMError) -> bool {
+        error.errortype().map_or(false, |errortype| read(&self.pragma_opt_ins).contains(errortype))
+    }
+
+    /// Note the macros an error came from, if its location is inside an
+    /// expansion.
+    fn with_recorded_expansion(&self, error: DMError) -> DMError {
+        let chain = read(&self.expansions).get(&error.location).cloned();
+        match chain {
+            Some(chain) => error.with_expansion_notes(&chain),
+            None => error,
+        }
+    }
+
+    fn pragma_level(&self, error: &DMError) -> Option<WarningLevel> {
+        let pragmas = read(&self.pragmas);
+        if pragmas.is_empty() {
+            return None;
+        }
+        pragmas.level(error.errortype?, error.location)
+    }
+
+    /// A copy of the diagnostics generated so far.
+    pub fn errors(&self) -> Vec<DMError> {
+        lock(&self.errors).clone()
     }
 
-    /// Access the list of diagnostics generated so far.
-    pub fn errors(&self) -> Ref<[DMError]> {
-        Ref::map(self.errors.borrow(), |x| &**x)
+    /// The number of diagnostics generated so far.
+    pub fn error_count(&self) -> usize {
+        lock(&self.errors).len()
     }
 
     /// Mutably access the diagnostics list. Dangerous.
     #[doc(hidden)]
-    pub fn errors_mut(&self) -> RefMut<Vec<DMError>> {
-        self.errors.borrow_mut()
+    pub fn errors_mut(&self) -> MutexGuard<Vec<DMError>> {
+        lock(&self.errors)
+    }
+
+    /// Sort the diagnostics generated so far by location.
+    ///
+    /// Errors registered from several threads arrive in an unpredictable
+    /// order; sorting them makes the output reproducible.
+    pub fn sort_errors(&self) {
+        self.sort_errors_from(0);
+    }
+
+    /// Sort only the diagnostics registered after the first `start`.
+    pub(crate) fn sort_errors_from(&self, start: usize) {
+        let mut errors = lock(&self.errors);
+        if start < errors.len() {
+            errors[start..].sort_by(DMError::sort_order);
+        }
+    }
+
+    // ------------------------------------------------------------------------
+    // Pragmas
+
+    /// Record a `#pragma` setting a BYOND warning's level.
+    pub(crate) fn register_pragma(&self, location: Location, warning: &str, level: WarningLevel) {
+        write(&self.pragmas).set(location, warning, level);
+    }
+
+    /// Record where a file was included from, so that pragmas can be ordered.
+    pub(crate) fn register_include_site(&self, file: FileId, location: Location) {
+        write(&self.pragmas).include_site(file, location);
+    }
+
+    /// Whether an errortype which is off by default is turned on, by the
+    /// configuration or by a `#pragma` anywhere. One turned on only by
+    /// `#pragma` is reported only where the `#pragma` applies.
+    pub fn opted_in(&self, errortype: &str) -> bool {
+        if self.config().opted_in(errortype) {
+            return true;
+        }
+        if read(&self.pragmas).enables(errortype) {
+            write(&self.pragma_opt_ins).insert(errortype.to_owned());
+            return true;
+        }
+        if !read(&self.skipped_opt_ins).contains(errortype) {
+            write(&self.skipped_opt_ins).insert(errortype.to_owned());
+        }
+        false
+    }
+
+    /// Access the warning levels set by `#pragma` directives.
+    pub fn pragmas(&self) -> RwLockReadGuard<Pragmas> {
+        read(&self.pragmas)
+    }
+
+    pub(crate) fn set_pragmas(&self, pragmas: Pragmas) {
+        *write(&self.pragmas) = pragmas;
+    }
+
+    // ------------------------------------------------------------------------
+    // Suppressions
+
+    /// Record a suppression comment, returning its index.
+    pub(crate) fn register_suppression(&self, location: Location, errortypes: Vec<String>) -> usize {
+        write(&self.suppressions).add(location, errortypes)
+    }
+
+    /// Set the first line a suppression applies to.
+    ///
+    /// Matching diagnostics registered earlier on that line, such as those
+    /// raised before a comment at the end of the line was reached, are
+    /// removed.
+    pub(crate) fn begin_suppression(&self, idx: usize, file: FileId, line: u32) {
+        let mut suppressions = write(&self.suppressions);
+        suppressions.begin(idx, line);
+        // Only the diagnostics registered since the line began can match.
+        let mut errors = lock(&self.errors);
+        let start = errors.iter()
+            .rposition(|error| error.location().file != file || error.location().line < line)
+            .map_or(0, |i| i + 1);
+        let kept: Vec<DMError> = errors.drain(start..).filter(|error| !suppressions.suppresses(error)).collect();
+        errors.extend(kept);
+    }
+
+    /// Set the last line a suppression applies to.
+    pub(crate) fn end_suppression(&self, idx: usize, line: u32) {
+        write(&self.suppressions).end(idx, line);
+    }
+
+    /// Access the suppression comments seen so far.
+    pub fn suppressions(&self) -> RwLockReadGuard<Suppressions> {
+        read(&self.suppressions)
+    }
+
+    pub(crate) fn set_suppressions(&self, suppressions: Suppressions) {
+        *write(&self.suppressions) = suppressions;
+    }
+
+    /// Drop the diagnostics in a file, along with its suppression comments
+    /// and `#pragma` settings, before the file is parsed again.
+    pub fn clear_file(&self, file: FileId) {
+        lock(&self.errors).retain(|error| error.location().file != file);
+        self.clear_file_directives(file);
+    }
+
+    /// Drop a file's suppression comments, `#pragma` settings and recorded
+    /// macro expansions.
+    pub(crate) fn clear_file_directives(&self, file: FileId) {
+        write(&self.suppressions).clear_file(file);
+        write(&self.pragmas).clear_file(file);
+        write(&self.expansions).retain(|location, _| location.file != file);
+    }
+
+    // ------------------------------------------------------------------------
+    // Macro provenance
+
+    /// Record the macros, outermost first, whose expansion produced the
+    /// tokens at a location. The longest chain seen at a location is kept.
+    pub(crate) fn record_expansion(&self, location: Location, chain: &[MacroExpansion]) {
+        let mut expansions = write(&self.expansions);
+        let entry = expansions.entry(location).or_insert_with(Vec::new);
+        if chain.len() > entry.len() {
+            *entry = chain.to_vec();
+        }
+    }
+
+    /// Access the macro expansions recorded so far.
+    pub fn expansions(&self) -> RwLockReadGuard<BTreeMap<Location, Vec<MacroExpansion>>> {
+        read(&self.expansions)
+    }
+
+    pub(crate) fn set_expansions(&self, expansions: BTreeMap<Location, Vec<MacroExpansion>>) {
+        *write(&self.expansions) = expansions;
+    }
+
+    /// Warn about each errortype named by a suppression comment which has not
+    /// matched any diagnostic. Call once all diagnostics are registered.
+    ///
+    /// Errortypes whose checks were skipped because they are off are not
+    /// warned about, since nothing could have matched them.
+    pub fn register_unused_suppressions(&self) {
+        let skipped = read(&self.skipped_opt_ins);
+        let unused: Vec<(Location, String)> = read(&self.suppressions).unused().into_iter()
+            .filter(|(_, errortype)| !skipped.contains(*errortype))
+            .map(|(location, errortype)| (location, errortype.to_owned()))
+            .collect();
+        drop(skipped);
+        for (location, errortype) in unused {
+            DMError::new(location, format!("unused suppression of {}", errortype))
+                .set_severity(Severity::Warning)
+                .with_errortype("unused_suppression")
+                .register(self);
+        }
     }
 
     /// Pretty-print a `DMError` to the given output.
@@ -241,11 +486,26 @@ impl Context {
     }
 }
 
+// A panic while registering an error shouldn't take the whole context with
+// it, so lock poisoning is ignored.
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<T> {
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn read<T>(lock: &RwLock<T>) -> RwLockReadGuard<T> {
+    lock.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<T> {
+    lock.write().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
 // ----------------------------------------------------------------------------
 // Location handling
 
 /// File, line, and column information for an error.
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Default)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Default, Serialize, Deserialize)]
 pub struct Location {
     /// The index into the file table.
     pub file: FileId,
@@ -308,7 +568,7 @@ pub(crate) trait HasLocation {
 // Error handling
 
 /// The possible diagnostic severities available.
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
 pub enum Severity {
     Error = 1,
     Warning = 2,
@@ -347,7 +607,7 @@ impl fmt::Display for Severity {
 }
 
 /// A component which generated a diagnostic, when separation is desired.
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum Component {
     Unspecified,
     DreamChecker,
@@ -391,7 +651,7 @@ pub struct DMError {
 }
 
 /// An additional note attached to an error, at some other location.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DiagnosticNote {
     location: Location,
     description: String,
@@ -438,6 +698,17 @@ impl DMError {
         self
     }
 
+    /// Note which macros, given outermost first, the error came from.
+    pub fn with_expansion_notes(mut self, expansions: &[MacroExpansion]) -> DMError {
+        for expansion in expansions.iter().rev() {
+            let description = format!("expanded from macro {:?}", expansion.name);
+            if !self.notes.iter().any(|note| note.location == expansion.definition && note.description == description) {
+                self.add_note(expansion.definition, description);
+            }
+        }
+        self
+    }
+
     pub fn with_component(mut self, component: Component) -> DMError {
         self.component = component;
         self
@@ -487,6 +758,13 @@ impl DMError {
     pub fn notes(&self) -> &[DiagnosticNote] {
         &self.notes
     }
+
+    /// Compare two errors by location, then severity, then description.
+    pub fn sort_order(&self, other: &DMError) -> Ordering {
+        self.location.cmp(&other.location)
+            .then(self.severity.cmp(&other.severity))
+            .then_with(|| self.description.cmp(&other.description))
+    }
 }
 
 impl fmt::Display for DMError {
@@ -519,6 +797,60 @@ impl Clone for DMError {
     }
 }
 
+/// The serialized form of a `DMError`, which loses its cause.
+#[derive(Serialize, Deserialize)]
+struct SerializedError<'a> {
+    location: Location,
+    severity: Severity,
+    component: Component,
+    description: Cow<'a, str>,
+    notes: Cow<'a, [DiagnosticNote]>,
+    errortype: Option<Cow<'a, str>>,
+}
+
+impl Serialize for DMError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerializedError {
+            location: self.location,
+            severity: self.severity,
+            component: self.component,
+            description: Cow::Borrowed(&self.description),
+            notes: Cow::Borrowed(&self.notes),
+            errortype: self.errortype.map(Cow::Borrowed),
+        }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for DMError {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<DMError, D::Error> {
+        let error = SerializedError::deserialize(deserializer)?;
+        Ok(DMError {
+            location: error.location,
+            severity: error.severity,
+            component: error.component,
+            description: error.description.into_owned(),
+            notes: error.notes.into_owned(),
+            cause: None,
+            errortype: error.errortype.map(|name| intern_errortype(&name)),
+        })
+    }
+}
+
+/// Errortypes are static strings, but only a handful of distinct ones exist,
+/// so those which are deserialized are leaked once each.
+fn intern_errortype(name: &str) -> &'static str {
+    // Shared by every thread, so that each name is leaked only once.
+    static INTERNED: Mutex<Option<HashSet<&'static str>>> = Mutex::new(None);
+    let mut interned = lock(&INTERNED);
+    let interned = interned.get_or_insert_with(HashSet::new);
+    if let Some(&existing) = interned.get(name) {
+        return existing;
+    }
+    let leaked: &'static str = Box::leak(name.to_owned().into_boxed_str());
+    interned.insert(leaked);
+    leaked
+}
+
 impl DiagnosticNote {
     /// Get the location in the code at which this error was observed.
     pub fn location(&self) -> Location {
@@ -0,0 +1,267 @@
+//! A source code formatter built on the lossless syntax tree.
+//!
+//! Formatting only ever changes whitespace. Comments, strings, and
+//! preprocessor directives (including `#define` bodies) are kept exactly as
+//! written, and the result is checked against the original to be sure the
+//! token stream seen by the parser is unchanged.
+use super::{Context, DMError, FileId, Location};
+use super::config::Format;
+use super::cst::{self, Leaf, LeafKind, Node, NodeKind, Element, TriviaKind};
+use super::lexer::{Lexer, Token, Punctuation};
+use super::indents::IndentProcessor;
+
+/// Keywords which keep their original spacing before a `(`.
+const KEYWORDS: &[&str] = &[
+    "as", "catch", "del", "do", "else", "for", "goto", "if", "new", "return",
+    "set", "spawn", "step", "switch", "throw", "to", "try", "var", "while",
+];
+
+/// Keywords which begin a type path and may be directly followed by `/`.
+const PATH_KEYWORDS: &[&str] = &["var", "proc", "verb", "static", "global", "tmp", "const"];
+
+/// Reformat a file's source according to the given options.
+///
+/// Errors in the file are registered to the context, but do not prevent
+/// formatting. An error is returned only if formatting would change how the
+/// file is parsed, in which case the source should be left as-is.
+pub fn format(context: &Context, file: FileId, source: &str, options: &Format) -> Result<String, DMError> {
+    let tree = cst::parse(context, file, source.as_bytes());
+    let newline = if source.contains("\r\n") { "\r\n" } else { "\n" };
+    let indent_unit = if options.indent_spaces == 0 {
+        "\t".to_owned()
+    } else {
+        " ".repeat(options.indent_spaces)
+    };
+
+    let mut items = Vec::new();
+    flatten(&tree, 0, 0, &mut items);
+    let lines = split_lines(&items);
+
+    let mut output = String::with_capacity(source.len());
+    let mut blank_lines = 0;
+    let mut wrote_any = false;
+    let mut continued = false;
+    for (i, line) in lines.iter().enumerate() {
+        let significant: Vec<&Item> = line.iter().filter(|item| !is_whitespace(item.leaf)).collect();
+
+        if significant.is_empty() && !continued {
+            blank_lines += 1;
+            continue;
+        }
+        if wrote_any {
+            for _ in 0..blank_lines.min(options.max_blank_lines) {
+                output.push_str(newline);
+            }
+        }
+        blank_lines = 0;
+        wrote_any = true;
+
+        let verbatim = continued || is_directive(&significant);
+        continued = match significant.last() {
+            Some(item) => item.leaf.kind == LeafKind::Trivia(TriviaKind::LineContinuation),
+            None => false,
+        };
+
+        if verbatim || continued {
+            let end = line.iter().rposition(|item| !is_whitespace(item.leaf)).map_or(0, |i| i + 1);
+            for item in &line[..end] {
+                output.push_str(&item.leaf.text);
+            }
+        } else {
+            // Comment-only lines are indented to match the following code.
+            let indent = if significant.iter().all(|item| item.leaf.is_trivia()) {
+                lines[i + 1..].iter()
+                    .filter_map(|line| line.iter().find(|item| !item.leaf.is_trivia()))
+                    .next()
+                    .map_or(0, indent_of)
+            } else {
+                indent_of(significant[0])
+            };
+            for _ in 0..indent {
+                output.push_str(&indent_unit);
+            }
+            format_line(&mut output, line, options);
+        }
+        output.push_str(newline);
+    }
+
+    check_unchanged(file, source, &output)?;
+    Ok(output)
+}
+
+// ----------------------------------------------------------------------------
+// Lines
+
+/// A leaf along with the block and parenthesis depth it appears at.
+struct Item<'a> {
+    leaf: &'a Leaf,
+    blocks: usize,
+    parens: usize,
+}
+
+fn flatten<'a>(node: &'a Node, blocks: usize, parens: usize, output: &mut Vec<Item<'a>>) {
+    let (blocks, parens) = match node.kind {
+        NodeKind::Block => (blocks + 1, parens),
+        // Only parentheses suspend indentation processing, not brackets.
+        NodeKind::Parens => (blocks, parens + 1),
+        _ => (blocks, parens),
+    };
+    for child in node.children.iter() {
+        match child {
+            Element::Node(node) => flatten(node, blocks, parens, output),
+            // Synthetic tokens have no text and do not affect formatting.
+            Element::Leaf(leaf) if leaf.is_synthetic() => {}
+            Element::Leaf(leaf) => output.push(Item { leaf, blocks, parens }),
+        }
+    }
+}
+
+/// Split leaves into physical lines, dropping the newlines themselves.
+fn split_lines<'a, 'b>(items: &'b [Item<'a>]) -> Vec<&'b [Item<'a>]> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for (i, item) in items.iter().enumerate() {
+        if item.leaf.kind == LeafKind::Trivia(TriviaKind::Newline) {
+            lines.push(&items[start..i]);
+            start = i + 1;
+        }
+    }
+    if start < items.len() {
+        lines.push(&items[start..]);
+    }
+    lines
+}
+
+fn is_whitespace(leaf: &Leaf) -> bool {
+    leaf.kind == LeafKind::Trivia(TriviaKind::Whitespace)
+}
+
+fn is_directive(significant: &[&Item]) -> bool {
+    match significant.first() {
+        Some(item) => item.leaf.token() == Some(&Token::Punct(Punctuation::Hash)),
+        None => false,
+    }
+}
+
+fn indent_of(item: &Item) -> usize {
+    match item.leaf.token() {
+        // Braces on their own line sit with the code that owns them.
+        Some(Token::Punct(Punctuation::LBrace)) |
+        Some(Token::Punct(Punctuation::RBrace)) => item.blocks.saturating_sub(1),
+        // Closing a multi-line parenthesized list does not get an extra indent.
+        Some(Token::Punct(Punctuation::RParen)) => item.blocks + (item.parens > 1) as usize,
+        _ => item.blocks + (item.parens > 0) as usize,
+    }
+}
+
+fn format_line(output: &mut String, line: &[Item], options: &Format) {
+    let mut prev: Option<&Leaf> = None;
+    let mut had_space = false;
+    for item in line {
+        if is_whitespace(item.leaf) {
+            if prev.is_some() {
+                had_space = true;
+            }
+            continue;
+        }
+        if let Some(prev) = prev {
+            if wants_space(prev, item.leaf, had_space, options) {
+                output.push(' ');
+            }
+        }
+        output.push_str(&item.leaf.text);
+        prev = Some(item.leaf);
+        had_space = false;
+    }
+
+    // Trailing whitespace inside a final line comment is insignificant,
+    // unless trimming it would leave an escaping backslash.
+    if let Some(last) = prev {
+        if last.kind == LeafKind::Trivia(TriviaKind::Comment) && last.text.starts_with("//") {
+            let trimmed = last.text.trim_end();
+            if !trimmed.ends_with('\\') {
+                let excess = last.text.len() - trimmed.len();
+                output.truncate(output.len() - excess);
+            }
+        }
+    }
+}
+
+fn wants_space(prev: &Leaf, next: &Leaf, had_space: bool, options: &Format) -> bool {
+    use self::Punctuation::*;
+
+    let (prev_token, next_token) = match (prev.token(), next.token()) {
+        (Some(p), Some(n)) => (p, n),
+        // Comments are separated from code by exactly one space.
+        (_, None) => return had_space || next.text.starts_with("//"),
+        (None, _) => return had_space,
+    };
+
+    match (prev_token, next_token) {
+        // Whitespace after a colon determines how it is lexed.
+        (Token::Punct(Colon), _) |
+        (Token::Punct(CloseColon), _) => had_space,
+        (Token::Punct(LParen), _) |
+        (Token::Punct(LBracket), _) |
+        (Token::Punct(SafeLBracket), _) |
+        (_, Token::Punct(RParen)) |
+        (_, Token::Punct(RBracket)) |
+        (_, Token::Punct(Comma)) |
+        (_, Token::Punct(Semicolon)) => false,
+        (Token::Punct(Comma), _) => had_space || options.space_after_comma,
+        (Token::Punct(p), _) | (_, Token::Punct(p)) if options.space_around_operators && is_spaced_operator(*p) => true,
+        (Token::Ident(ident, _), Token::Punct(Slash)) if PATH_KEYWORDS.contains(&ident.as_str()) => false,
+        (Token::Ident(ident, _), Token::Punct(LParen)) if !KEYWORDS.contains(&ident.as_str()) => false,
+        _ => had_space,
+    }
+}
+
+fn is_spaced_operator(p: Punctuation) -> bool {
+    use self::Punctuation::*;
+    match p {
+        Assign | AddAssign | SubAssign | MulAssign | DivAssign | ModAssign | FloatModAssign |
+        BitAndAssign | BitOrAssign | BitXorAssign | LShiftAssign | RShiftAssign |
+        Eq | NotEq | Less | LessEq | Greater | GreaterEq | LessGreater | Equiv | NotEquiv |
+        And | Or => true,
+        _ => false,
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Verification
+
+fn check_unchanged(file: FileId, original: &str, formatted: &str) -> Result<(), DMError> {
+    let before = parser_tokens(file, original);
+    let after = parser_tokens(file, formatted);
+    for (i, (old, new)) in before.iter().zip(after.iter()).enumerate() {
+        if !same_token(&old.1, &new.1) {
+            return Err(DMError::new(old.0, format!(
+                "formatting would change token {} from {} to {}",
+                i, old.1, new.1,
+            )));
+        }
+    }
+    if before.len() != after.len() {
+        let location = before.last().map_or(Location::default(), |t| t.0);
+        return Err(DMError::new(location, "formatting would change the number of tokens"));
+    }
+    Ok(())
+}
+
+fn parser_tokens(file: FileId, source: &str) -> Vec<(Location, Token)> {
+    // Errors were already reported while parsing the original.
+    let context = Context::default();
+    let lexer = Lexer::new(&context, file, source.bytes().map(Ok));
+    IndentProcessor::new(&context, lexer)
+        .map(|token| (token.location, token.token))
+        .collect()
+}
+
+fn same_token(old: &Token, new: &Token) -> bool {
+    match (old, new) {
+        // Whether an identifier is followed by whitespace only matters to
+        // `#define`, which is never reformatted.
+        (Token::Ident(a, _), Token::Ident(b, _)) => a == b,
+        (a, b) => a == b,
+    }
+}
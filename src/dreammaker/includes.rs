@@ -0,0 +1,194 @@
+//! The graph of files included by an environment.
+//!
+//! The preprocessor records every `#include` it follows, in order, so that
+//! tools can inspect the include tree and find files which are never included.
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+
+use serde::Serialize;
+
+use super::{FileId, Location};
+
+/// The kind of file an `#include` refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IncludeKind {
+    /// A `.dme` or `.dm` code file.
+    Code,
+    /// A `.dmm` map file.
+    Map,
+    /// A `.dmf` interface file.
+    Interface,
+    /// A `.dms` script file.
+    Script,
+}
+
+/// A file named by an `#include` directive.
+#[derive(Debug, Clone, Serialize)]
+pub struct IncludedFile {
+    /// The path of the file, relative to the environment's directory.
+    pub path: PathBuf,
+    pub kind: IncludeKind,
+    /// The index of the file which included this one, or `None` for the
+    /// environment itself.
+    pub parent: Option<usize>,
+    /// The location of the `#include` directive.
+    #[serde(skip)]
+    pub location: Location,
+    /// Whether this file had already been included, and so was skipped.
+    pub duplicate: bool,
+}
+
+/// Every file included while preprocessing an environment, in order.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct IncludeGraph {
+    files: Vec<IncludedFile>,
+    #[serde(skip)]
+    code_files: HashMap<FileId, usize>,
+}
+
+impl IncludeGraph {
+    /// Start a graph whose root is the given environment file.
+    pub(crate) fn new(env_file: &Path, file: FileId) -> IncludeGraph {
+        let mut graph = IncludeGraph::default();
+        let path = env_file.file_name().map_or_else(|| env_file.to_owned(), PathBuf::from);
+        graph.code_files.insert(file, 0);
+        graph.files.push(IncludedFile {
+            path,
+            kind: IncludeKind::Code,
+            parent: None,
+            location: Location::default(),
+            duplicate: false,
+        });
+        graph
+    }
+
+    /// Record an include, returning the location of the earlier include of
+    /// the same file if this one is a duplicate.
+    pub(crate) fn record(
+        &mut self,
+        path: PathBuf,
+        kind: IncludeKind,
+        file: Option<FileId>,
+        parent: Option<FileId>,
+        location: Location,
+    ) -> Option<Location> {
+        let path = normalize(&path);
+        let previous = self.files.iter().position(|each| !each.duplicate && each.kind == kind && each.path == path);
+        let parent = parent.and_then(|parent| self.code_files.get(&parent).cloned());
+        if let (Some(file), None) = (file, previous) {
+            self.code_files.insert(file, self.files.len());
+        }
+        self.files.push(IncludedFile {
+            path,
+            kind,
+            parent,
+            location,
+            duplicate: previous.is_some(),
+        });
+        previous.map(|idx| self.files[idx].location)
+    }
+
+    /// The included files, in the order their `#include`s were followed.
+    pub fn files(&self) -> &[IncludedFile] {
+        &self.files
+    }
+
+    /// The indices of the files included directly by the given file.
+    pub fn children(&self, index: usize) -> impl Iterator<Item=usize> + '_ {
+        self.files.iter().enumerate()
+            .filter(move |(_, each)| each.parent == Some(index))
+            .map(|(i, _)| i)
+    }
+
+    /// Find the files under `root` which could be included but never were.
+    ///
+    /// `.dm` and `.dmm` files are considered. Directories whose names begin
+    /// with a `.` are skipped.
+    pub fn orphans(&self, root: &Path) -> io::Result<Vec<PathBuf>> {
+        let included: HashSet<&Path> = self.files.iter()
+            .filter(|each| !each.duplicate)
+            .map(|each| each.path.as_path())
+            .collect();
+
+        let root = if root.as_os_str().is_empty() { Path::new(".") } else { root };
+        let mut orphans = Vec::new();
+        let mut pending = vec![root.to_owned()];
+        while let Some(dir) = pending.pop() {
+            for entry in std::fs::read_dir(&dir)? {
+                let path = entry?.path();
+                if path.is_dir() {
+                    if !path.file_name().map_or(false, |name| name.to_string_lossy().starts_with('.')) {
+                        pending.push(path);
+                    }
+                    continue;
+                }
+                match path.extension().and_then(|ext| ext.to_str()) {
+                    Some("dm") | Some("dmm") => {}
+                    _ => continue,
+                }
+                let relative = normalize(path.strip_prefix(root).unwrap_or(&path));
+                if !included.contains(relative.as_path()) {
+                    orphans.push(relative);
+                }
+            }
+        }
+        orphans.sort();
+        Ok(orphans)
+    }
+
+    /// Write the include order as an indented tree.
+    pub fn write_tree<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut depths = Vec::with_capacity(self.files.len());
+        for each in self.files.iter() {
+            let depth = each.parent.map_or(0, |parent| depths[parent] + 1);
+            depths.push(depth);
+            write!(w, "{:width$}{}", "", each.path.display(), width = 2 * depth)?;
+            if each.duplicate {
+                write!(w, " (duplicate)")?;
+            }
+            writeln!(w)?;
+        }
+        Ok(())
+    }
+
+    /// Write the graph in Graphviz DOT format.
+    pub fn write_dot<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "digraph includes {{")?;
+        for each in self.files.iter() {
+            if let Some(parent) = each.parent {
+                write!(w, "    {} -> {}", DotQuote(&self.files[parent].path), DotQuote(&each.path))?;
+                if each.duplicate {
+                    write!(w, " [style=dashed]")?;
+                }
+                writeln!(w, ";")?;
+            }
+        }
+        writeln!(w, "}}")
+    }
+}
+
+struct DotQuote<'a>(&'a Path);
+
+impl<'a> std::fmt::Display for DotQuote<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let path = self.0.display().to_string();
+        write!(f, "\"{}\"", path.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+/// Resolve `.` and `..` components without touching the filesystem.
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => if !out.pop() {
+                out.push("..");
+            },
+            other => out.push(other.as_os_str()),
+        }
+    }
+    out
+}
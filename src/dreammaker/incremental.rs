@@ -0,0 +1,402 @@
+//! Incremental reparsing of an environment after one of its files changes.
+//!
+//! The preprocessor output of each file included by the `.dme` is kept along
+//! with the macros that file used and changed. When a file changes, only it
+//! and those later files which use a macro whose definition it changed are
+//! preprocessed again. The definitions those files made are then removed from
+//! the object tree, and their new tokens are parsed into it.
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::{Context, DMError, FileId, Location};
+use super::lexer::LocatedToken;
+use super::preprocessor::{Preprocessor, DefineMap, MacroEvent};
+use super::indents::IndentProcessor;
+use super::objtree::{ObjectTree, Mentions};
+use super::parser;
+
+/// The preprocessor output of one file included directly by the environment,
+/// or of the environment itself in between such files.
+#[derive(Debug)]
+struct Segment {
+    file: FileId,
+    tokens: Vec<LocatedToken>,
+    uses: HashSet<String>,
+    changes: Vec<MacroEvent>,
+    errors: Vec<DMError>,
+    mentions: Mentions,
+    fatal: bool,
+}
+
+impl Segment {
+    fn new(file: FileId) -> Segment {
+        Segment {
+            file,
+            tokens: Vec::new(),
+            uses: HashSet::new(),
+            changes: Vec::new(),
+            errors: Vec::new(),
+            mentions: Mentions::default(),
+            fatal: false,
+        }
+    }
+
+    fn record(&mut self, events: Vec<MacroEvent>) {
+        for event in events {
+            match event {
+                MacroEvent::Use(name) => { self.uses.insert(name); }
+                other => self.changes.push(other),
+            }
+        }
+    }
+
+    fn absorb(&mut self, other: Segment) {
+        self.tokens.extend(other.tokens);
+        self.uses.extend(other.uses);
+        self.changes.extend(other.changes);
+        self.errors.extend(other.errors);
+    }
+
+    fn apply(&self, defines: &mut DefineMap) {
+        for change in self.changes.iter() {
+            match change {
+                MacroEvent::Define(location, name, define) => {
+                    defines.insert(name.clone(), (*location, define.clone()));
+                }
+                MacroEvent::Undef(name) => {
+                    defines.remove(name);
+                }
+                MacroEvent::Use(_) => {}
+            }
+        }
+    }
+}
+
+/// Test whether two lists of macro changes are equal, ignoring the locations
+/// of the definitions.
+fn same_changes(lhs: &[MacroEvent], rhs: &[MacroEvent]) -> bool {
+    lhs.len() == rhs.len() && lhs.iter().zip(rhs.iter()).all(|pair| match pair {
+        (MacroEvent::Define(_, name1, define1), MacroEvent::Define(_, name2, define2)) => {
+            name1 == name2 && define1 == define2
+        }
+        (a, b) => a == b,
+    })
+}
+
+fn changed_names(changes: &[MacroEvent], names: &mut HashSet<String>) {
+    for change in changes {
+        match change {
+            MacroEvent::Define(_, name, _) | MacroEvent::Undef(name) => { names.insert(name.clone()); }
+            MacroEvent::Use(_) => {}
+        }
+    }
+}
+
+/// Copy the errors registered after the first `count`.
+fn errors_since(context: &Context, count: usize) -> Vec<DMError> {
+    context.errors().split_off(count)
+}
+
+/// Remove one registered copy of each of the given errors.
+fn forget_errors<'a, I: IntoIterator<Item=&'a DMError>>(context: &Context, errors: I) {
+    let mut stale: BTreeMap<Location, Vec<&str>> = BTreeMap::new();
+    for error in errors {
+        stale.entry(error.location()).or_insert_with(Vec::new).push(error.description());
+    }
+    if stale.is_empty() {
+        return;
+    }
+    context.errors_mut().retain(|error| {
+        let descriptions = match stale.get_mut(&error.location()) {
+            Some(descriptions) => descriptions,
+            None => return true,
+        };
+        match descriptions.iter().position(|&description| description == error.description()) {
+            Some(i) => {
+                descriptions.swap_remove(i);
+                false
+            }
+            None => true,
+        }
+    });
+}
+
+/// An environment which has been parsed in a way that allows individual
+/// files to be reparsed after they change.
+#[derive(Debug)]
+pub struct Environment {
+    env_file: PathBuf,
+    env_id: FileId,
+    include_locations: HashMap<FileId, Location>,
+    segments: Vec<Segment>,
+    /// The builtins alone, to restore the vars a file's definitions replaced.
+    builtins: ObjectTree,
+    /// The errors from finalizing the object tree.
+    tree_errors: Vec<DMError>,
+}
+
+impl Environment {
+    /// Preprocess and parse a `.dme` file, keeping what is needed to reparse
+    /// it incrementally.
+    ///
+    /// As with `Context::parse_environment`, will only return failure on an
+    /// `io::Error`.
+    pub fn parse(context: &Context, env_file: &Path) -> io::Result<(Environment, ObjectTree)> {
+        let mut environment = Environment::preprocess(context, env_file)?;
+        let objtree = environment.build(context);
+        Ok((environment, objtree))
+    }
+
+    /// Reparse the environment after the given file has changed on disk,
+    /// updating `objtree` in place.
+    ///
+    /// The changed file is preprocessed again, followed by any later files
+    /// which use a macro it defines differently than before. Only the
+    /// definitions and errors of those files are replaced. Changes to the
+    /// `.dme` itself or to the files a file includes, or a var set both by a
+    /// reparsed file and by another file, cause the whole environment to be
+    /// parsed again.
+    ///
+    /// Returns the files which were preprocessed again.
+    pub fn update(&mut self, context: &Context, file: FileId, objtree: &mut ObjectTree) -> io::Result<Vec<FileId>> {
+        let unit = self.unit_of(file);
+        let index = match self.segments.iter().position(|segment| segment.file == unit) {
+            Some(index) if unit != self.env_id && self.segments[index + 1..].iter().all(|s| s.file != unit) => index,
+            // The environment or a file which is interrupted by it.
+            _ => return self.rebuild(context, objtree, Vec::new()),
+        };
+
+        let mut defines = DefineMap::with_builtins_for(context.config().byond_version());
+        let mut changed = HashSet::new();
+        let mut dirty = Vec::new();
+        let mut replaced = Vec::new();
+        let mut files = HashSet::new();
+        for i in 0..self.segments.len() {
+            let is_dirty = i == index || (i > index &&
+                !changed.is_empty() &&
+                self.segments[i].uses.iter().any(|name| changed.contains(name)));
+
+            if is_dirty {
+                if self.segments[i].file == self.env_id {
+                    return self.rebuild(context, objtree, replaced);
+                }
+                let file = self.segments[i].file;
+                let old_includes = self.nested_includes(file);
+                let segment = self.reprocess(context, i, defines.clone());
+                if self.nested_includes(file) != old_includes {
+                    replaced.push(segment);
+                    return self.rebuild(context, objtree, replaced);
+                }
+                if !same_changes(&segment.changes, &self.segments[i].changes) {
+                    changed_names(&segment.changes, &mut changed);
+                    changed_names(&self.segments[i].changes, &mut changed);
+                }
+                replaced.push(std::mem::replace(&mut self.segments[i], segment));
+                dirty.push(i);
+                files.insert(file);
+                files.extend(old_includes);
+            }
+            self.segments[i].apply(&mut defines);
+        }
+
+        let mut kept = Mentions::default();
+        for (i, segment) in self.segments.iter().enumerate() {
+            if !dirty.contains(&i) {
+                kept.extend(&segment.mentions);
+            }
+        }
+        // Which file set a var last is not recorded, so a var set in both a
+        // replaced and a kept file cannot be untangled.
+        let shared = |segment: &Segment| segment.mentions.vars.iter().any(|var| kept.vars.contains(var));
+        if replaced.iter().any(shared) {
+            return self.rebuild(context, objtree, replaced);
+        }
+
+        objtree.remove_files(&files, &kept, &self.builtins);
+        objtree.file_order = Some(self.file_order());
+        for &i in dirty.iter() {
+            self.parse_segment(context, i, objtree);
+        }
+        objtree.file_order = None;
+        if dirty.iter().any(|&i| shared(&self.segments[i])) {
+            return self.rebuild(context, objtree, replaced);
+        }
+
+        forget_errors(context, replaced.iter().flat_map(|segment| segment.errors.iter()).chain(self.tree_errors.iter()));
+        self.finalize(context, objtree);
+        Ok(dirty.into_iter().map(|i| self.segments[i].file).collect())
+    }
+
+    /// Preprocess and parse the whole environment again. The errors of the
+    /// `stale` segments are removed along with those of the current ones.
+    fn rebuild(&mut self, context: &Context, objtree: &mut ObjectTree, stale: Vec<Segment>) -> io::Result<Vec<FileId>> {
+        forget_errors(context, self.segments.iter()
+            .chain(stale.iter())
+            .flat_map(|segment| segment.errors.iter())
+            .chain(self.tree_errors.iter()));
+        for &file in self.include_locations.keys().chain(std::iter::once(&self.env_id)) {
+            context.clear_file_directives(file);
+        }
+        *self = Environment::preprocess(context, &self.env_file)?;
+        *objtree = self.build(context);
+        Ok(self.segments.iter()
+            .map(|segment| segment.file)
+            .filter(|&file| file != self.env_id)
+            .collect())
+    }
+
+    /// Find the file included directly by the environment which contains
+    /// the given file.
+    fn unit_of(&self, mut file: FileId) -> FileId {
+        while let Some(location) = self.include_locations.get(&file) {
+            if location.file == self.env_id {
+                break;
+            }
+            file = location.file;
+        }
+        file
+    }
+
+    /// Find the position of each file's segment within the environment.
+    fn file_order(&self) -> HashMap<FileId, usize> {
+        let mut positions = HashMap::new();
+        for (i, segment) in self.segments.iter().enumerate() {
+            positions.entry(segment.file).or_insert(i);
+        }
+        let mut order = HashMap::new();
+        for &file in self.include_locations.keys().chain(std::iter::once(&self.env_id)) {
+            if let Some(&position) = positions.get(&self.unit_of(file)) {
+                order.insert(file, position);
+            }
+        }
+        order
+    }
+
+    fn nested_includes(&self, unit: FileId) -> HashSet<FileId> {
+        self.include_locations.keys()
+            .cloned()
+            .filter(|&file| file != unit && self.unit_of(file) == unit)
+            .collect()
+    }
+
+    fn preprocess(context: &Context, env_file: &Path) -> io::Result<Environment> {
+        let mut preprocessor = Preprocessor::new(context, env_file.to_owned())?;
+        preprocessor.enable_macro_log();
+        let env_id = context.register_file(env_file);
+
+        // Split the output into runs of tokens from the same file, noting
+        // which macros and errors were involved in producing each token.
+        let mut runs: Vec<Segment> = Vec::new();
+        let mut error_count = context.error_count();
+        while let Some(token) = preprocessor.next() {
+            if runs.last().map_or(true, |run| run.file != token.location.file) {
+                runs.push(Segment::new(token.location.file));
+            }
+            let run = runs.last_mut().unwrap();
+            run.record(preprocessor.take_macro_log());
+            let errors = errors_since(context, error_count);
+            error_count += errors.len();
+            run.errors.extend(errors);
+            run.tokens.push(token);
+        }
+        if let Some(run) = runs.last_mut() {
+            run.record(preprocessor.take_macro_log());
+            run.errors.extend(errors_since(context, error_count));
+        }
+
+        let mut builtins = ObjectTree::default();
+        builtins.register_builtins(context.config().byond_version());
+        let mut environment = Environment {
+            env_file: env_file.to_owned(),
+            env_id,
+            include_locations: preprocessor.include_locations_mut().clone(),
+            segments: Vec::new(),
+            builtins,
+            tree_errors: Vec::new(),
+        };
+
+        // Merge runs from files included by the same top-level file.
+        for mut run in runs {
+            run.file = environment.unit_of(run.file);
+            match environment.segments.last_mut() {
+                Some(last) if last.file == run.file => last.absorb(run),
+                _ => environment.segments.push(run),
+            }
+        }
+        Ok(environment)
+    }
+
+    /// Preprocess the file of the given segment again, starting from the
+    /// given macro state.
+    fn reprocess(&mut self, context: &Context, index: usize, defines: DefineMap) -> Segment {
+        let file = self.segments[index].file;
+        let location = self.include_locations.get(&file).cloned().unwrap_or_default();
+        let path = self.env_file.parent().unwrap().join(context.file_path(file));
+
+        context.clear_file_directives(file);
+        for nested in self.nested_includes(file) {
+            context.clear_file_directives(nested);
+        }
+
+        let mut preprocessor = Preprocessor::branch(context, self.env_file.clone(), defines, location);
+        preprocessor.enable_macro_log();
+
+        // Files included by earlier segments are not included again.
+        let earlier: HashSet<FileId> = self.segments[..index].iter().map(|segment| segment.file).collect();
+        for (&included, &location) in self.include_locations.iter() {
+            if earlier.contains(&self.unit_of(included)) {
+                preprocessor.include_locations_mut().insert(included, location);
+            }
+        }
+
+        let error_count = context.error_count();
+        if let Err(e) = preprocessor.include_file(path) {
+            context.register_error(e);
+        }
+        let mut segment = Segment::new(file);
+        segment.tokens.extend(preprocessor.by_ref());
+        segment.record(preprocessor.take_macro_log());
+        segment.errors.extend(errors_since(context, error_count));
+
+        let nested = self.nested_includes(file);
+        self.include_locations.retain(|included, _| !nested.contains(included));
+        for (&included, &location) in preprocessor.include_locations_mut().iter() {
+            self.include_locations.insert(included, location);
+        }
+        segment
+    }
+
+    fn build(&mut self, context: &Context) -> ObjectTree {
+        let mut objtree = ObjectTree::default();
+        objtree.register_builtins(context.config().byond_version());
+        for i in 0..self.segments.len() {
+            self.parse_segment(context, i, &mut objtree);
+        }
+        self.finalize(context, &mut objtree);
+        objtree
+    }
+
+    /// Parse the tokens of a segment into the object tree, noting what it
+    /// mentions and the errors it caused.
+    fn parse_segment(&mut self, context: &Context, index: usize, objtree: &mut ObjectTree) {
+        let error_count = context.error_count();
+        objtree.mentions = Some(Mentions::default());
+        let tokens = self.segments[index].tokens.iter().cloned();
+        let fatal = parser::parse_into(context, objtree, IndentProcessor::new(context, tokens));
+
+        let segment = &mut self.segments[index];
+        segment.fatal = fatal;
+        segment.mentions = objtree.mentions.take().unwrap_or_default();
+        segment.errors.extend(errors_since(context, error_count));
+    }
+
+    /// Evaluate the object tree's constants again, replacing the errors of
+    /// the last evaluation.
+    fn finalize(&mut self, context: &Context, objtree: &mut ObjectTree) {
+        let error_count = context.error_count();
+        objtree.reset_constants();
+        objtree.finalize(context, self.segments.iter().any(|segment| segment.fatal));
+        self.tree_errors = errors_since(context, error_count);
+    }
+}
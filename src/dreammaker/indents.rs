@@ -1,7 +1,8 @@
 //! The indentation processor.
 use std::collections::VecDeque;
+use std::sync::Arc;
 
-use crate::{Location, Context, DMError};
+use crate::{Location, Context, DMError, MacroExpansion};
 use crate::lexer::{LocatedToken, Token, Punctuation};
 
 /// Eliminates blank lines, parses and validates indentation, braces, and semicolons.
@@ -12,6 +13,7 @@ pub struct IndentProcessor<'ctx, I> {
     inner: I,
 
     last_input_loc: Location,
+    last_input_expansion: Option<Arc<[MacroExpansion]>>,
     eol_location: Option<Location>,
     output: VecDeque<LocatedToken>,
 
@@ -31,6 +33,7 @@ impl<'ctx, I> IndentProcessor<'ctx, I> where
             context,
             inner: inner.into_iter(),
             last_input_loc: Location::default(),
+            last_input_expansion: None,
             eol_location: None,
             output: VecDeque::new(),
             current: None,
@@ -181,7 +184,11 @@ impl<'ctx, I> IndentProcessor<'ctx, I> where
         }
 
         self.eol_location = None;
-        self.push(read);
+        self.output.push_back(LocatedToken {
+            location: self.last_input_loc,
+            token: read,
+            expansion: self.last_input_expansion.take(),
+        });
     }
 }
 
@@ -198,6 +205,7 @@ impl<'ctx, I> Iterator for IndentProcessor<'ctx, I> where
 
             if let Some(tok) = self.inner_next() {
                 self.last_input_loc = tok.location;
+                self.last_input_expansion = tok.expansion;
                 self.real_next(tok.token);
             } else if self.eof_yielded {
                 return None;
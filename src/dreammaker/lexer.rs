@@ -3,9 +3,14 @@ use std::io;
 use std::str::FromStr;
 use std::fmt;
 use std::borrow::Cow;
+use std::sync::Arc;
 
-use super::{DMError, Location, HasLocation, FileId, Context, Severity};
+use serde::{Serialize, Deserialize};
+
+use super::{DMError, Location, HasLocation, FileId, Context, Severity, MacroExpansion};
 use super::docs::*;
+use super::cst::Span;
+use super::suppression::{self, SuppressionKind};
 
 macro_rules! table {
     (
@@ -13,7 +18,7 @@ macro_rules! table {
         $($literal:expr, $name:ident $(-> $close:ident)*;)*
     ) => {
         $(#[$attr])*
-        #[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+        #[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
         pub enum $enum_ {
             $(
                 $name,
@@ -73,6 +78,8 @@ table! {
     "#",   Hash;
     "##",  TokenPaste;
     "%",   Mod;
+    "%%",  FloatMod;
+    "%%=", FloatModAssign;
     "%=",  ModAssign;
     "&",   BitAnd;
     "&&",  And;
@@ -98,6 +105,7 @@ table! {
     "//",  LineComment;
     "/=",  DivAssign;
     ":",   Colon -> CloseColon;
+    "::",  ColonColon;
     ";",   Semicolon;
     "<",   Less;
     "<<",  LShift;
@@ -113,6 +121,7 @@ table! {
     "?",   QuestionMark;
     "?.",  SafeDot;
     "?:",  SafeColon;
+    "?[",  SafeLBracket;
     "[",   LBracket;
     "]",   RBracket;
     "^",   BitXor;
@@ -144,18 +153,18 @@ static SPEEDY_TABLE: [(usize, usize); 127] = [
     (0, 0), (0, 1), (1, 2), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0),
     (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0),
     (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0),
-    (2, 3), (3, 5), (5, 6), (6, 8), (0, 0), (8, 10), (10, 13), (13, 14),
-    (14, 15), (15, 16), (16, 19), (19, 22), (22, 23), (23, 26), (26, 29), (29, 33),
+    (2, 3), (3, 5), (5, 6), (6, 8), (0, 0), (8, 12), (12, 15), (15, 16),
+    (16, 17), (17, 18), (18, 21), (21, 24), (24, 25), (25, 28), (28, 31), (31, 35),
     (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0),
-    (0, 0), (0, 0), (33, 34), (34, 35), (35, 40), (40, 42), (42, 46), (46, 49),
+    (0, 0), (0, 0), (35, 37), (37, 38), (38, 43), (43, 45), (45, 49), (49, 53),
     (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0),
     (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0),
     (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0),
-    (0, 0), (0, 0), (0, 0), (49, 50), (0, 0), (50, 51), (51, 53), (0, 0),
+    (0, 0), (0, 0), (0, 0), (53, 54), (0, 0), (54, 55), (55, 57), (0, 0),
     (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0),
     (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0),
     (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0),
-    (0, 0), (0, 0), (0, 0), (53, 55), (55, 58), (58, 59), (59, 62)];
+    (0, 0), (0, 0), (0, 0), (57, 59), (59, 62), (62, 63), (63, 66)];
 
 #[test]
 fn make_speedy_table() {
@@ -224,7 +233,7 @@ fn filter_punct<'a>(input: &'a [(&'static str, Punctuation)], filter: &[u8]) ->
 }
 
 /// A single DM token.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Token {
     /// An end-of-file. Exists as a convenience and is not emitted by the lexer.
     Eof,
@@ -407,16 +416,20 @@ impl fmt::Display for FormatFloat {
 }
 
 /// A token with a location attached.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct LocatedToken {
     pub location: Location,
     pub token: Token,
+    /// The macros, outermost first, whose expansion produced this token, if
+    /// the preprocessor was asked to record them.
+    #[serde(skip)]
+    pub expansion: Option<Arc<[MacroExpansion]>>,
 }
 
 impl LocatedToken {
     #[inline]
     pub fn new(location: Location, token: Token) -> LocatedToken {
-        LocatedToken { location, token }
+        LocatedToken { location, token, expansion: None }
     }
 }
 
@@ -479,6 +492,8 @@ pub struct LocationTracker<I> {
     inner: I,
     /// The location of the last character returned by `next()`.
     location: Location,
+    /// The number of bytes returned by `next()` so far.
+    offset: usize,
     at_line_end: bool,
 }
 
@@ -491,6 +506,7 @@ impl<I> LocationTracker<I> {
                 line: 0,
                 column: 0,
             },
+            offset: 0,
             at_line_end: true,
         }
     }
@@ -498,6 +514,11 @@ impl<I> LocationTracker<I> {
     pub fn location(&self) -> Location {
         self.location
     }
+
+    /// The byte offset of the next character to be returned by `next()`.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
 }
 
 impl<I> fmt::Debug for LocationTracker<I> {
@@ -505,6 +526,7 @@ impl<I> fmt::Debug for LocationTracker<I> {
         fmt.debug_struct("LocationTracker")
             // inner omitted
             .field("location", &self.location)
+            .field("offset", &self.offset)
             .field("at_line_end", &self.at_line_end)
             .finish()
     }
@@ -526,6 +548,7 @@ impl<I: Iterator<Item=io::Result<u8>>> Iterator for LocationTracker<I> {
         match self.inner.next() {
             None => None,
             Some(Ok(ch)) => {
+                self.offset += 1;
                 if ch == b'\n' {
                     self.at_line_end = true;
                 }
@@ -550,6 +573,19 @@ pub struct Lexer<'ctx, I> {
     close_allowed: bool,
     directive: Directive,
     interp_stack: Vec<Interpolation>,
+    token_start: usize,
+    /// The last line on which a token other than a comment began.
+    last_code_line: u32,
+    /// Suppression comments waiting for the lines they apply to.
+    pending_suppressions: Vec<PendingSuppression>,
+}
+
+#[derive(Debug)]
+struct PendingSuppression {
+    idx: usize,
+    kind: SuppressionKind,
+    /// The column of the first line of the block, once it is reached.
+    indent: Option<u16>,
 }
 
 impl<'ctx, I> fmt::Debug for Lexer<'ctx, I> {
@@ -562,6 +598,9 @@ impl<'ctx, I> fmt::Debug for Lexer<'ctx, I> {
             .field("at_line_head", &self.at_line_head)
             .field("directive", &self.directive)
             .field("interp_stack", &self.interp_stack)
+            .field("token_start", &self.token_start)
+            .field("last_code_line", &self.last_code_line)
+            .field("pending_suppressions", &self.pending_suppressions)
             .finish()
     }
 }
@@ -592,9 +631,24 @@ impl<'ctx, I: Iterator<Item=io::Result<u8>>> Lexer<'ctx, I> {
             close_allowed: true,
             directive: Directive::None,
             interp_stack: Vec::new(),
+            token_start: 0,
+            last_code_line: 0,
+            pending_suppressions: Vec::new(),
         }
     }
 
+    /// The byte span of the most recently returned token.
+    ///
+    /// Bytes which fall between the spans of consecutive tokens are
+    /// whitespace, comments, or other input the lexer skipped over.
+    pub fn last_span(&self) -> Span {
+        Span::new(self.token_start, self.offset())
+    }
+
+    fn offset(&self) -> usize {
+        self.input.offset() - self.next.is_some() as usize
+    }
+
     fn next(&mut self) -> Option<u8> {
         if let Some(next) = self.next.take() {
             return Some(next);
@@ -679,11 +733,13 @@ impl<'ctx, I: Iterator<Item=io::Result<u8>>> Lexer<'ctx, I> {
         comment.filter(|c| !c.text.is_empty()).map(Token::DocComment)
     }
 
-    fn skip_line_comment(&mut self) -> Option<Token> {
+    fn skip_line_comment(&mut self, start: Location) -> Option<Token> {
         let mut backslash = false;
 
         // read the first character and check for being a comment
         let mut comment = None;
+        // the text of an ordinary comment, checked for suppressions
+        let mut text = String::new();
         match self.next() {
             Some(b'/') => comment = Some(DocComment::new(CommentKind::Line, DocTarget::FollowingItem)),
             Some(b'!') => comment = Some(DocComment::new(CommentKind::Line, DocTarget::EnclosingItem)),
@@ -692,13 +748,15 @@ impl<'ctx, I: Iterator<Item=io::Result<u8>>> Lexer<'ctx, I> {
                 return None;
             }
             Some(b'\\') => backslash = true,
-            _ => {}
+            Some(other) => text.push(other as char),
+            None => {}
         }
 
         while let Some(ch) = self.next() {
             if ch != b'\r' && ch != b'\n' {
-                if let Some(ref mut comment) = comment {
-                    comment.text.push(ch as char);
+                match comment {
+                    Some(ref mut comment) => comment.text.push(ch as char),
+                    None => text.push(ch as char),
                 }
             }
 
@@ -714,9 +772,68 @@ impl<'ctx, I: Iterator<Item=io::Result<u8>>> Lexer<'ctx, I> {
             }
         }
 
+        if comment.is_none() {
+            self.check_suppression(start, &text);
+        }
         comment.map(Token::DocComment)
     }
 
+    fn check_suppression(&mut self, start: Location, text: &str) {
+        guard!(let Some((kind, errortypes)) = suppression::parse_comment(text) else { return });
+        if errortypes.is_empty() {
+            DMError::new(start, "suppression comment does not name any errortypes")
+                .set_severity(Severity::Warning)
+                .with_note(start, "expected e.g. `// dreamchecker-ignore: errortype`")
+                .with_errortype("invalid_suppression")
+                .register(self.context);
+            return;
+        }
+        let idx = self.context.register_suppression(start, errortypes);
+        if kind == SuppressionKind::Line && self.last_code_line == start.line {
+            // at the end of a line of code, applies to that line
+            self.context.end_suppression(idx, start.line);
+            self.context.begin_suppression(idx, start.file, start.line);
+        } else {
+            self.pending_suppressions.push(PendingSuppression { idx, kind, indent: None });
+        }
+    }
+
+    /// Note that a token other than a comment began at the given location,
+    /// and start or finish any suppressions waiting for it.
+    fn code_at(&mut self, location: Location) {
+        if location.line == self.last_code_line {
+            return;
+        }
+        self.last_code_line = location.line;
+        if self.pending_suppressions.is_empty() {
+            return;
+        }
+        let context = self.context;
+        self.pending_suppressions.retain(|pending| {
+            match (pending.kind, pending.indent) {
+                (SuppressionKind::Line, _) => {
+                    context.end_suppression(pending.idx, location.line);
+                    context.begin_suppression(pending.idx, location.file, location.line);
+                    false
+                }
+                (SuppressionKind::Block, None) => {
+                    context.begin_suppression(pending.idx, location.file, location.line);
+                    true
+                }
+                (SuppressionKind::Block, Some(indent)) if location.column <= indent => {
+                    context.end_suppression(pending.idx, location.line - 1);
+                    false
+                }
+                (SuppressionKind::Block, Some(_)) => true,
+            }
+        });
+        for pending in self.pending_suppressions.iter_mut() {
+            if pending.indent.is_none() {
+                pending.indent = Some(location.column);
+            }
+        }
+    }
+
     fn read_number_inner(&mut self, first: u8) -> (bool, u32, Cow<'static, str>) {
         let mut integer = true;
         let mut exponent = false;
@@ -1064,12 +1181,10 @@ impl<'ctx, I: Iterator<Item=io::Result<u8>>> Iterator for Lexer<'ctx, I> {
                     // always end with a newline
                     if !self.final_newline {
                         self.final_newline = true;
+                        self.token_start = self.offset();
                         let mut location = self.location();
                         location.column += 1;
-                        return Some(LocatedToken {
-                            location,
-                            token: Token::Punct(Punctuation::Newline),
-                        });
+                        return Some(LocatedToken::new(location, Token::Punct(Punctuation::Newline)));
                     } else {
                         return None;
                     }
@@ -1077,6 +1192,7 @@ impl<'ctx, I: Iterator<Item=io::Result<u8>>> Iterator for Lexer<'ctx, I> {
             };
             skip_newlines = false;
 
+            self.token_start = self.offset() - 1;
             let loc = self.location();
             let locate = |token| LocatedToken::new(loc, token);
 
@@ -1090,6 +1206,12 @@ impl<'ctx, I: Iterator<Item=io::Result<u8>>> Iterator for Lexer<'ctx, I> {
             if self.close_allowed {
                 punct = punct.map(|p| self.check_close(p));
             }
+            // Indentation and newlines, such as those of blank lines or of
+            // lines with only a comment, are not code.
+            match punct {
+                Some(BlockComment) | Some(LineComment) | Some(Newline) | Some(Tab) | Some(Space) => {}
+                _ => self.code_at(loc),
+            }
             return match punct {
                 Some(Hash) if self.directive == Directive::None => {
                     self.directive = Directive::Hash;
@@ -1102,7 +1224,7 @@ impl<'ctx, I: Iterator<Item=io::Result<u8>>> Iterator for Lexer<'ctx, I> {
                     continue;
                 }
                 Some(LineComment) => {
-                    if let Some(t) = self.skip_line_comment() {
+                    if let Some(t) = self.skip_line_comment(loc) {
                         return Some(locate(t));
                     }
                     continue;
@@ -1110,11 +1232,11 @@ impl<'ctx, I: Iterator<Item=io::Result<u8>>> Iterator for Lexer<'ctx, I> {
                 Some(SingleQuote) => Some(locate(Resource(self.read_resource()))),
                 Some(DoubleQuote) => Some(locate(self.read_string(b"\"", false))),
                 Some(BlockString) => Some(locate(self.read_string(b"\"}", false))),
-                Some(LBracket) => {
+                Some(p @ LBracket) | Some(p @ SafeLBracket) => {
                     if let Some(interp) = self.interp_stack.last_mut() {
                         interp.bracket_depth += 1;
                     }
-                    Some(locate(Punct(LBracket)))
+                    Some(locate(Punct(p)))
                 }
                 Some(RBracket) => {
                     if let Some(mut interp) = self.interp_stack.pop() {
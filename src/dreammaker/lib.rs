@@ -12,6 +12,8 @@ extern crate ordered_float;
 extern crate serde;
 extern crate serde_derive;
 extern crate toml;
+extern crate bincode;
+extern crate rayon;
 
 use std::io;
 use std::path::Path;
@@ -34,7 +36,12 @@ pub use error::*;
 pub mod docs;
 pub mod lexer;
 pub mod preprocessor;
+pub mod includes;
+pub mod pragma;
+pub mod suppression;
 pub mod indents;
+pub mod incremental;
+pub mod cst;
 pub mod parser;
 pub mod annotation;
 pub mod ast;
@@ -43,6 +50,9 @@ mod builtins;
 pub mod constants;
 pub mod dmi;
 pub mod config;
+pub mod cache;
+pub mod formatter;
+pub mod printer;
 
 impl Context {
     /// Run the parsing suite on a given `.dme` file, producing an object tree.
@@ -1,6 +1,6 @@
 //! The object tree representation, used as a parsing target.
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt;
 
 pub use petgraph::graph::NodeIndex;
@@ -8,20 +8,23 @@ use petgraph::graph::Graph;
 use petgraph::visit::EdgeRef;
 use petgraph::Direction;
 use linked_hash_map::LinkedHashMap;
+use serde::{Serialize, Deserialize};
 
-use super::ast::{Expression, VarType, VarSuffix, PathOp, Parameter, Block, ProcDeclKind};
+use super::ast::{Expression, VarType, VarSuffix, PathOp, Parameter, Block, ProcDeclKind, ProcReturnType};
 use super::constants::{Constant, Pop};
 use super::docs::DocCollection;
-use super::{DMError, Location, Context, Severity};
+use super::lexer::LocatedToken;
+use super::{DMError, Location, Context, Severity, FileId};
+use super::config::ByondVersion;
 
 // ----------------------------------------------------------------------------
 // Symbol IDs
 
 /// An identifier referring to a symbol in the object tree.
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
 pub struct SymbolId(u32);
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct SymbolIdSource(SymbolId);
 
 #[derive(Copy, Clone, Debug)]
@@ -50,14 +53,14 @@ impl SymbolIdSource {
 
 pub type Vars = LinkedHashMap<String, Constant>;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VarDeclaration {
     pub var_type: VarType,
     pub location: Location,
     pub id: SymbolId,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VarValue {
     pub location: Location,
     /// Syntactic value, as specified in the source.
@@ -68,13 +71,13 @@ pub struct VarValue {
     pub docs: DocCollection,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TypeVar {
     pub value: VarValue,
     pub declaration: Option<VarDeclaration>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProcDeclaration {
     pub location: Location,
     pub kind: ProcDeclKind,
@@ -83,23 +86,50 @@ pub struct ProcDeclaration {
     pub is_protected: bool,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProcValue {
     pub location: Location,
     pub parameters: Vec<Parameter>,
+    pub return_type: Option<ProcReturnType>,
     pub docs: DocCollection,
     pub code: Code,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Code {
     Present(Block),
     Invalid(DMError),
     Builtin,
     Disabled,
+    /// The tokens of a body which has not been parsed yet.
+    Deferred(Vec<LocatedToken>),
 }
 
-#[derive(Debug, Clone, Default)]
+impl Code {
+    /// Parse this body if it was deferred, registering any errors.
+    ///
+    /// Returns whether a deferred body parsed without any errors, including
+    /// statements which were recovered from.
+    pub fn parse(&mut self, context: &Context) -> bool {
+        if let Code::Deferred(tokens) = self {
+            let tokens = std::mem::replace(tokens, Vec::new());
+            let (result, statements_bad) = super::parser::parse_proc_body(context, tokens);
+            let good = result.is_ok() && statements_bad == 0;
+            *self = match result {
+                Ok(block) => Code::Present(block),
+                Err(err) => {
+                    context.register_error(err.clone());
+                    Code::Invalid(err)
+                }
+            };
+            good
+        } else {
+            false
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct TypeProc {
     pub value: Vec<ProcValue>,
     pub declaration: Option<ProcDeclaration>,
@@ -117,7 +147,7 @@ impl TypeProc {
 
 const BAD_NODE_INDEX: usize = std::usize::MAX;
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Type {
     pub name: String,
     pub path: String,
@@ -625,11 +655,49 @@ impl<'a> std::hash::Hash for ProcRef<'a> {
 // ----------------------------------------------------------------------------
 // The object tree itself
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct ObjectTree {
     pub graph: Graph<Type, ()>,
     pub types: BTreeMap<String, NodeIndex>,
     symbols: SymbolIdSource,
+    /// The types and vars mentioned since recording began, used when
+    /// reparsing part of an environment.
+    #[serde(skip)]
+    pub(crate) mentions: Option<Mentions>,
+    /// The position of each file within its environment, used to order
+    /// proc overrides when files are parsed out of order.
+    #[serde(skip)]
+    pub(crate) file_order: Option<HashMap<FileId, usize>>,
+    /// Builtin procs and vars left out because the target BYOND version
+    /// predates them, keyed like `/regex/proc/Find_char`, with the version
+    /// they need.
+    newer_builtins: HashMap<String, (u32, u32)>,
+}
+
+/// The types and vars which some part of an environment mentions.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Mentions {
+    /// The least specific mention of each type, as in `Type::location`.
+    pub types: HashMap<String, (Location, usize)>,
+    /// Type paths and names of the vars declared or overridden.
+    pub vars: HashSet<(String, String)>,
+}
+
+impl Mentions {
+    fn mention_type(&mut self, path: &str, location: Location, len: usize) {
+        match self.types.get(path) {
+            Some(&(_, specificity)) if specificity <= len => {}
+            _ => { self.types.insert(path.to_owned(), (location, len)); }
+        }
+    }
+
+    /// Merge mentions from a later part of the environment.
+    pub fn extend(&mut self, other: &Mentions) {
+        for (path, &(location, len)) in other.types.iter() {
+            self.mention_type(path, location, len);
+        }
+        self.vars.extend(other.vars.iter().cloned());
+    }
 }
 
 impl Default for ObjectTree {
@@ -638,6 +706,9 @@ impl Default for ObjectTree {
             graph: Default::default(),
             types: Default::default(),
             symbols: SymbolIdSource::new(SymbolIdCategory::ObjectTree),
+            mentions: None,
+            file_order: None,
+            newer_builtins: Default::default(),
         };
         tree.graph.add_node(Type {
             name: String::new(),
@@ -663,12 +734,12 @@ pub enum EntryType {
 impl ObjectTree {
     pub fn with_builtins() -> ObjectTree {
         let mut objtree = ObjectTree::default();
-        objtree.register_builtins();
+        objtree.register_builtins(None);
         objtree
     }
 
-    pub(crate) fn register_builtins(&mut self) {
-        super::builtins::register_builtins(self).expect("register_builtins failed");
+    pub(crate) fn register_builtins(&mut self, version: Option<ByondVersion>) {
+        super::builtins::register_builtins(self, version).expect("register_builtins failed");
     }
 
     // ------------------------------------------------------------------------
@@ -692,6 +763,30 @@ impl ObjectTree {
         }
     }
 
+    /// The BYOND version required by a builtin proc which was left out of
+    /// this tree because the target version predates it.
+    pub fn newer_builtin_proc(&self, ty: TypeRef, name: &str) -> Option<ByondVersion> {
+        self.newer_builtin(ty, "proc", name)
+    }
+
+    /// The BYOND version required by a builtin var which was left out of
+    /// this tree because the target version predates it.
+    pub fn newer_builtin_var(&self, ty: TypeRef, name: &str) -> Option<ByondVersion> {
+        self.newer_builtin(ty, "var", name)
+    }
+
+    fn newer_builtin(&self, ty: TypeRef, kind: &str, name: &str) -> Option<ByondVersion> {
+        let mut current = Some(ty);
+        while let Some(ty) = current {
+            let key = format!("{}/{}/{}", ty.path, kind, name);
+            if let Some(&(major, build)) = self.newer_builtins.get(&key) {
+                return Some(ByondVersion::new(major, build));
+            }
+            current = ty.parent_type();
+        }
+        None
+    }
+
     pub fn parent_of(&self, type_: &Type) -> Option<&Type> {
         self.graph.node_weight(type_.parent_type)
     }
@@ -754,6 +849,87 @@ impl ObjectTree {
         }
     }
 
+    /// Forget every evaluated constant, so that `finalize` evaluates them
+    /// again.
+    pub(crate) fn reset_constants(&mut self) {
+        for ty in self.graph.node_weights_mut() {
+            for (_, var) in ty.vars.iter_mut() {
+                var.value.constant = None;
+                var.value.being_evaluated = false;
+            }
+        }
+    }
+
+    /// Remove the definitions made in the given files.
+    ///
+    /// Types are kept if they are builtin or mentioned by `kept`. Vars the
+    /// builtins declare on a type are restored to their builtin values.
+    pub(crate) fn remove_files(&mut self, files: &HashSet<FileId>, kept: &Mentions, builtins: &ObjectTree) {
+        let mut removed = Vec::new();
+        for idx in self.graph.node_indices() {
+            let builtin = builtins.find(&self.graph[idx].path).map(TypeRef::get);
+            let ty = &mut self.graph[idx];
+
+            let stale_vars: Vec<String> = ty.vars.iter()
+                .filter(|(_, var)| {
+                    files.contains(&var.value.location.file) ||
+                        var.declaration.as_ref().map_or(false, |decl| files.contains(&decl.location.file))
+                })
+                .map(|(name, _)| name.clone())
+                .collect();
+            for name in stale_vars {
+                match builtin.and_then(|builtin| builtin.vars.get(&name)) {
+                    Some(var) => { ty.vars.insert(name, var.clone()); }
+                    None => { ty.vars.remove(&name); }
+                }
+            }
+
+            let mut stale_procs = Vec::new();
+            for (name, proc) in ty.procs.iter_mut() {
+                proc.value.retain(|value| !files.contains(&value.location.file));
+                if proc.declaration.as_ref().map_or(false, |decl| files.contains(&decl.location.file)) {
+                    proc.declaration = None;
+                }
+                if proc.value.is_empty() {
+                    stale_procs.push(name.clone());
+                }
+            }
+            for name in stale_procs {
+                ty.procs.remove(&name);
+            }
+
+            if ty.is_root() {
+                continue;
+            }
+            let mention = kept.types.get(&ty.path).cloned();
+            if builtin.is_none() && mention.is_none() {
+                removed.push(idx);
+            } else if files.contains(&ty.location.file) {
+                let mut best = builtin.map(|builtin| (builtin.location, builtin.location_specificity));
+                if let Some((location, len)) = mention {
+                    if best.map_or(true, |(_, specificity)| specificity > len) {
+                        best = Some((location, len));
+                    }
+                }
+                if let Some((location, len)) = best {
+                    ty.location = location;
+                    ty.location_specificity = len;
+                }
+            }
+        }
+
+        // Removing a node moves the last node into its place, so remove
+        // from the back to keep the remaining indices valid.
+        removed.sort_unstable_by(|a, b| b.cmp(a));
+        for idx in removed {
+            self.graph.remove_node(idx);
+        }
+        self.types = self.graph.node_indices()
+            .skip(1)
+            .map(|idx| (self.graph[idx].path.clone(), idx))
+            .collect();
+    }
+
     fn assign_parent_types(&mut self, context: &Context) {
         for (path, &type_idx) in self.types.iter() {
             let mut location = self.graph.node_weight(type_idx).unwrap().location;
@@ -845,6 +1021,9 @@ impl ObjectTree {
         while let Some(target) = neighbors.next_node(&self.graph) {
             let node = self.graph.node_weight_mut(target).unwrap();
             if node.name == child {
+                if let Some(mentions) = self.mentions.as_mut() {
+                    mentions.mention_type(&node.path, location, len);
+                }
                 if node.location_specificity > len {
                     node.location_specificity = len;
                     node.location = location;
@@ -855,6 +1034,9 @@ impl ObjectTree {
 
         // time to add a new child
         let path = format!("{}/{}", self.graph.node_weight(parent).unwrap().path, child);
+        if let Some(mentions) = self.mentions.as_mut() {
+            mentions.mention_type(&path, location, len);
+        }
         let node = self.graph.add_node(Type {
             name: child.to_owned(),
             path: path.clone(),
@@ -951,6 +1133,9 @@ impl ObjectTree {
 
         let symbols = &mut self.symbols;
         let node = self.graph.node_weight_mut(parent).unwrap();
+        if let Some(mentions) = self.mentions.as_mut() {
+            mentions.vars.insert((node.path.clone(), prev.to_owned()));
+        }
         // TODO: warn and merge docs for repeats
         Ok(Some(node.vars.entry(prev.to_owned()).or_insert_with(|| TypeVar {
             value: VarValue {
@@ -982,8 +1167,15 @@ impl ObjectTree {
         parameters: Vec<Parameter>,
         code: Code,
     ) -> Result<(usize, &mut ProcValue), DMError> {
+        let file_order = &self.file_order;
+        let rank = |location: Location| file_order.as_ref()
+            .and_then(|order| order.get(&location.file))
+            .cloned()
+            .unwrap_or(0);
         let node = self.graph.node_weight_mut(parent).unwrap();
         let proc = node.procs.entry(name.to_owned()).or_insert_with(Default::default);
+        let declared_first = proc.declaration.as_ref()
+            .map_or(false, |decl| proc.value.first().map_or(false, |first| first.location == decl.location));
         if let Some(kind) = declaration {
             if let Some(ref decl) = proc.declaration {
                 DMError::new(location, format!("duplicate definition of {}/{}", kind, name))
@@ -1003,6 +1195,7 @@ impl ObjectTree {
         let value = ProcValue {
             location,
             parameters,
+            return_type: None,
             docs: Default::default(),
             code
         };
@@ -1019,17 +1212,27 @@ impl ObjectTree {
                 // Show the hint now, make up for it by putting the original
                 // at the beginning of the list (so `..()` finds it).
                 // Configuration can be used to upgrade this above a hint.
-                DMError::new(proc.value[0].location, format!("override of {}/{} precedes definition", node.path, name))
-                    .set_severity(Severity::Hint)
-                    .with_errortype("override_precedes_definition")
-                    .with_note(location, format!("{}/{}/{} is defined here", node.path, decl, name))
-                    .register(context);
+                // Overrides from later files only precede the definition
+                // because those files were parsed first.
+                if let Some(first) = proc.value.iter().find(|value| file_order.is_none() || rank(value.location) < rank(location)) {
+                    DMError::new(first.location, format!("override of {}/{} precedes definition", node.path, name))
+                        .set_severity(Severity::Hint)
+                        .with_errortype("override_precedes_definition")
+                        .with_note(location, format!("{}/{}/{} is defined here", node.path, decl, name))
+                        .register(context);
+                }
                 proc.value.insert(0, value);
                 Ok((len, proc.value.first_mut().unwrap()))
             },
             _ => {
-                proc.value.push(value);
-                Ok((len, proc.value.last_mut().unwrap()))
+                // Keep overrides in the order of their files, after the
+                // definition.
+                let index = proc.value.iter()
+                    .rposition(|value| rank(value.location) <= rank(location))
+                    .map_or(0, |index| index + 1)
+                    .max(declared_first as usize);
+                proc.value.insert(index, value);
+                Ok((index, &mut proc.value[index]))
             }
         }
     }
@@ -1086,6 +1289,23 @@ impl ObjectTree {
         )
     }
 
+    pub(crate) fn add_newer_builtin(&mut self, elems: &[&'static str], since: ByondVersion) {
+        // Key by type, kind and name, dropping modifiers like `var/const`.
+        let idx = match elems.iter().position(|&each| each == "var" || each == "proc" || each == "verb") {
+            Some(idx) => idx,
+            None => return,
+        };
+        let mut key = String::new();
+        for each in &elems[..idx] {
+            key.push('/');
+            key.push_str(each);
+        }
+        let name = elems[elems.len() - 1];
+        let kind = if elems[idx] == "var" { "var" } else { "proc" };
+        key.push_str(&format!("/{}/{}", kind, name));
+        self.newer_builtins.insert(key, (since.major, since.build));
+    }
+
     // an entry which is definitely a var because a value is specified
     pub fn add_var<'a, I: Iterator<Item = &'a str>>(
         &mut self,
@@ -1153,6 +1373,33 @@ impl ObjectTree {
         self.register_proc(context, location, parent, proc_name, declaration, parameters, code)
     }
 
+    /// Parse every deferred proc body, spreading the work across threads.
+    pub fn parse_proc_bodies(&mut self, context: &Context) {
+        use rayon::prelude::*;
+
+        let mut bodies = Vec::new();
+        for node in self.graph.node_weights_mut() {
+            for (_, typroc) in node.procs.iter_mut() {
+                for proc in typroc.value.iter_mut() {
+                    if let Code::Deferred(_) = proc.code {
+                        bodies.push(&mut proc.code);
+                    }
+                }
+            }
+        }
+
+        // Errors arrive from each thread in no particular order, so sort
+        // them afterwards.
+        let error_count = context.error_count();
+        let good = bodies.par_iter_mut().map(|code| code.parse(context)).filter(|&good| good).count();
+        context.sort_errors_from(error_count);
+
+        let total = bodies.len();
+        if total > 0 {
+            eprintln!("parsed {}/{} proc bodies ({}%)", good, total, good * 100 / total);
+        }
+    }
+
     /// Drop all code ASTs to attempt to reduce memory usage.
     pub fn drop_code(&mut self) {
         for node in self.graph.node_weights_mut() {
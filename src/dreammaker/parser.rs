@@ -4,10 +4,11 @@ use std::borrow::Cow;
 use std::collections::BTreeMap;
 use std::ops::Range;
 use std::fmt;
+use std::sync::Arc;
 
 use linked_hash_map::LinkedHashMap;
 
-use super::{DMError, Location, HasLocation, Context, Severity, FileId};
+use super::{DMError, Location, HasLocation, Context, Severity, FileId, MacroExpansion};
 use super::lexer::{LocatedToken, Token, Punctuation};
 use super::objtree::{ObjectTree, EntryType};
 use super::annotation::*;
@@ -62,6 +63,29 @@ where
     Parser::new(context, iter.into_iter()).parse_object_tree()
 }
 
+/// Parse part of an environment into an existing object tree, without
+/// finalizing it. Returns whether the parser gave up on a fatal error.
+pub(crate) fn parse_into<I>(context: &Context, tree: &mut ObjectTree, iter: I) -> bool
+where
+    I: IntoIterator<Item=LocatedToken>,
+{
+    let mut parser = Parser::new(context, iter.into_iter());
+    parser.tree = std::mem::replace(tree, Default::default());
+    parser.run();
+    *tree = std::mem::replace(&mut parser.tree, Default::default());
+    parser.fatal_errored
+}
+
+/// Parse the tokens of a proc body, as recorded by `Parser::defer_procs`.
+///
+/// Also returns the number of statements which failed to parse and were
+/// recovered from.
+pub(crate) fn parse_proc_body(context: &Context, tokens: Vec<LocatedToken>) -> (Result<Block, DMError>, u64) {
+    let mut parser = Parser::new(context, tokens);
+    let block = parser.block(&LoopContext::None);
+    (parser.require(block), parser.statements_bad)
+}
+
 /// Parse a token stream into an expression.
 ///
 /// Fatal errors will be directly returned and miscellaneous diagnostics will
@@ -75,6 +99,36 @@ where
     Ok(require!(parser.expression()))
 }
 
+// ----------------------------------------------------------------------------
+// Helpers for the 515 syntax additions
+
+fn list_access_kind(token: &Token) -> ListAccessKind {
+    match token {
+        Token::Punct(Punctuation::SafeLBracket) => ListAccessKind::Safe,
+        _ => ListAccessKind::Normal,
+    }
+}
+
+/// Recognize the `Key, Value in List` form of a for loop from the init and
+/// test of what would otherwise be a `for(Init, Test)` loop.
+fn for_key_value(init: &Statement, test: &Expression) -> Option<(Option<VarType>, String, String, Expression)> {
+    let (var_type, key) = match init {
+        Statement::Var(VarStatement { var_type, name, value: None }) => (Some(var_type.clone()), name.clone()),
+        Statement::Expr(expr) => match expr.as_term() {
+            Some(Term::Ident(name)) => (None, name.clone()),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    match test {
+        Expression::BinaryOp { op: BinaryOp::In, lhs, rhs } => match lhs.as_term() {
+            Some(Term::Ident(value)) => Some((var_type, key, value.clone(), (**rhs).clone())),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
 // ----------------------------------------------------------------------------
 // Path stack and iterator over parts so far
 
@@ -223,11 +277,12 @@ oper_table! { BINARY_OPS;
     Pow {
         (BinaryOp, Pow),
     }
-    // * / %
+    // * / % %%
     Mul {
         (BinaryOp, Mul), //
         (BinaryOp, Div = Slash), //
         (BinaryOp, Mod),
+        (BinaryOp, FloatMod),
     }
     // + -
     Add {
@@ -272,7 +327,7 @@ oper_table! { BINARY_OPS;
     Conditional {
         (TernaryOp, Conditional = QuestionMark),
     }
-    // = += -= -= *= /= %= &= |= ^= <<= >>=
+    // = += -= -= *= /= %= %%= &= |= ^= <<= >>=
     Assign {
         (AssignOp, Assign),
         (AssignOp, AddAssign),
@@ -280,6 +335,7 @@ oper_table! { BINARY_OPS;
         (AssignOp, MulAssign),
         (AssignOp, DivAssign),
         (AssignOp, ModAssign),
+        (AssignOp, FloatModAssign),
         (AssignOp, BitAndAssign),
         (AssignOp, BitOrAssign),
         (AssignOp, BitXorAssign),
@@ -317,7 +373,8 @@ impl TTKind {
         match *token {
             Token::Punct(Punctuation::LParen) => Some(TTKind::Paren),
             Token::Punct(Punctuation::LBrace) => Some(TTKind::Brace),
-            Token::Punct(Punctuation::LBracket) => Some(TTKind::Bracket),
+            Token::Punct(Punctuation::LBracket) |
+            Token::Punct(Punctuation::SafeLBracket) => Some(TTKind::Bracket),
             _ => None,
         }
     }
@@ -354,6 +411,8 @@ pub struct Parser<'ctx, 'an, 'inp> {
     annotations: Option<&'an mut AnnotationTree>,
     tree: ObjectTree,
     fatal_errored: bool,
+    /// The macros which produced the most recent token, if recorded.
+    expansion: Option<Arc<[MacroExpansion]>>,
 
     input: Box<dyn Iterator<Item=LocatedToken> + 'inp>,
     eof: bool,
@@ -368,8 +427,13 @@ pub struct Parser<'ctx, 'an, 'inp> {
     in_docs: usize,
 
     procs: bool,
+    defer_procs: bool,
     procs_bad: u64,
     procs_good: u64,
+    statements_bad: u64,
+    /// Locals declared by the statements being parsed, kept for statements
+    /// which fail to parse after declaring them.
+    declared_vars: Vec<VarStatement>,
 }
 
 impl<'ctx, 'an, 'inp> HasLocation for Parser<'ctx, 'an, 'inp> {
@@ -386,6 +450,7 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
             annotations: None,
             tree: ObjectTree::default(),
             fatal_errored: false,
+            expansion: None,
 
             input: Box::new(input.into_iter()),
             eof: false,
@@ -400,8 +465,11 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
             in_docs: 0,
 
             procs: false,
+            defer_procs: false,
             procs_bad: 0,
             procs_good: 0,
+            statements_bad: 0,
+            declared_vars: Vec::new(),
         }
     }
 
@@ -409,19 +477,25 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
         self.procs = true;
     }
 
+    /// Keep the tokens of proc bodies without parsing them, to be parsed by
+    /// `Code::parse` or `ObjectTree::parse_proc_bodies` when needed.
+    pub fn defer_procs(&mut self) {
+        self.defer_procs = true;
+    }
+
     pub fn annotate_to(&mut self, annotations: &'an mut AnnotationTree) {
         self.annotations = Some(annotations);
         self.procs = true;
     }
 
     pub fn parse_object_tree(mut self) -> ObjectTree {
-        self.tree.register_builtins();
+        self.tree.register_builtins(self.context.config().byond_version());
         self.run();
         self.finalize_object_tree()
     }
 
     pub fn parse_with_module_docs(mut self) -> (ObjectTree, BTreeMap<FileId, Vec<(u32, DocComment)>>) {
-        self.tree.register_builtins();
+        self.tree.register_builtins(self.context.config().byond_version());
         self.run();
         let docs = std::mem::replace(&mut self.module_docs, Default::default());
         (self.finalize_object_tree(), docs)
@@ -500,6 +574,19 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
         }
     }
 
+    /// Warn if the configured target BYOND version predates `major`.
+    fn require_version(&mut self, major: u32, what: &str) {
+        let target = self.context.config().byond_version();
+        if let Some(target) = target {
+            if !target.supports(major, 0) {
+                self.error(format!("{} requires BYOND {}, but the target version is {}", what, major, target))
+                    .set_severity(Severity::Warning)
+                    .with_errortype("byond_version")
+                    .register(self.context);
+            }
+        }
+    }
+
     fn next<S: Into<Cow<'static, str>>>(&mut self, expected: S) -> Result<Token, DMError> {
         let tok = loop {
             if let Some(next) = self.next.take() {
@@ -509,6 +596,7 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
                 Some(LocatedToken {
                     location,
                     token: Token::DocComment(dc),
+                    ..
                 }) => match dc.target {
                     DocTarget::EnclosingItem if self.in_docs == 0 => {
                         self.module_docs
@@ -522,6 +610,7 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
                 Some(token) => {
                     self.expected.clear();
                     self.location = token.location;
+                    self.expansion = token.expansion;
                     break Ok(token.token);
                 }
                 None => {
@@ -726,6 +815,10 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
                     last_part.push('%');
                 } else if self.exact(Punct(ModAssign))?.is_some() {
                     last_part.push_str("%=");
+                } else if self.exact(Punct(FloatMod))?.is_some() {
+                    last_part.push_str("%%");
+                } else if self.exact(Punct(FloatModAssign))?.is_some() {
+                    last_part.push_str("%%=");
                 } else if self.exact(Punct(BitAnd))?.is_some() {
                     last_part.push('&');
                 } else if self.exact(Punct(BitAndAssign))?.is_some() {
@@ -847,6 +940,12 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
                 // `something(` - proc
                 let location = self.location;
                 let parameters = require!(self.separated(Comma, RParen, None, Parser::proc_parameter));
+                let return_type = if let Some(()) = self.exact_ident("as")? {
+                    self.require_version(515, "proc return types");
+                    Some(require!(self.proc_return_type()))
+                } else {
+                    None
+                };
 
                 // split off a subparser so we can keep parsing the objtree
                 // even when the proc body doesn't parse
@@ -872,15 +971,16 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
                 }));
 
                 let code = if self.procs {
-                    let result = {
+                    let (result, statements_bad) = {
                         let mut subparser: Parser<'ctx, '_, '_> = Parser::new(self.context, body_tt);
                         if let Some(a) = self.annotations.as_mut() {
                             subparser.annotations = Some(&mut *a);
                         }
                         let block = subparser.block(&LoopContext::None);
-                        subparser.require(block)
+                        (subparser.require(block), subparser.statements_bad)
                     };
-                    if result.is_ok() {
+                    // bodies with recovered errors are still kept, but not counted as good
+                    if result.is_ok() && statements_bad == 0 {
                         self.procs_good += 1;
                     } else {
                         self.procs_bad += 1;
@@ -895,6 +995,8 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
                             Code::Present(code)
                         }
                     }
+                } else if self.defer_procs {
+                    Code::Deferred(body_tt)
                 } else {
                     Code::Disabled
                 };
@@ -902,6 +1004,7 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
                 match self.tree.add_proc(self.context, location, new_stack.iter(), new_stack.len(), parameters, code) {
                     Ok((idx, proc)) => {
                         proc.docs.extend(comment);
+                        proc.return_type = return_type;
                         // manually performed for borrowck reasons
                         if let Some(dest) = self.annotations.as_mut() {
                             dest.insert(entry_start..body_start, Annotation::ProcHeader(new_stack.to_vec(), idx));
@@ -1066,6 +1169,20 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
         success((input_type, in_list))
     }
 
+    /// Parse the return type annotation of a proc, after the `as`.
+    fn proc_return_type(&mut self) -> Status<ProcReturnType> {
+        // proc/foo() as /obj/item
+        if let Some(()) = self.exact(Token::Punct(Punctuation::Slash))? {
+            let mut path = vec![require!(self.ident())];
+            while let Some(()) = self.exact(Token::Punct(Punctuation::Slash))? {
+                path.push(require!(self.ident()));
+            }
+            return success(ProcReturnType::TypePath(path));
+        }
+        // proc/foo() as num|text
+        success(ProcReturnType::InputType(leading!(self.input_type())))
+    }
+
     /// Parse a verb input type. Used by proc params and the input() form.
     fn input_type(&mut self) -> Status<InputType> {
         let ident = leading!(self.ident());
@@ -1098,8 +1215,23 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
                     break;
                 } else if let Some(()) = self.exact(Token::Punct(Punctuation::Semicolon))? {
                     continue;
-                } else {
-                    statements.push(require!(self.statement(loop_ctx, &mut vars)));
+                }
+                let start = self.updated_location();
+                let declared = self.declared_vars.len();
+                match self.statement(loop_ctx, &mut vars) {
+                    Ok(Some(statement)) => {
+                        self.declared_vars.truncate(declared);
+                        statements.push(statement);
+                    }
+                    other => {
+                        let error = match other {
+                            Err(error) => error,
+                            _ => self.describe_parse_error(),
+                        };
+                        self.recover_statement(error)?;
+                        let declared = self.declared_vars.drain(declared..).collect();
+                        statements.push(Spanned::new(start, Statement::Error(declared)));
+                    }
                 }
             }
             statements
@@ -1108,7 +1240,9 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
             Vec::new()
         } else {
             // and one-line blocks: if(1) neat();
+            let declared = self.declared_vars.len();
             let statement = require!(self.statement(loop_ctx, &mut vars));
+            self.declared_vars.truncate(declared);
             vec![statement]
         };
         for (loc, var_type, name) in vars {
@@ -1117,6 +1251,36 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
         success(result)
     }
 
+    /// Report a statement which failed to parse and skip ahead to the start
+    /// of the next statement in the same block, so that the rest of the
+    /// block can still be parsed.
+    ///
+    /// Fails with the original error if the end of input is reached first.
+    fn recover_statement(&mut self, error: DMError) -> Result<(), DMError> {
+        self.possible_indentation_error = false;
+        loop {
+            match self.next("")? {
+                Token::Eof => return Err(error),
+                Token::Punct(Punctuation::Semicolon) => break,
+                p @ Token::Punct(Punctuation::RBrace) => {
+                    self.put_back(p);
+                    break;
+                }
+                other => if TTKind::from_token(&other).is_some() {
+                    // skip nested blocks and groups as a whole
+                    self.put_back(other);
+                    match self.read_any_tt(&mut Vec::new()) {
+                        Ok(Some(())) => {}
+                        _ => return Err(error),
+                    }
+                },
+            }
+        }
+        self.statements_bad += 1;
+        self.context.register_error(error);
+        Ok(())
+    }
+
     fn statement(&mut self, loop_ctx: &LoopContext, vars: &mut Vec<(Location, VarType, String)>) -> Status<Spanned<Statement>> {
         let start = self.location();
         let spanned = |v| success(Spanned::new(start, v));
@@ -1169,16 +1333,34 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
             // for (Init, Test, Inc) Statement
             // for (Var in Low to High)
             // for (Var = Low to High)
+            // for (Key, Value in List) Statement
             require!(self.exact(Token::Punct(Punctuation::LParen)));
             let init = self.simple_statement(true, vars)?;
             if let Some(()) = self.comma_or_semicolon()? {
                 // three-pronged loop form ("for loop")
+                let value_location = self.updated_location();
                 let test = self.expression()?;
                 let inc = match self.comma_or_semicolon()? {
                     Some(()) => self.simple_statement(false, vars)?,
                     None => None,
                 };
                 require!(self.exact(Token::Punct(Punctuation::RParen)));
+                if inc.is_none() {
+                    if let (Some(init), Some(test)) = (init.as_ref(), test.as_ref()) {
+                        if let Some((var_type, key, value, in_list)) = for_key_value(init, test) {
+                            // key-value form ("for key value")
+                            self.require_version(515, "key-value 'for' loops");
+                            vars.push((value_location, VarType::default(), value.clone()));
+                            return spanned(Statement::ForKeyValue {
+                                var_type,
+                                key,
+                                value,
+                                in_list,
+                                block: require!(self.block(&LoopContext::ForList)),
+                            });
+                        }
+                    }
+                }
                 spanned(Statement::ForLoop {
                     init: init.map(Box::new),
                     test,
@@ -1455,6 +1637,7 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
                 }
                 let var_suffix = require!(self.var_suffix());
                 var_type.suffix(&var_suffix);
+                self.declared_vars.push(VarStatement { var_type: var_type.clone(), name: name.clone(), value: None });
 
                 if self.annotations.is_some() {
                     vars.push((self.location, var_type.clone(), name.clone()));
@@ -1657,9 +1840,18 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
         success(expr)
     }
 
+    fn operator_version(&mut self, token: Punctuation) {
+        match token {
+            Punctuation::FloatMod |
+            Punctuation::FloatModAssign => self.require_version(515, &format!("'{}'", token)),
+            _ => {}
+        }
+    }
+
     fn expression_part(&mut self, lhs: Expression, prev_op: OpInfo, in_ternary: bool) -> Status<Expression> {
         use std::cmp::Ordering;
 
+        self.operator_version(prev_op.token);
         let mut bits = vec![lhs];
         let mut ops = vec![prev_op.oper];
         let mut rhs = require!(self.group(in_ternary));
@@ -1688,6 +1880,7 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
                 }
                 Ordering::Equal => {
                     // the same strength... push it to the list
+                    self.operator_version(info.token);
                     ops.push(info.oper);
                     bits.push(rhs);
                     rhs = require!(self.group(in_ternary));
@@ -1953,6 +2146,16 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
                 }
             },
 
+            // term :: '::' ident arglist?
+            Token::Punct(Punctuation::ColonColon) => {
+                self.require_version(515, "'::'");
+                let i = require!(self.ident());
+                match self.arguments(&[], &i)? {
+                    Some(args) => Term::GlobalCall(i, args),
+                    None => Term::GlobalIdent(i),
+                }
+            },
+
             // term :: '..' arglist
             Token::Punct(Punctuation::Super) => {
                 self.annotate(start, || Annotation::ParentCall);
@@ -2033,11 +2236,17 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
         let first_location = self.updated_location();
         let kind = match self.next("field access")? {
             // follow :: '[' expression ']'
-            Token::Punct(Punctuation::LBracket) => {
+            // follow :: '?[' expression ']'
+            t @ Token::Punct(Punctuation::LBracket) |
+            t @ Token::Punct(Punctuation::SafeLBracket) => {
                 belongs_to.clear();
+                let kind = list_access_kind(&t);
+                if kind == ListAccessKind::Safe {
+                    self.require_version(515, "'?['");
+                }
                 let expr = require!(self.expression());
                 require!(self.exact(Token::Punct(Punctuation::RBracket)));
-                return success(Spanned::new(first_location, Follow::Index(Box::new(expr))))
+                return success(Spanned::new(first_location, Follow::Index(kind, Box::new(expr))))
             }
 
             // follow :: '.' ident arglist?
@@ -2046,9 +2255,13 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
             Token::Punct(Punctuation::CloseColon) if !belongs_to.is_empty() || !in_ternary => IndexKind::Colon,
             Token::Punct(Punctuation::SafeDot) => IndexKind::SafeDot,
             Token::Punct(Punctuation::SafeColon) => IndexKind::SafeColon,
+            Token::Punct(Punctuation::ColonColon) => IndexKind::Scope,
 
             other => return self.try_another(other),
         };
+        if kind == IndexKind::Scope {
+            self.require_version(515, "'::'");
+        }
 
         let mut index_op_loc = self.location;
         let start = self.updated_location();
@@ -2090,11 +2303,17 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
     fn index_or_field(&mut self, belongs_to: &mut Vec<String>, in_ternary: bool) -> Status<IndexOrField> {
         let kind = match self.next("field access")? {
             // follow :: '[' expression ']'
-            Token::Punct(Punctuation::LBracket) => {
+            // follow :: '?[' expression ']'
+            t @ Token::Punct(Punctuation::LBracket) |
+            t @ Token::Punct(Punctuation::SafeLBracket) => {
                 belongs_to.clear();
+                let kind = list_access_kind(&t);
+                if kind == ListAccessKind::Safe {
+                    self.require_version(515, "'?['");
+                }
                 let expr = require!(self.expression());
                 require!(self.exact(Token::Punct(Punctuation::RBracket)));
-                return success(IndexOrField::Index(Box::new(expr)))
+                return success(IndexOrField::Index(kind, Box::new(expr)))
             }
 
             // follow :: '.' ident
@@ -2103,9 +2322,13 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
             Token::Punct(Punctuation::CloseColon) if !belongs_to.is_empty() || !in_ternary => IndexKind::Colon,
             Token::Punct(Punctuation::SafeDot) => IndexKind::SafeDot,
             Token::Punct(Punctuation::SafeColon) => IndexKind::SafeColon,
+            Token::Punct(Punctuation::ColonColon) => IndexKind::Scope,
 
             other => return self.try_another(other),
         };
+        if kind == IndexKind::Scope {
+            self.require_version(515, "'::'");
+        }
 
         let mut index_op_loc = self.location;
         let start = self.updated_location();
@@ -2215,7 +2438,7 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
         // read a single arbitrary "token tree", either a group or a single token
         let start = self.next("anything")?;
         let kind = TTKind::from_token(&start);
-        target.push(LocatedToken::new(self.location(), start));
+        target.push(LocatedToken { location: self.location, token: start, expansion: self.expansion.clone() });
         let kind = match kind {
             Some(k) => k,
             None => return SUCCESS,
@@ -2223,7 +2446,7 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
         loop {
             let token = self.next("anything")?;
             if kind.is_end(&token) {
-                target.push(LocatedToken::new(self.location(), token));
+                target.push(LocatedToken { location: self.location, token, expansion: self.expansion.clone() });
                 return SUCCESS;
             } else {
                 self.put_back(token);
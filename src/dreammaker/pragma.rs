@@ -0,0 +1,173 @@
+//! Support for BYOND's `#pragma` directive.
+//!
+//! BYOND 515 allows the severity of its own warnings to be changed with
+//! `#pragma WarningName level`, and the settings to be saved and restored
+//! with `#pragma push` and `#pragma pop`. Where a BYOND warning means the
+//! same thing as one of SpacemanDMM's errortypes, the pragma applies to both.
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use serde::{Serialize, Deserialize};
+
+use super::{FileId, Location};
+use super::config::WarningLevel;
+
+/// The warnings BYOND allows `#pragma` to configure, and the errortypes which
+/// correspond to each.
+const BYOND_WARNINGS: &[(&str, &[&str])] = &[
+    ("AmbiguousResourcePath", &[]),
+    ("AssignmentInConditional", &[]),
+    ("DanglingVarType", &[]),
+    ("EmptyBlock", &[]),
+    ("EmptyProc", &[]),
+    ("FallbackBuiltinArgument", &[]),
+    ("ImplicitNullType", &[]),
+    ("InvalidIndexOperation", &[]),
+    ("InvalidRange", &[]),
+    ("InvalidSetStatement", &["invalid_set_value"]),
+    ("MalformedRange", &[]),
+    ("MissingInterpolatedExpression", &[]),
+    ("PointlessBuiltinCall", &[]),
+    ("PointlessParentCall", &[]),
+    ("PointlessPositionalArgument", &[]),
+    ("PointlessScopeOperator", &[]),
+    ("ProcArgumentGlobal", &["static_in_proc_parameter"]),
+    ("SoftReservedKeyword", &[]),
+    ("SuspiciousMatrixCall", &[]),
+    ("SuspiciousSemicolon", &[]),
+    ("TooManyArguments", &["too_many_arguments"]),
+    ("UnsafeClientAccess", &[]),
+    ("UnusedVar", &["unused_local", "unused_var"]),
+];
+
+/// Whether BYOND recognizes the given warning name.
+pub fn is_known_warning(name: &str) -> bool {
+    BYOND_WARNINGS.iter().any(|&(each, _)| each == name)
+}
+
+/// The errortypes which a BYOND warning corresponds to.
+pub fn errortypes_of(name: &str) -> &'static [&'static str] {
+    match BYOND_WARNINGS.iter().find(|&&(each, _)| each == name) {
+        Some(&(_, errortypes)) => errortypes,
+        None => &[],
+    }
+}
+
+/// Parse the level half of `#pragma WarningName level`.
+pub fn parse_level(text: &str) -> Option<WarningLevel> {
+    match text {
+        "error" => Some(WarningLevel::Error),
+        "warning" => Some(WarningLevel::Warning),
+        "notice" => Some(WarningLevel::Info),
+        "ignore" | "disabled" => Some(WarningLevel::Disabled),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PragmaEvent {
+    location: Location,
+    warning: String,
+    level: WarningLevel,
+}
+
+/// The `#pragma` settings seen while preprocessing an environment.
+///
+/// A setting applies to everything after it in preprocessing order, so the
+/// `#include`s leading to each file are tracked as well.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Pragmas {
+    events: Vec<PragmaEvent>,
+    /// The location of each `#include` leading to a file, outermost first.
+    include_chains: HashMap<FileId, Vec<Location>>,
+}
+
+impl Pragmas {
+    /// Whether no `#pragma` has changed any warning level.
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub(crate) fn set(&mut self, location: Location, warning: &str, level: WarningLevel) {
+        // preprocessing a file again should not duplicate its pragmas
+        if let Some(event) = self.events.iter_mut().find(|event| event.location == location && event.warning == warning) {
+            event.level = level;
+            return;
+        }
+        self.events.push(PragmaEvent {
+            location,
+            warning: warning.to_owned(),
+            level,
+        });
+    }
+
+    /// Forget the settings made in a file which is about to be preprocessed
+    /// again.
+    pub(crate) fn clear_file(&mut self, file: FileId) {
+        self.events.retain(|event| event.location.file != file);
+    }
+
+    pub(crate) fn include_site(&mut self, file: FileId, location: Location) {
+        let mut chain = self.include_chain(location.file).to_vec();
+        if chain.iter().any(|each| each.file == file) {
+            return;  // a cycle would mean a bad include site
+        }
+        chain.push(location);
+        self.include_chains.insert(file, chain);
+    }
+
+    /// Whether any `#pragma` turns on an errortype.
+    pub fn enables(&self, errortype: &str) -> bool {
+        self.events.iter().any(|event| match event.level {
+            WarningLevel::Disabled | WarningLevel::Unset => false,
+            _ => errortypes_of(&event.warning).iter().any(|&each| each == errortype),
+        })
+    }
+
+    /// The level set by `#pragma` for an errortype at the given location, if
+    /// any.
+    pub fn level(&self, errortype: &str, location: Location) -> Option<WarningLevel> {
+        let mut latest: Option<&PragmaEvent> = None;
+        for event in self.events.iter() {
+            if !errortypes_of(&event.warning).iter().any(|&each| each == errortype) {
+                continue;
+            }
+            if self.compare(event.location, location) != Ordering::Less {
+                continue;
+            }
+            if latest.map_or(true, |latest| self.compare(latest.location, event.location) != Ordering::Greater) {
+                latest = Some(event);
+            }
+        }
+        match latest {
+            Some(event) if event.level != WarningLevel::Unset => Some(event.level),
+            _ => None,
+        }
+    }
+
+    /// Compare two locations by the order in which the preprocessor reached
+    /// them.
+    fn compare(&self, a: Location, b: Location) -> Ordering {
+        let a_sites = self.include_chain(a.file);
+        let b_sites = self.include_chain(b.file);
+        let a_chain = a_sites.iter().chain(Some(&a));
+        let b_chain = b_sites.iter().chain(Some(&b));
+        for (a, b) in a_chain.zip(b_chain) {
+            let ordering = if a.file == b.file {
+                (a.line, a.column).cmp(&(b.line, b.column))
+            } else {
+                a.file.cmp(&b.file)
+            };
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        // an `#include` line comes before the contents of the file
+        a_sites.len().cmp(&b_sites.len())
+    }
+
+    /// The locations of each `#include` leading to a file, outermost first.
+    fn include_chain(&self, file: FileId) -> &[Location] {
+        self.include_chains.get(&file).map_or(&[][..], Vec::as_slice)
+    }
+}
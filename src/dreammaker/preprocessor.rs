@@ -4,13 +4,18 @@ use std::{io, fmt};
 use std::fs::File;
 use std::path::{Path, PathBuf};
 use std::borrow::Cow;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
 
 use interval_tree::{IntervalTree, range};
 
-use super::{DMError, Location, HasLocation, FileId, Context, Severity};
+use super::{DMError, Location, HasLocation, FileId, Context, Severity, MacroExpansion};
 use super::lexer::*;
 use super::docs::{DocComment, DocTarget, DocCollection};
 use super::annotation::*;
+use super::config::{ByondVersion, WarningLevel};
+use super::includes::{IncludeGraph, IncludeKind};
+use super::pragma;
 
 /// The maximum recursion depth of macro expansion.
 const MAX_RECURSION_DEPTH: usize = 32;
@@ -79,53 +84,13 @@ impl DefineHistory {
     pub fn branch_at_file<'ctx2>(&self, file: FileId, context: &'ctx2 Context) -> Preprocessor<'ctx2> {
         let location = Location { file, line: 0, column: 0 };
         let defines = DefineMap::from_history(self, location);
-
-        Preprocessor {
-            context,
-            env_file: self.env_file.clone(),
-            include_stack: Default::default(),
-            include_locations: Default::default(),
-            history: Default::default(),  // TODO: support branching a second time
-            defines,
-            maps: Default::default(),
-            skins: Default::default(),
-            scripts: Default::default(),
-            ifdef_stack: Default::default(),  // should be fine
-            ifdef_history: Default::default(),
-            last_input_loc: location,
-            last_printable_input_loc: location,
-            output: Default::default(),
-            danger_idents: Default::default(),
-            docs_in: Default::default(),
-            docs_out: Default::default(),
-            in_interp_string: 0,
-            annotations: None,
-        }
+        Preprocessor::branch(context, self.env_file.clone(), defines, location)
     }
 
     /// Branch a child preprocessor from this preprocessor's current state.
     pub fn branch_at_end<'ctx2>(&self, context: &'ctx2 Context) -> Preprocessor<'ctx2> {
-        Preprocessor {
-            context,
-            env_file: self.env_file.clone(),
-            include_stack: Default::default(),
-            include_locations: Default::default(),
-            history: Default::default(),  // TODO: support branching a second time
-            defines: DefineMap::from_history(self, self.last_input_loc),
-            maps: Default::default(),
-            skins: Default::default(),
-            scripts: Default::default(),
-            ifdef_stack: Default::default(),  // should be fine
-            ifdef_history: Default::default(),
-            last_input_loc: self.last_input_loc,
-            last_printable_input_loc: self.last_input_loc,
-            output: Default::default(),
-            danger_idents: Default::default(),
-            docs_in: Default::default(),
-            docs_out: Default::default(),
-            in_interp_string: 0,
-            annotations: None,
-        }
+        let defines = DefineMap::from_history(self, self.last_input_loc);
+        Preprocessor::branch(context, self.env_file.clone(), defines, self.last_input_loc)
     }
 }
 
@@ -151,12 +116,20 @@ impl std::ops::DerefMut for DefineHistory {
 #[derive(Debug, Clone, Default)]
 pub struct DefineMap {
     inner: HashMap<String, Vec<(Location, Define)>>,
+    /// Builtin macros left out because the target BYOND version predates
+    /// them, with the version they need.
+    newer: HashMap<String, (ByondVersion, Define)>,
 }
 
 impl DefineMap {
     pub fn with_builtins() -> DefineMap {
+        DefineMap::with_builtins_for(None)
+    }
+
+    /// Create a define map with the builtins of the given BYOND version.
+    pub fn with_builtins_for(version: Option<ByondVersion>) -> DefineMap {
         let mut this = Default::default();
-        super::builtins::default_defines(&mut this);
+        super::builtins::default_defines(&mut this, version);
         this
     }
 
@@ -193,6 +166,17 @@ impl DefineMap {
         result
     }
 
+    /// Record a builtin macro which the target BYOND version lacks.
+    pub(crate) fn insert_newer(&mut self, key: String, since: ByondVersion, define: Define) {
+        self.newer.insert(key, (since, define));
+    }
+
+    /// The BYOND version required by a builtin macro which was left out
+    /// because the target version predates it.
+    pub fn newer_builtin(&self, key: &str) -> Option<ByondVersion> {
+        self.newer.get(key).map(|&(since, _)| since)
+    }
+
     /// Removes a key from the map, returning the value at the key if the key
     /// was previously in the map.
     pub fn remove(&mut self, key: &str) -> Option<(Location, Define)> {
@@ -238,6 +222,15 @@ impl DefineMap {
     */
 }
 
+/// A macro lookup or change, recorded for incremental reprocessing.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum MacroEvent {
+    /// The macro's definition, or lack thereof, was consulted.
+    Use(String),
+    Define(Location, String, Define),
+    Undef(String),
+}
+
 // ----------------------------------------------------------------------------
 // The stack of currently #included files
 
@@ -252,6 +245,8 @@ enum Include<'ctx> {
         name: String,
         location: Location,
         tokens: VecDeque<Token>,
+        /// The chain of macros producing these tokens, if recorded.
+        provenance: Option<Arc<[MacroExpansion]>>,
     },
 }
 
@@ -264,6 +259,13 @@ impl<'ctx> Include<'ctx> {
             path,
         }
     }
+
+    fn file_id(&self) -> FileId {
+        match *self {
+            Include::File { file, .. } => file,
+            Include::Expansion { .. } => unreachable!(),
+        }
+    }
 }
 
 #[derive(Debug, Default)]
@@ -281,6 +283,15 @@ impl<'ctx> IncludeStack<'ctx> {
         "".as_ref()
     }
 
+    fn top_file_id(&self) -> Option<FileId> {
+        for each in self.stack.iter().rev() {
+            if let Include::File { file, .. } = *each {
+                return Some(file);
+            }
+        }
+        None
+    }
+
     fn in_expansion(&self) -> bool {
         match self.stack.last() {
             Some(Include::Expansion { .. }) => true,
@@ -303,9 +314,10 @@ impl<'ctx> Iterator for IncludeStack<'ctx> {
                 Some(&mut Include::Expansion {
                     ref mut tokens,
                     location,
+                    ref provenance,
                     ..
                 }) => match tokens.pop_front() {
-                    Some(token) => return Some(LocatedToken { location, token }),
+                    Some(token) => return Some(LocatedToken { location, token, expansion: provenance.clone() }),
                     None => {} // fall through
                 },
                 None => return None,
@@ -357,11 +369,15 @@ pub struct Preprocessor<'ctx> {
 
     include_stack: IncludeStack<'ctx>,
     include_locations: HashMap<FileId, Location>,
+    include_graph: IncludeGraph,
     last_input_loc: Location,
     output: VecDeque<Token>,
     ifdef_stack: Vec<Ifdef>,
     ifdef_history: IntervalTree<Location, bool>,
     annotations: Option<AnnotationTree>,
+    macro_log: Option<Vec<MacroEvent>>,
+    macro_provenance: bool,
+    last_input_expansion: Option<Arc<[MacroExpansion]>>,
 
     history: InnerDefineHistory,
     defines: DefineMap,
@@ -373,6 +389,9 @@ pub struct Preprocessor<'ctx> {
     danger_idents: HashMap<String, Location>,
     in_interp_string: u32,
 
+    pragma_levels: HashMap<String, WarningLevel>,
+    pragma_stack: Vec<HashMap<String, WarningLevel>>,
+
     docs_in: VecDeque<(Location, DocComment)>,
     docs_out: VecDeque<(Location, DocComment)>,
 }
@@ -399,14 +418,16 @@ impl<'ctx> Preprocessor<'ctx> {
             file.read_to_end(&mut buffer)?;
         }
         let include = Include::from_read(context, env_file.clone(), Box::new(io::Cursor::new(buffer)));
+        let include_graph = IncludeGraph::new(&env_file, include.file_id());
 
         Ok(Preprocessor {
             context,
             env_file,
             include_stack: IncludeStack { stack: vec![include] },
             include_locations: Default::default(),
+            include_graph,
             history: Default::default(),
-            defines: DefineMap::with_builtins(),
+            defines: DefineMap::with_builtins_for(context.config().byond_version()),
             maps: Default::default(),
             skins: Default::default(),
             scripts: Default::default(),
@@ -419,7 +440,12 @@ impl<'ctx> Preprocessor<'ctx> {
             docs_in: Default::default(),
             docs_out: Default::default(),
             in_interp_string: 0,
+            pragma_levels: Default::default(),
+            pragma_stack: Default::default(),
             annotations: None,
+            macro_log: None,
+            macro_provenance: false,
+            last_input_expansion: None,
         })
     }
 
@@ -429,13 +455,15 @@ impl<'ctx> Preprocessor<'ctx> {
             Cow::Owned(s) => Cow::Owned(s.into_bytes()),
         };
         let include = Include::from_read(context, env_file.clone(), Box::new(io::Cursor::new(cow_u8)));
+        let include_graph = IncludeGraph::new(&env_file, include.file_id());
         Preprocessor {
             context,
             env_file,
             include_stack: IncludeStack { stack: vec![include] },
             include_locations: Default::default(),
+            include_graph,
             history: Default::default(),
-            defines: DefineMap::with_builtins(),
+            defines: DefineMap::with_builtins_for(context.config().byond_version()),
             maps: Default::default(),
             skins: Default::default(),
             scripts: Default::default(),
@@ -448,7 +476,43 @@ impl<'ctx> Preprocessor<'ctx> {
             docs_in: Default::default(),
             docs_out: Default::default(),
             in_interp_string: 0,
+            pragma_levels: Default::default(),
+            pragma_stack: Default::default(),
+            annotations: None,
+            macro_log: None,
+            macro_provenance: false,
+            last_input_expansion: None,
+        }
+    }
+
+    /// Create a preprocessor with no input, starting from the given macros.
+    pub(crate) fn branch(context: &'ctx Context, env_file: PathBuf, defines: DefineMap, location: Location) -> Self {
+        Preprocessor {
+            context,
+            env_file,
+            include_stack: Default::default(),
+            include_locations: Default::default(),
+            include_graph: Default::default(),
+            history: Default::default(),  // TODO: support branching a second time
+            defines,
+            maps: Default::default(),
+            skins: Default::default(),
+            scripts: Default::default(),
+            ifdef_stack: Default::default(),  // should be fine
+            ifdef_history: Default::default(),
+            last_input_loc: location,
+            last_printable_input_loc: location,
+            output: Default::default(),
+            danger_idents: Default::default(),
+            docs_in: Default::default(),
+            docs_out: Default::default(),
+            in_interp_string: 0,
+            pragma_levels: Default::default(),
+            pragma_stack: Default::default(),
             annotations: None,
+            macro_log: None,
+            macro_provenance: false,
+            last_input_expansion: None,
         }
     }
 
@@ -511,9 +575,101 @@ impl<'ctx> Preprocessor<'ctx> {
         self.annotations.take()
     }
 
+    /// Include a file as if by `#include`, relative to the environment.
+    pub(crate) fn include_file(&mut self, path: PathBuf) -> Result<FileId, DMError> {
+        let include = self.prepare_include_file(path)?;
+        let file = match include {
+            Include::File { file, .. } => file,
+            Include::Expansion { .. } => unreachable!(),
+        };
+        self.include_stack.stack.push(include);
+        Ok(file)
+    }
+
+    /// Access the graph of files included so far.
+    pub fn include_graph(&self) -> &IncludeGraph {
+        &self.include_graph
+    }
+
+    /// Access the locations each file was included from.
+    pub(crate) fn include_locations_mut(&mut self) -> &mut HashMap<FileId, Location> {
+        &mut self.include_locations
+    }
+
+    /// Record on each token, and in the context by location, the macros whose
+    /// expansion produced it, so that diagnostics inside expansions can say
+    /// where they came from.
+    pub fn enable_macro_provenance(&mut self) {
+        self.macro_provenance = true;
+    }
+
+    /// Begin recording which macros are used, defined, and undefined.
+    pub(crate) fn enable_macro_log(&mut self) {
+        self.macro_log = Some(Vec::new());
+    }
+
+    /// Retrieve the macro events recorded since the last call.
+    pub(crate) fn take_macro_log(&mut self) -> Vec<MacroEvent> {
+        match self.macro_log.as_mut() {
+            Some(log) => std::mem::replace(log, Vec::new()),
+            None => Vec::new(),
+        }
+    }
+
+    /// Preprocess to the end, rendering the fully expanded tokens which
+    /// originate from the given lines of the given file.
+    pub fn expand_lines(mut self, file: &Path, lines: RangeInclusive<u32>) -> String {
+        // Included files are registered relative to the environment.
+        let env_dir = self.env_file.parent().map(Path::to_owned).unwrap_or_default();
+        let relative = file.strip_prefix(&env_dir).unwrap_or(file).to_owned();
+        let context = self.context;
+        let matches = |id: FileId| context.get_file(&relative).or_else(|| context.get_file(file)) == Some(id);
+
+        let mut output = String::new();
+        let mut current: Option<(FileId, bool)> = None;
+        let mut prev: Option<Token> = None;
+        while let Some(LocatedToken { location, token, .. }) = self.next() {
+            let in_file = match current {
+                Some((id, in_file)) if id == location.file => in_file,
+                _ => {
+                    let in_file = matches(location.file);
+                    current = Some((location.file, in_file));
+                    in_file
+                }
+            };
+            if !in_file || !lines.contains(&location.line) {
+                continue;
+            }
+            match token {
+                Token::DocComment(_) => continue,
+                Token::Punct(Punctuation::Newline) => output.push('\n'),
+                Token::Punct(Punctuation::Tab) => output.push('\t'),
+                Token::Punct(Punctuation::Space) => output.push(' '),
+                ref other => {
+                    if prev.as_ref().map_or(false, |prev| other.separate_from(prev)) {
+                        output.push(' ');
+                    }
+                    use std::fmt::Write;
+                    let _ = write!(output, "{}", other);
+                }
+            }
+            prev = Some(token);
+        }
+        if !output.ends_with('\n') {
+            output.push('\n');
+        }
+        output
+    }
+
     // ------------------------------------------------------------------------
     // Macro definition handling
 
+    fn log_macro<F: FnOnce() -> MacroEvent>(&mut self, f: F) {
+        if let Some(log) = self.macro_log.as_mut() {
+            log.push(f());
+        }
+    }
+
     fn annotate_macro(&mut self, ident: &str, def_loc: Location) {
         if self.include_stack.in_expansion() {
             return;
@@ -624,6 +780,8 @@ impl<'ctx> Preprocessor<'ctx> {
         // Make sure the file hasn't already been included.
         // All DM source is effectively `#pragma once`.
         let file_id = self.context.register_file(&register);
+        let parent = self.include_stack.top_file_id();
+        self.include_graph.record(register.to_owned(), IncludeKind::Code, Some(file_id), parent, self.last_input_loc);
         if let Some(&loc) = self.include_locations.get(&file_id) {
             Err(DMError::new(self.last_input_loc, format!("duplicate #include {:?}", path))
                 .set_severity(Severity::Warning)
@@ -631,6 +789,7 @@ impl<'ctx> Preprocessor<'ctx> {
                 .with_errortype("duplicate_include"))
         } else {
             self.include_locations.insert(file_id, self.last_input_loc);
+            self.context.register_include_site(file_id, self.last_input_loc);
             Ok(Include::File {
                 path,
                 file: file_id,
@@ -639,6 +798,29 @@ impl<'ctx> Preprocessor<'ctx> {
         }
     }
 
+    /// Undo the `#pragma` settings made since the matching `#pragma push`.
+    fn restore_pragmas(&mut self, location: Location, saved: HashMap<String, WarningLevel>) {
+        let mut names: Vec<&String> = self.pragma_levels.keys().chain(saved.keys()).collect();
+        names.sort();
+        names.dedup();
+        for name in names {
+            let level = saved.get(name).cloned().unwrap_or(WarningLevel::Unset);
+            if self.pragma_levels.get(name) != Some(&level) {
+                self.context.register_pragma(location, name, level);
+            }
+        }
+        self.pragma_levels = saved;
+    }
+
+    /// Record a map, interface, or script include in the include graph. BYOND
+    /// loads these however many times they are included, so duplicates are
+    /// kept.
+    fn record_resource(&mut self, path: &Path, kind: IncludeKind) {
+        let register = path.strip_prefix(self.env_file.parent().unwrap()).unwrap_or(path).to_owned();
+        let parent = self.include_stack.top_file_id();
+        self.include_graph.record(register, kind, None, parent, self.last_input_loc);
+    }
+
     fn check_danger_ident(&mut self, name: &str, kind: &str) {
         if let Some(loc) = self.danger_idents.get(name) {
             self.context.register_error(DMError::new(*loc, format!(
@@ -652,6 +834,22 @@ impl<'ctx> Preprocessor<'ctx> {
         self.include_stack.next()
     }
 
+    /// The chain of macros for an expansion of `name` at the current input
+    /// token, if provenance is being recorded.
+    fn provenance(&self, name: &str, definition: Location) -> Option<Arc<[MacroExpansion]>> {
+        if !self.macro_provenance {
+            return None;
+        }
+        if definition.is_builtins() {
+            return self.last_input_expansion.clone();
+        }
+        let mut chain: Vec<MacroExpansion> = self.last_input_expansion.as_ref().map_or_else(Vec::new, |chain| chain.to_vec());
+        chain.push(MacroExpansion { name: name.to_owned(), definition });
+        // diagnostics raised after parsing find the chain by location
+        self.context.record_expansion(self.last_input_loc, &chain);
+        Some(chain.into())
+    }
+
     #[allow(unreachable_code)]
     fn real_next(&mut self, read: Token, inside_condition: bool) -> Result<(), DMError> {
         let mut _last_expected_loc = self.last_input_loc;
@@ -677,7 +875,7 @@ impl<'ctx> Preprocessor<'ctx> {
 
         const ALL_DIRECTIVES: &[&str] = &[
             "if", "ifdef", "ifndef", "elif", "else", "endif",
-            "include", "define", "undef", "warn", "error",
+            "include", "define", "undef", "warn", "error", "pragma",
         ];
         let disabled = !inside_condition && self.is_disabled();
         match read {
@@ -698,12 +896,14 @@ impl<'ctx> Preprocessor<'ctx> {
                     "ifdef" => {
                         expect_token!((define_name) = Token::Ident(define_name, _));
                         expect_token!(() = Token::Punct(Punctuation::Newline));
+                        self.log_macro(|| MacroEvent::Use(define_name.clone()));
                         let enabled = self.is_defined(&define_name);
                         self.ifdef_stack.push(Ifdef::new(self.last_input_loc, enabled));
                     }
                     "ifndef" => {
                         expect_token!((define_name) = Token::Ident(define_name, _));
                         expect_token!(() = Token::Punct(Punctuation::Newline));
+                        self.log_macro(|| MacroEvent::Use(define_name.clone()));
                         let enabled = !self.is_defined(&define_name);
                         self.ifdef_stack.push(Ifdef::new(self.last_input_loc, enabled));
                     }
@@ -762,9 +962,18 @@ impl<'ctx> Preprocessor<'ctx> {
                                     return Ok(());
                                 }
                             } {
-                                FileType::DMM => self.maps.push(candidate),
-                                FileType::DMF => self.skins.push(candidate),
-                                FileType::DMS => self.scripts.push(candidate),
+                                FileType::DMM => {
+                                    self.record_resource(&candidate, IncludeKind::Map);
+                                    self.maps.push(candidate);
+                                },
+                                FileType::DMF => {
+                                    self.record_resource(&candidate, IncludeKind::Interface);
+                                    self.skins.push(candidate);
+                                },
+                                FileType::DMS => {
+                                    self.record_resource(&candidate, IncludeKind::Script);
+                                    self.scripts.push(candidate);
+                                },
                                 FileType::DM => match self.prepare_include_file(candidate) {
                                     Ok(include) => {
                                         // A phantom newline keeps the include
@@ -871,6 +1080,8 @@ impl<'ctx> Preprocessor<'ctx> {
                         };
                         // DEBUG can only be defined in the root .dme file
                         if define_name != "DEBUG" || self.in_environment() {
+                            self.log_macro(|| MacroEvent::Use(define_name.clone()));
+                            self.log_macro(|| MacroEvent::Define(define_name_loc, define_name.clone(), define.clone()));
                             if let Some(previous_loc) = self.defines.insert(define_name.clone(), (define_name_loc, define)) {
                                 // DM doesn't issue a warning for this, but it's usually a mistake, so let's.
                                 // FILE_DIR is handled specially and sometimes makes sense to define multiple times.
@@ -890,7 +1101,9 @@ impl<'ctx> Preprocessor<'ctx> {
                         let define_name_loc = _last_expected_loc;
                         self.check_danger_ident(&define_name, "undefined");
                         expect_token!(() = Token::Punct(Punctuation::Newline));
+                        self.log_macro(|| MacroEvent::Use(define_name.clone()));
                         if let Some(previous) = self.defines.remove(&define_name) {
+                            self.log_macro(|| MacroEvent::Undef(define_name.clone()));
                             self.move_to_history(define_name, previous);
                         } else {
                             DMError::new(define_name_loc, format!("macro undefined while not defined: {}", define_name))
@@ -911,6 +1124,46 @@ impl<'ctx> Preprocessor<'ctx> {
                         expect_token!((text) = Token::String(text));
                         self.context.register_error(DMError::new(self.last_input_loc, format!("#{} {}", ident, text)));
                     }
+                    "pragma" if disabled => {}
+                    "pragma" => {
+                        expect_token!((name) = Token::Ident(name, _));
+                        let name_loc = _last_expected_loc;
+                        match &name[..] {
+                            "push" => {
+                                expect_token!(() = Token::Punct(Punctuation::Newline));
+                                self.pragma_stack.push(self.pragma_levels.clone());
+                            }
+                            "pop" => {
+                                expect_token!(() = Token::Punct(Punctuation::Newline));
+                                match self.pragma_stack.pop() {
+                                    Some(saved) => self.restore_pragmas(name_loc, saved),
+                                    None => DMError::new(name_loc, "#pragma pop without matching #pragma push")
+                                        .set_severity(Severity::Warning)
+                                        .with_errortype("invalid_pragma")
+                                        .register(self.context),
+                                }
+                            }
+                            _ => {
+                                expect_token!((level) = Token::Ident(level, _));
+                                expect_token!(() = Token::Punct(Punctuation::Newline));
+                                if !pragma::is_known_warning(&name) {
+                                    DMError::new(name_loc, format!("unknown #pragma: {}", name))
+                                        .set_severity(Severity::Warning)
+                                        .with_errortype("unknown_pragma")
+                                        .register(self.context);
+                                } else if let Some(level) = pragma::parse_level(&level) {
+                                    self.pragma_levels.insert(name.clone(), level);
+                                    self.context.register_pragma(name_loc, &name, level);
+                                } else {
+                                    DMError::new(name_loc, format!("unknown #pragma level: {}", level))
+                                        .set_severity(Severity::Warning)
+                                        .with_note(name_loc, "expected one of: error, warning, notice, ignore")
+                                        .with_errortype("invalid_pragma")
+                                        .register(self.context);
+                                }
+                            }
+                        }
+                    }
                     // none of this other stuff should even exist
                     other => {
                         let mut meant = "";
@@ -955,6 +1208,8 @@ impl<'ctx> Preprocessor<'ctx> {
                     return Ok(());
                 }
 
+                self.log_macro(|| MacroEvent::Use(ident.clone()));
+
                 // special case for inside a defined() call
                 if let Some(Token::Punct(Punctuation::LParen)) = self.output.back() {
                     if let Some(idx) = self.output.len().checked_sub(2) {
@@ -969,6 +1224,19 @@ impl<'ctx> Preprocessor<'ctx> {
 
                 // if it's a define, perform the substitution
                 let mut expansion = self.defines.get(ident).cloned();  // TODO: don't clone?
+                if expansion.is_none() {
+                    // a builtin the target version lacks still expands, so
+                    // that only the version is reported
+                    if let Some((since, define)) = self.defines.newer.get(ident).cloned() {
+                        if let Some(target) = self.context.config().byond_version() {
+                            self.error(format!("{:?} requires BYOND {}, but the target version is {}", ident, since, target))
+                                .set_severity(Severity::Warning)
+                                .with_errortype("byond_version")
+                                .register(self.context);
+                        }
+                        expansion = Some((Location::builtins(), define));
+                    }
+                }
                 if expansion.is_some() && self.include_stack.stack.len() > MAX_RECURSION_DEPTH {
                     self.error(format!("expanding {:?} would exceed max recursion depth of {} levels",
                         ident, MAX_RECURSION_DEPTH)).register(self.context);
@@ -978,10 +1246,12 @@ impl<'ctx> Preprocessor<'ctx> {
                 match expansion {
                     Some((location, Define::Constant { subst, docs: _ })) => {
                         self.annotate_macro(ident, location);
+                        let provenance = self.provenance(ident, location);
                         self.include_stack.stack.push(Include::Expansion {
                             name: ident.to_owned(),
                             tokens: subst.into_iter().collect(),
                             location: self.last_input_loc,
+                            provenance,
                         });
                         return Ok(());
                     }
@@ -1125,10 +1395,12 @@ impl<'ctx> Preprocessor<'ctx> {
                                 _ => expansion.push_back(token),
                             }
                         }
+                        let provenance = self.provenance(ident, location);
                         self.include_stack.stack.push(Include::Expansion {
                             name: ident.to_owned(),
                             tokens: expansion,
                             location: self.last_input_loc,
+                            provenance,
                         });
                         return Ok(());
                     }
@@ -1169,6 +1441,7 @@ impl<'ctx> Iterator for Preprocessor<'ctx> {
                 return Some(LocatedToken {
                     location: self.last_input_loc,
                     token,
+                    expansion: self.last_input_expansion.clone(),
                 });
             }
 
@@ -1185,6 +1458,7 @@ impl<'ctx> Iterator for Preprocessor<'ctx> {
 
                 // update last_input_loc and attempt to process the input token
                 self.last_input_loc = tok.location;
+                self.last_input_expansion = tok.expansion;
                 if let Err(e) = self.real_next(tok.token, false) {
                     self.context.register_error(e);
                 }
@@ -0,0 +1,716 @@
+//! Printing of AST nodes back to DM source code.
+//!
+//! The output does not preserve the formatting of the original source, but
+//! parses back to the same AST. Parentheses are only inserted where operator
+//! precedence requires them.
+use std::fmt::{self, Write};
+
+use crate::ast::*;
+use crate::lexer::{FormatFloat, Quote};
+
+/// Prints AST nodes as DM source to a `fmt::Write`.
+pub struct Printer<W> {
+    out: W,
+    indent: String,
+    depth: usize,
+}
+
+impl<W: Write> Printer<W> {
+    /// Create a printer which indents blocks with tabs.
+    pub fn new(out: W) -> Printer<W> {
+        Printer {
+            out,
+            indent: "\t".to_owned(),
+            depth: 0,
+        }
+    }
+
+    /// Indent blocks with the given string instead of tabs.
+    pub fn with_indent<S: Into<String>>(mut self, indent: S) -> Printer<W> {
+        self.indent = indent.into();
+        self
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    // ------------------------------------------------------------------------
+    // Procs and statements
+
+    /// Print a proc definition, such as `/obj/proc/fire(range = 5) as num`,
+    /// followed by its body.
+    pub fn proc_definition(
+        &mut self,
+        path: &str,
+        parameters: &[Parameter],
+        return_type: Option<&ProcReturnType>,
+        body: &[Spanned<Statement>],
+    ) -> fmt::Result {
+        self.line_start()?;
+        write!(self.out, "{}(", path)?;
+        for (i, parameter) in parameters.iter().enumerate() {
+            if i > 0 {
+                self.out.write_str(", ")?;
+            }
+            self.parameter(parameter)?;
+        }
+        self.out.write_str(")")?;
+        if let Some(return_type) = return_type {
+            write!(self.out, " as {}", return_type)?;
+        }
+        self.out.write_str("\n")?;
+        self.nested(body)
+    }
+
+    pub fn parameter(&mut self, parameter: &Parameter) -> fmt::Result {
+        write!(self.out, "{}{}", parameter.var_type, parameter.name)?;
+        if let Some(default) = &parameter.default {
+            self.out.write_str(" = ")?;
+            self.expression(default)?;
+        }
+        if !parameter.input_type.is_empty() {
+            write!(self.out, " as {}", parameter.input_type)?;
+        }
+        if let Some(in_list) = &parameter.in_list {
+            self.out.write_str(" in ")?;
+            self.expression(in_list)?;
+        }
+        Ok(())
+    }
+
+    /// Print each statement of a block on its own line at the current depth.
+    ///
+    /// Statements which failed to parse are marked with a comment.
+    pub fn block(&mut self, block: &[Spanned<Statement>]) -> fmt::Result {
+        for statement in block {
+            self.statement(&statement.elem)?;
+        }
+        Ok(())
+    }
+
+    fn nested(&mut self, block: &[Spanned<Statement>]) -> fmt::Result {
+        self.depth += 1;
+        let result = self.block(block);
+        self.depth -= 1;
+        result
+    }
+
+    fn line_start(&mut self) -> fmt::Result {
+        for _ in 0..self.depth {
+            self.out.write_str(&self.indent)?;
+        }
+        Ok(())
+    }
+
+    /// Print a statement, including any nested blocks, as one or more lines.
+    pub fn statement(&mut self, statement: &Statement) -> fmt::Result {
+        self.line_start()?;
+        match statement {
+            Statement::While { condition, block } => {
+                self.out.write_str("while(")?;
+                self.expression(condition)?;
+                self.out.write_str(")\n")?;
+                self.nested(block)
+            }
+            Statement::DoWhile { block, condition } => {
+                self.out.write_str("do\n")?;
+                self.nested(block)?;
+                self.line_start()?;
+                self.out.write_str("while(")?;
+                self.expression(condition)?;
+                self.out.write_str(")\n")
+            }
+            Statement::If { arms, else_arm } => {
+                for (i, (condition, block)) in arms.iter().enumerate() {
+                    if i > 0 {
+                        self.line_start()?;
+                        self.out.write_str("else ")?;
+                    }
+                    self.out.write_str("if(")?;
+                    self.expression(&condition.elem)?;
+                    self.out.write_str(")\n")?;
+                    self.nested(block)?;
+                }
+                if let Some(else_arm) = else_arm {
+                    self.line_start()?;
+                    self.out.write_str("else\n")?;
+                    self.nested(else_arm)?;
+                }
+                Ok(())
+            }
+            Statement::ForLoop { init, test, inc, block } => {
+                self.out.write_str("for(")?;
+                if let Some(init) = init {
+                    self.simple_statement(init)?;
+                }
+                self.out.write_str(";")?;
+                if let Some(test) = test {
+                    self.out.write_str(" ")?;
+                    self.expression(test)?;
+                }
+                self.out.write_str(";")?;
+                if let Some(inc) = inc {
+                    self.out.write_str(" ")?;
+                    self.simple_statement(inc)?;
+                }
+                self.out.write_str(")\n")?;
+                self.nested(block)
+            }
+            Statement::ForList { var_type, name, input_type, in_list, block } => {
+                self.out.write_str("for(")?;
+                self.loop_var(var_type.as_ref(), name)?;
+                if !input_type.is_empty() {
+                    write!(self.out, " as {}", input_type)?;
+                }
+                if let Some(in_list) = in_list {
+                    self.out.write_str(" in ")?;
+                    self.expression(in_list)?;
+                }
+                self.out.write_str(")\n")?;
+                self.nested(block)
+            }
+            Statement::ForKeyValue { var_type, key, value, in_list, block } => {
+                self.out.write_str("for(")?;
+                self.loop_var(var_type.as_ref(), key)?;
+                write!(self.out, ", {} in ", value)?;
+                self.expression(in_list)?;
+                self.out.write_str(")\n")?;
+                self.nested(block)
+            }
+            Statement::ForRange { var_type, name, start, end, step, block } => {
+                self.out.write_str("for(")?;
+                self.loop_var(var_type.as_ref(), name)?;
+                self.out.write_str(" = ")?;
+                self.expression(start)?;
+                self.out.write_str(" to ")?;
+                self.expression(end)?;
+                if let Some(step) = step {
+                    self.out.write_str(" step ")?;
+                    self.expression(step)?;
+                }
+                self.out.write_str(")\n")?;
+                self.nested(block)
+            }
+            Statement::Spawn { delay, block } => {
+                self.out.write_str("spawn(")?;
+                if let Some(delay) = delay {
+                    self.expression(delay)?;
+                }
+                self.out.write_str(")\n")?;
+                self.nested(block)
+            }
+            Statement::Switch { input, cases, default } => {
+                self.out.write_str("switch(")?;
+                self.expression(input)?;
+                self.out.write_str(")\n")?;
+                self.depth += 1;
+                for (case_list, block) in cases {
+                    self.line_start()?;
+                    self.out.write_str("if(")?;
+                    for (i, case) in case_list.iter().enumerate() {
+                        if i > 0 {
+                            self.out.write_str(", ")?;
+                        }
+                        match case {
+                            Case::Exact(expr) => self.expression(expr)?,
+                            Case::Range(start, end) => {
+                                self.expression(start)?;
+                                self.out.write_str(" to ")?;
+                                self.expression(end)?;
+                            }
+                        }
+                    }
+                    self.out.write_str(")\n")?;
+                    self.nested(block)?;
+                }
+                if let Some(default) = default {
+                    self.line_start()?;
+                    self.out.write_str("else\n")?;
+                    self.nested(default)?;
+                }
+                self.depth -= 1;
+                Ok(())
+            }
+            Statement::TryCatch { try_block, catch_params, catch_block } => {
+                self.out.write_str("try\n")?;
+                self.nested(try_block)?;
+                self.line_start()?;
+                self.out.write_str("catch")?;
+                if !catch_params.is_empty() {
+                    self.out.write_str("(")?;
+                    for (i, param) in catch_params.iter().enumerate() {
+                        if i > 0 {
+                            self.out.write_str(", ")?;
+                        }
+                        self.out.write_str(&param.join("/"))?;
+                    }
+                    self.out.write_str(")")?;
+                }
+                self.out.write_str("\n")?;
+                self.nested(catch_block)
+            }
+            Statement::Label { name, block } => {
+                writeln!(self.out, "{}:", name)?;
+                self.nested(block)
+            }
+            Statement::Error(_) => self.out.write_str("// statement failed to parse\n"),
+            other => {
+                self.simple_statement(other)?;
+                self.out.write_str("\n")
+            }
+        }
+    }
+
+    /// Print a statement which fits on one line, as in the header of a `for`
+    /// loop. Fails if the statement contains a block.
+    pub fn simple_statement(&mut self, statement: &Statement) -> fmt::Result {
+        match statement {
+            Statement::Expr(expr) => self.expression(expr),
+            Statement::Return(None) => self.out.write_str("return"),
+            Statement::Return(Some(expr)) => {
+                self.out.write_str("return ")?;
+                self.expression(expr)
+            }
+            Statement::Throw(expr) => {
+                self.out.write_str("throw ")?;
+                self.expression(expr)
+            }
+            Statement::Var(var) => {
+                self.out.write_str("var/")?;
+                self.var_statement(var)
+            }
+            Statement::Vars(vars) => {
+                self.out.write_str("var/")?;
+                for (i, var) in vars.iter().enumerate() {
+                    if i > 0 {
+                        self.out.write_str(", ")?;
+                    }
+                    self.var_statement(var)?;
+                }
+                Ok(())
+            }
+            Statement::Setting { name, mode, value } => {
+                write!(self.out, "set {} {} ", name, mode)?;
+                self.expression(value)
+            }
+            Statement::Continue(label) => self.jump("continue", label.as_ref()),
+            Statement::Break(label) => self.jump("break", label.as_ref()),
+            Statement::Goto(label) => write!(self.out, "goto {}", label),
+            Statement::Del(expr) => {
+                self.out.write_str("del ")?;
+                self.expression(expr)
+            }
+            Statement::Crash(expr) => {
+                self.out.write_str("CRASH(")?;
+                self.expression(expr)?;
+                self.out.write_str(")")
+            }
+            _ => Err(fmt::Error),
+        }
+    }
+
+    fn var_statement(&mut self, var: &VarStatement) -> fmt::Result {
+        write!(self.out, "{}{}", var.var_type, var.name)?;
+        if let Some(value) = &var.value {
+            self.out.write_str(" = ")?;
+            self.expression(value)?;
+        }
+        Ok(())
+    }
+
+    fn loop_var(&mut self, var_type: Option<&VarType>, name: &str) -> fmt::Result {
+        match var_type {
+            Some(var_type) => write!(self.out, "var/{}{}", var_type, name),
+            None => self.out.write_str(name),
+        }
+    }
+
+    fn jump(&mut self, keyword: &str, label: Option<&String>) -> fmt::Result {
+        self.out.write_str(keyword)?;
+        if let Some(label) = label {
+            write!(self.out, " {}", label)?;
+        }
+        Ok(())
+    }
+
+    // ------------------------------------------------------------------------
+    // Expressions
+
+    pub fn expression(&mut self, expression: &Expression) -> fmt::Result {
+        match expression {
+            Expression::Base { unary, term, follow } => {
+                let mut prefix = String::new();
+                for op in unary.iter().filter(|op| !is_postfix(**op)) {
+                    // keep `- -x` from becoming `--x`
+                    if prefix.ends_with('-') && op.name().starts_with('-') {
+                        prefix.push(' ');
+                    }
+                    prefix.push_str(op.name());
+                }
+                self.out.write_str(&prefix)?;
+
+                let mut core = String::new();
+                {
+                    let mut printer = Printer::new(&mut core);
+                    let wrap = match term.elem {
+                        // `/obj.name` would be read as one path
+                        Term::Prefab(_) => follow.iter().any(|f| match f.elem {
+                            Follow::Field(kind, _) | Follow::Call(kind, _, _) => kind == IndexKind::Dot || kind == IndexKind::Colon,
+                            Follow::Index(..) => false,
+                        }),
+                        ref other => term_open_ended(other) && (!follow.is_empty() || unary.iter().any(|op| is_postfix(*op))),
+                    };
+                    if wrap {
+                        printer.out.write_str("(")?;
+                        printer.term(&term.elem)?;
+                        printer.out.write_str(")")?;
+                    } else {
+                        printer.term(&term.elem)?;
+                    }
+                    for each in follow {
+                        printer.follow(&each.elem)?;
+                    }
+                }
+                if prefix.ends_with('-') && core.starts_with('-') {
+                    self.out.write_str(" ")?;
+                }
+                self.out.write_str(&core)?;
+
+                for op in unary.iter().filter(|op| is_postfix(**op)) {
+                    self.out.write_str(op.name())?;
+                }
+                Ok(())
+            }
+            Expression::BinaryOp { op: BinaryOp::In, lhs, rhs } => {
+                // `x in 1 to 5` is the one place `to` may appear
+                let rhs_parens = match **rhs {
+                    Expression::BinaryOp { op: BinaryOp::To, .. } => false,
+                    ref rhs => strength(rhs) >= Strength::In,
+                };
+                self.operand(lhs, strength(lhs) > Strength::In || open_ended(lhs))?;
+                self.out.write_str(" in ")?;
+                self.operand(rhs, rhs_parens)
+            }
+            Expression::BinaryOp { op: BinaryOp::To, lhs, rhs } => {
+                self.operand(lhs, strength(lhs) >= Strength::In || open_ended(lhs))?;
+                self.out.write_str(" to ")?;
+                self.expression(rhs)
+            }
+            Expression::BinaryOp { op, lhs, rhs } => {
+                self.infix(binary_strength(*op), false, lhs, &op.to_string(), rhs)
+            }
+            Expression::AssignOp { op, lhs, rhs } => {
+                self.infix(Strength::Assign, true, lhs, &op.to_string(), rhs)
+            }
+            Expression::TernaryOp { cond, if_, else_ } => {
+                self.operand(cond, strength(cond) >= Strength::Conditional || open_ended(cond))?;
+                self.out.write_str(" ? ")?;
+                self.operand(if_, strength(if_) >= Strength::Conditional || open_ended(if_))?;
+                self.out.write_str(" : ")?;
+                // the else arm extends as far to the right as it can
+                self.expression(else_)
+            }
+        }
+    }
+
+    fn infix(&mut self, op_strength: Strength, right_binding: bool, lhs: &Expression, op: &str, rhs: &Expression) -> fmt::Result {
+        let lhs_strength = strength(lhs);
+        let rhs_strength = strength(rhs);
+        self.operand(lhs, lhs_strength > op_strength
+            || (lhs_strength == op_strength && right_binding)
+            || open_ended(lhs))?;
+        write!(self.out, " {} ", op)?;
+        self.operand(rhs, rhs_strength > op_strength
+            || (rhs_strength == op_strength && !right_binding))
+    }
+
+    fn operand(&mut self, expression: &Expression, parens: bool) -> fmt::Result {
+        if parens {
+            self.out.write_str("(")?;
+            self.expression(expression)?;
+            self.out.write_str(")")
+        } else {
+            self.expression(expression)
+        }
+    }
+
+    fn arguments(&mut self, args: &[Expression]) -> fmt::Result {
+        self.out.write_str("(")?;
+        for (i, arg) in args.iter().enumerate() {
+            if i > 0 {
+                self.out.write_str(", ")?;
+            }
+            self.expression(arg)?;
+        }
+        self.out.write_str(")")
+    }
+
+    pub fn term(&mut self, term: &Term) -> fmt::Result {
+        match term {
+            Term::Null => self.out.write_str("null"),
+            Term::Int(i) => write!(self.out, "{}", i),
+            Term::Float(f) if f.is_finite() => write!(self.out, "{:?}", f),
+            Term::Float(f) => write!(self.out, "{}", FormatFloat(*f)),
+            Term::Ident(name) => self.out.write_str(name),
+            Term::GlobalIdent(name) => write!(self.out, "::{}", name),
+            Term::String(string) => write!(self.out, "{}", Quote(string)),
+            Term::Resource(path) => {
+                self.out.write_str("'")?;
+                for ch in path.chars() {
+                    if ch == '\'' || ch == '\\' {
+                        self.out.write_char('\\')?;
+                    }
+                    self.out.write_char(ch)?;
+                }
+                self.out.write_str("'")
+            }
+            Term::As(input_type) => write!(self.out, "as({})", input_type),
+            Term::Expr(expr) => {
+                self.out.write_str("(")?;
+                self.expression(expr)?;
+                self.out.write_str(")")
+            }
+            Term::Prefab(prefab) => self.prefab(prefab),
+            Term::InterpString(first, parts) => {
+                let braced = std::iter::once(first)
+                    .chain(parts.iter().map(|(_, text)| text))
+                    .any(|text| text.contains('"') || text.contains('\n'));
+                self.out.write_str(if braced { "{\"" } else { "\"" })?;
+                self.out.write_str(first)?;
+                for (expr, text) in parts {
+                    self.out.write_str("[")?;
+                    if let Some(expr) = expr {
+                        self.expression(expr)?;
+                    }
+                    self.out.write_str("]")?;
+                    self.out.write_str(text)?;
+                }
+                self.out.write_str(if braced { "\"}" } else { "\"" })
+            }
+            Term::Call(name, args) => {
+                self.out.write_str(name)?;
+                self.arguments(args)
+            }
+            Term::GlobalCall(name, args) => {
+                write!(self.out, "::{}", name)?;
+                self.arguments(args)
+            }
+            Term::SelfCall(args) => {
+                self.out.write_str(".")?;
+                self.arguments(args)
+            }
+            Term::ParentCall(args) => {
+                self.out.write_str("..")?;
+                self.arguments(args)
+            }
+            Term::New { type_, args } => {
+                self.out.write_str("new")?;
+                match type_ {
+                    NewType::Implicit => {}
+                    NewType::Prefab(prefab) => {
+                        self.out.write_str(" ")?;
+                        self.prefab(prefab)?;
+                    }
+                    NewType::MiniExpr { ident, fields } => {
+                        write!(self.out, " {}", ident)?;
+                        for field in fields {
+                            match field {
+                                IndexOrField::Index(kind, expr) => self.index(*kind, expr)?,
+                                IndexOrField::Field(kind, name) => write!(self.out, "{}{}", kind, name)?,
+                            }
+                        }
+                    }
+                }
+                match args {
+                    Some(args) => self.arguments(args),
+                    None => Ok(()),
+                }
+            }
+            Term::List(args) => {
+                self.out.write_str("list")?;
+                self.arguments(args)
+            }
+            Term::Input { args, input_type, in_list } => {
+                self.out.write_str("input")?;
+                self.arguments(args)?;
+                if !input_type.is_empty() {
+                    write!(self.out, " as {}", input_type)?;
+                }
+                if let Some(in_list) = in_list {
+                    self.out.write_str(" in ")?;
+                    self.expression(in_list)?;
+                }
+                Ok(())
+            }
+            Term::Locate { args, in_list } => {
+                self.out.write_str("locate")?;
+                self.arguments(args)?;
+                if let Some(in_list) = in_list {
+                    self.out.write_str(" in ")?;
+                    self.expression(in_list)?;
+                }
+                Ok(())
+            }
+            Term::Pick(choices) => {
+                self.out.write_str("pick(")?;
+                for (i, (weight, value)) in choices.iter().enumerate() {
+                    if i > 0 {
+                        self.out.write_str(", ")?;
+                    }
+                    if let Some(weight) = weight {
+                        self.expression(weight)?;
+                        self.out.write_str("; ")?;
+                    }
+                    self.expression(value)?;
+                }
+                self.out.write_str(")")
+            }
+            Term::DynamicCall(lhs_args, rhs_args) => {
+                self.out.write_str("call")?;
+                self.arguments(lhs_args)?;
+                self.arguments(rhs_args)
+            }
+        }
+    }
+
+    pub fn follow(&mut self, follow: &Follow) -> fmt::Result {
+        match follow {
+            Follow::Index(kind, expr) => self.index(*kind, expr),
+            Follow::Field(kind, name) => write!(self.out, "{}{}", kind, name),
+            Follow::Call(kind, name, args) => {
+                write!(self.out, "{}{}", kind, name)?;
+                self.arguments(args)
+            }
+        }
+    }
+
+    fn index(&mut self, kind: ListAccessKind, expr: &Expression) -> fmt::Result {
+        self.out.write_str(kind.name())?;
+        self.expression(expr)?;
+        self.out.write_str("]")
+    }
+
+    pub fn prefab(&mut self, prefab: &Prefab) -> fmt::Result {
+        write!(self.out, "{}", FormatTypePath(&prefab.path))?;
+        if !prefab.vars.is_empty() {
+            self.out.write_str("{")?;
+            for (i, (name, value)) in prefab.vars.iter().enumerate() {
+                if i > 0 {
+                    self.out.write_str("; ")?;
+                }
+                write!(self.out, "{} = ", name)?;
+                self.expression(value)?;
+            }
+            self.out.write_str("}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Print a block of statements as DM source, indented with tabs.
+pub fn block_to_string(block: &[Spanned<Statement>]) -> Result<String, fmt::Error> {
+    let mut out = String::new();
+    Printer::new(&mut out).block(block)?;
+    Ok(out)
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Printer::new(f).expression(self)
+    }
+}
+
+impl fmt::Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Printer::new(f).term(self)
+    }
+}
+
+impl fmt::Display for Follow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Printer::new(f).follow(self)
+    }
+}
+
+impl fmt::Display for Prefab {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Printer::new(f).prefab(self)
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Precedence
+
+/// How tightly an expression binds, mirroring the parser's operator table.
+/// Earlier variants bind more tightly.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Strength {
+    Term,
+    Pow,
+    Mul,
+    Add,
+    Compare,
+    Shift,
+    Equality,
+    Bitwise,
+    And,
+    Or,
+    Conditional,
+    Assign,
+    In,
+}
+
+fn binary_strength(op: BinaryOp) -> Strength {
+    match op {
+        BinaryOp::Pow => Strength::Pow,
+        BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod | BinaryOp::FloatMod => Strength::Mul,
+        BinaryOp::Add | BinaryOp::Sub => Strength::Add,
+        BinaryOp::Less | BinaryOp::Greater | BinaryOp::LessEq | BinaryOp::GreaterEq => Strength::Compare,
+        BinaryOp::LShift | BinaryOp::RShift => Strength::Shift,
+        BinaryOp::Eq | BinaryOp::NotEq | BinaryOp::Equiv | BinaryOp::NotEquiv => Strength::Equality,
+        BinaryOp::BitAnd | BinaryOp::BitXor | BinaryOp::BitOr => Strength::Bitwise,
+        BinaryOp::And => Strength::And,
+        BinaryOp::Or => Strength::Or,
+        BinaryOp::In | BinaryOp::To => Strength::In,
+    }
+}
+
+fn strength(expression: &Expression) -> Strength {
+    match expression {
+        Expression::Base { .. } => Strength::Term,
+        Expression::BinaryOp { op, .. } => binary_strength(*op),
+        Expression::AssignOp { .. } => Strength::Assign,
+        Expression::TernaryOp { .. } => Strength::Conditional,
+    }
+}
+
+fn is_postfix(op: UnaryOp) -> bool {
+    match op {
+        UnaryOp::PostIncr | UnaryOp::PostDecr => true,
+        _ => false,
+    }
+}
+
+/// Whether a term ends with a clause that would absorb anything printed
+/// after it, like the `in` of `locate(x) in y`.
+fn term_open_ended(term: &Term) -> bool {
+    match term {
+        Term::Input { in_list, .. } |
+        Term::Locate { in_list, .. } => in_list.is_some(),
+        _ => false,
+    }
+}
+
+/// Whether an expression must be parenthesized when anything follows it.
+fn open_ended(expression: &Expression) -> bool {
+    match expression {
+        Expression::Base { unary, term, follow } => {
+            follow.is_empty() && !unary.iter().any(|op| is_postfix(*op)) && term_open_ended(&term.elem)
+        }
+        Expression::BinaryOp { op: BinaryOp::To, .. } => true,
+        Expression::BinaryOp { rhs, .. } |
+        Expression::AssignOp { rhs, .. } => open_ended(rhs),
+        Expression::TernaryOp { .. } => true,
+    }
+}
@@ -0,0 +1,195 @@
+//! Comments which suppress diagnostics on nearby lines.
+//!
+//! `// dreamchecker-ignore: errortype, ...` at the end of a line suppresses
+//! the listed errortypes on that line, and on a line by itself suppresses them
+//! on the next line of code. `// dreamchecker-ignore-next-block: errortype`
+//! suppresses them on the next line of code and every more-indented line
+//! after it.
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use serde::{Serialize, Deserialize};
+
+use super::{DMError, FileId, Location};
+
+const IGNORE: &str = "dreamchecker-ignore";
+const IGNORE_NEXT_BLOCK: &str = "dreamchecker-ignore-next-block";
+
+/// The kinds of suppression comment.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SuppressionKind {
+    /// Applies to a single line.
+    Line,
+    /// Applies to a line and the block indented beneath it.
+    Block,
+}
+
+/// Parse the text of a line comment, without the leading `//`.
+///
+/// Returns `None` if the comment is not a suppression, and an empty list of
+/// errortypes if it is one but names none.
+pub fn parse_comment(text: &str) -> Option<(SuppressionKind, Vec<String>)> {
+    let text = text.trim();
+    let (kind, rest) = if text.starts_with(IGNORE_NEXT_BLOCK) {
+        (SuppressionKind::Block, &text[IGNORE_NEXT_BLOCK.len()..])
+    } else if text.starts_with(IGNORE) {
+        (SuppressionKind::Line, &text[IGNORE.len()..])
+    } else {
+        return None;
+    };
+    if rest.is_empty() {
+        return Some((kind, Vec::new()));
+    }
+    if !rest.starts_with(':') {
+        // some other word which happens to share the prefix
+        if !rest.starts_with(char::is_whitespace) {
+            return None;
+        }
+        return Some((kind, Vec::new()));
+    }
+    let errortypes = rest[1..]
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|each| !each.is_empty())
+        .map(ToOwned::to_owned)
+        .collect();
+    Some((kind, errortypes))
+}
+
+/// A single suppression comment.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Suppression {
+    /// The location of the comment.
+    location: Location,
+    /// The errortypes suppressed, and whether each has matched a diagnostic.
+    ///
+    /// Matches are recorded while only a read lock is held.
+    errortypes: Vec<(String, AtomicBool)>,
+    /// The first line suppressed, once the lexer has reached it.
+    first_line: Option<u32>,
+    /// The last line suppressed, or `None` to continue to the end of the file.
+    last_line: Option<u32>,
+}
+
+impl Suppression {
+    fn covers(&self, location: Location) -> bool {
+        location.file == self.location.file
+            && self.first_line.map_or(false, |first| first <= location.line)
+            && self.last_line.map_or(true, |last| location.line <= last)
+    }
+}
+
+/// The suppression comments seen while lexing an environment.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Suppressions {
+    entries: Vec<Suppression>,
+    /// The suppressions in each file.
+    files: HashMap<FileId, Vec<usize>>,
+    /// The suppressions covering a single line, by file and line.
+    lines: HashMap<(FileId, u32), Vec<usize>>,
+    /// The other suppressions which have begun, by file.
+    blocks: HashMap<FileId, Vec<usize>>,
+}
+
+impl Suppressions {
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Add a suppression and return its index, or the index of the existing
+    /// one if the same comment is lexed twice.
+    pub(crate) fn add(&mut self, location: Location, errortypes: Vec<String>) -> usize {
+        let entries = &self.entries;
+        let in_file = self.files.entry(location.file).or_insert_with(Vec::new);
+        if let Some(&idx) = in_file.iter().find(|&&idx| entries[idx].location == location) {
+            return idx;
+        }
+        in_file.push(self.entries.len());
+        self.entries.push(Suppression {
+            location,
+            errortypes: errortypes.into_iter().map(|each| (each, AtomicBool::new(false))).collect(),
+            first_line: None,
+            last_line: None,
+        });
+        self.entries.len() - 1
+    }
+
+    /// Set the first line a suppression applies to. A suppression which has
+    /// already ended on that line covers only it.
+    pub(crate) fn begin(&mut self, idx: usize, line: u32) {
+        let entry = &mut self.entries[idx];
+        if entry.first_line.is_some() {
+            return;  // the same comment lexed twice
+        }
+        entry.first_line = Some(line);
+        self.index(idx);
+    }
+
+    fn index(&mut self, idx: usize) {
+        let entry = &self.entries[idx];
+        match (entry.first_line, entry.last_line) {
+            (Some(first), Some(last)) if first == last => {
+                self.lines.entry((entry.location.file, first)).or_insert_with(Vec::new).push(idx);
+            }
+            (Some(_), _) => {
+                self.blocks.entry(entry.location.file).or_insert_with(Vec::new).push(idx);
+            }
+            (None, _) => {}
+        }
+    }
+
+    pub(crate) fn end(&mut self, idx: usize, line: u32) {
+        self.entries[idx].last_line = Some(line);
+    }
+
+    /// Forget the suppressions in a file which is about to be lexed again.
+    pub(crate) fn clear_file(&mut self, file: FileId) {
+        if self.files.remove(&file).is_none() {
+            return;
+        }
+        self.entries.retain(|entry| entry.location.file != file);
+        self.files.clear();
+        self.lines.clear();
+        self.blocks.clear();
+        for idx in 0..self.entries.len() {
+            self.files.entry(self.entries[idx].location.file).or_insert_with(Vec::new).push(idx);
+            self.index(idx);
+        }
+    }
+
+    /// Whether a diagnostic is suppressed. Every suppression which matches is
+    /// marked as used.
+    pub(crate) fn suppresses(&self, error: &DMError) -> bool {
+        guard!(let Some(errortype) = error.errortype() else { return false });
+        let location = error.location();
+        let lines = self.lines.get(&(location.file, location.line));
+        let blocks = self.blocks.get(&location.file);
+        let mut found = false;
+        for &idx in lines.into_iter().chain(blocks).flatten() {
+            let entry = &self.entries[idx];
+            if !entry.covers(location) {
+                continue;
+            }
+            for (each, used) in entry.errortypes.iter() {
+                if each == errortype {
+                    used.store(true, Ordering::Relaxed);
+                    found = true;
+                }
+            }
+        }
+        found
+    }
+
+    /// The location of each suppression comment, and the errortypes it names
+    /// which have not matched any diagnostic.
+    pub fn unused(&self) -> Vec<(Location, &str)> {
+        let mut unused = Vec::new();
+        for entry in self.entries.iter() {
+            for (each, used) in entry.errortypes.iter() {
+                if !used.load(Ordering::Relaxed) {
+                    unused.push((entry.location, &each[..]));
+                }
+            }
+        }
+        unused
+    }
+}
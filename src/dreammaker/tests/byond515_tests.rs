@@ -0,0 +1,137 @@
+extern crate dreammaker as dm;
+
+use dm::ast::*;
+use dm::constants::Constant;
+use dm::lexer::Lexer;
+use dm::objtree::{Code, ObjectTree};
+use dm::parser::*;
+
+fn parse_expr(f: &str) -> Expression {
+    let context = Default::default();
+    let lexer = Lexer::new(&context, Default::default(), f.bytes().map(Ok));
+    let result = parse_expression(&context, Default::default(), lexer).expect("failed to parse expression");
+    context.assert_success();
+    result
+}
+
+fn parse_tree(code: &'static str) -> ObjectTree {
+    let context = dm::Context::default();
+    let pp = dm::preprocessor::Preprocessor::from_buffer(&context, "byond515.dm".into(), code);
+    let mut parser = Parser::new(&context, dm::indents::IndentProcessor::new(&context, pp));
+    parser.enable_procs();
+    let tree = parser.parse_object_tree();
+    context.assert_success();
+    tree
+}
+
+fn follows(expr: Expression) -> Vec<Follow> {
+    match expr {
+        Expression::Base { follow, .. } => follow.into_iter().map(|f| f.elem).collect(),
+        other => panic!("not a base expression: {:?}", other),
+    }
+}
+
+#[test]
+fn float_modulo() {
+    assert_eq!(
+        parse_expr("a %% b"),
+        Expression::BinaryOp {
+            op: BinaryOp::FloatMod,
+            lhs: Box::new(Expression::from(Term::Ident("a".to_owned()))),
+            rhs: Box::new(Expression::from(Term::Ident("b".to_owned()))),
+        }
+    );
+    match parse_expr("a %%= 2") {
+        Expression::AssignOp { op: AssignOp::FloatModAssign, .. } => {}
+        other => panic!("unexpected {:?}", other),
+    }
+    assert_eq!(
+        dm::constants::evaluate_str(Default::default(), b"-7 %% 3").expect("evaluation failed"),
+        Constant::Float(2.),
+    );
+}
+
+#[test]
+fn safe_index() {
+    assert_eq!(follows(parse_expr("L?[1]")), vec![
+        Follow::Index(ListAccessKind::Safe, Box::new(Expression::from(Term::Int(1)))),
+    ]);
+    assert_eq!(follows(parse_expr("L[1]")), vec![
+        Follow::Index(ListAccessKind::Normal, Box::new(Expression::from(Term::Int(1)))),
+    ]);
+}
+
+#[test]
+fn scope_operator() {
+    assert_eq!(follows(parse_expr("/obj::name")), vec![
+        Follow::Field(IndexKind::Scope, "name".to_owned()),
+    ]);
+    assert_eq!(follows(parse_expr("src::fire()")), vec![
+        Follow::Call(IndexKind::Scope, "fire".to_owned(), vec![]),
+    ]);
+    assert_eq!(parse_expr("::glob"), Expression::from(Term::GlobalIdent("glob".to_owned())));
+    assert_eq!(parse_expr("::helper(1)"), Expression::from(Term::GlobalCall("helper".to_owned(), vec![
+        Expression::from(Term::Int(1)),
+    ])));
+}
+
+#[test]
+fn proc_return_types() {
+    let tree = parse_tree(r#"
+/obj/proc/make() as /obj/item
+    return new /obj/item
+/obj/proc/count() as num
+    return 1
+/obj/item
+"#.trim());
+    let obj = tree.expect("/obj");
+    assert_eq!(
+        obj.get_proc("make").unwrap().get().return_type,
+        Some(ProcReturnType::TypePath(vec!["obj".to_owned(), "item".to_owned()])),
+    );
+    assert_eq!(
+        obj.get_proc("count").unwrap().get().return_type,
+        Some(ProcReturnType::InputType(InputType::NUM)),
+    );
+}
+
+#[test]
+fn key_value_loop() {
+    let tree = parse_tree(r#"
+/proc/walk(list/L)
+    for(var/k, v in L)
+        world.log << "[k]=[v?[1]]"
+"#.trim());
+    let block = match tree.root().get_proc("walk").unwrap().get().code {
+        Code::Present(ref block) => block.clone(),
+        ref other => panic!("unexpected {:?}", other),
+    };
+    match block[0].elem {
+        Statement::ForKeyValue { ref var_type, ref key, ref value, .. } => {
+            assert!(var_type.is_some());
+            assert_eq!(key, "k");
+            assert_eq!(value, "v");
+        }
+        ref other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn compile_time_names() {
+    let tree = parse_tree(r#"
+/obj/item
+    var/kind = __TYPE__
+    var/label = nameof(src.name)
+    var/base = /obj/item::name
+    name = "thing"
+/obj/item/sub
+"#.trim());
+    let item = tree.expect("/obj/item");
+    let value = |name: &str| item.get_value(name).and_then(|v| v.constant.clone());
+    match value("kind") {
+        Some(Constant::Prefab(pop)) => assert_eq!(pop.path, vec!["obj".to_owned(), "item".to_owned()]),
+        other => panic!("unexpected {:?}", other),
+    }
+    assert_eq!(value("label"), Some(Constant::String("name".to_owned())));
+    assert_eq!(value("base"), Some(Constant::String("thing".to_owned())));
+}
@@ -0,0 +1,101 @@
+extern crate dreammaker as dm;
+
+mod common;
+
+use dm::config::ByondVersion;
+use dm::lexer::Token;
+use dm::objtree::ObjectTree;
+use dm::preprocessor::{Define, DefineMap};
+
+fn targeting(name: &str, version: &str) -> dm::Context {
+    common::configured(&format!("version-{}", name), &format!("[environment]\nbyond_version = \"{}\"\n", version))
+}
+
+fn parse_tree(context: &dm::Context, code: &'static str) -> ObjectTree {
+    let pp = dm::preprocessor::Preprocessor::from_buffer(context, "version.dm".into(), code);
+    let mut parser = dm::parser::Parser::new(context, dm::indents::IndentProcessor::new(context, pp));
+    parser.enable_procs();
+    parser.parse_object_tree()
+}
+
+fn version_warnings(context: &dm::Context) -> Vec<String> {
+    context.errors().iter()
+        .filter(|e| e.errortype() == Some("byond_version"))
+        .map(|e| e.description().to_owned())
+        .collect()
+}
+
+#[test]
+fn parse_version() {
+    assert_eq!("514.1589".parse::<ByondVersion>(), Ok(ByondVersion::new(514, 1589)));
+    assert_eq!("513".parse::<ByondVersion>(), Ok(ByondVersion::new(513, 0)));
+    assert!("five".parse::<ByondVersion>().is_err());
+    assert!(ByondVersion::new(514, 1589) < ByondVersion::new(515, 1));
+    assert!(ByondVersion::new(514, 1589).supports(514, 1500));
+    assert!(!ByondVersion::new(514, 1500).supports(514, 1589));
+    assert!(!ByondVersion::new(514, 1589).supports(515, 0));
+}
+
+#[test]
+fn version_defines() {
+    let defines = DefineMap::with_builtins_for(Some(ByondVersion::new(512, 1488)));
+    match defines.get("DM_VERSION") {
+        Some((_, Define::Constant { subst, .. })) => assert_eq!(subst, &[Token::Int(512)]),
+        other => panic!("unexpected {:?}", other),
+    }
+    assert!(!defines.contains_key("VIS_HIDE"));
+    assert!(DefineMap::with_builtins().contains_key("VIS_HIDE"));
+}
+
+#[test]
+fn version_builtins() {
+    let context = targeting("builtins", "514.1589");
+    let tree = parse_tree(&context, "/obj\n");
+    assert!(tree.root().get_proc("arctan").is_some());
+    assert!(tree.root().get_proc("nameof").is_none());
+
+    let context = targeting("old-builtins", "512.1488");
+    let tree = parse_tree(&context, "/obj\n");
+    assert!(tree.root().get_proc("arctan").is_none());
+    assert!(tree.expect("/atom").get_value("render_target").is_none());
+    assert_eq!(tree.newer_builtin_proc(tree.root(), "arctan"), Some(ByondVersion::new(513, 0)));
+    assert_eq!(tree.newer_builtin_proc(tree.expect("/regex"), "Find_char"), Some(ByondVersion::new(513, 0)));
+    assert_eq!(tree.newer_builtin_proc(tree.expect("/obj"), "missing"), None);
+
+    let tree = ObjectTree::with_builtins();
+    assert!(tree.root().get_proc("nameof").is_some());
+}
+
+#[test]
+fn newer_syntax() {
+    let code = r#"
+/proc/helper()
+/proc/test(list/L)
+    var/x = 7 %% 2
+    x = L?[1]
+    x = ::helper()
+"#.trim();
+
+    let context = targeting("syntax", "514.1589");
+    parse_tree(&context, code);
+    assert_eq!(version_warnings(&context), vec![
+        "'%%' requires BYOND 515, but the target version is 514.1589",
+        "'?[' requires BYOND 515, but the target version is 514.1589",
+        "'::' requires BYOND 515, but the target version is 514.1589",
+    ]);
+
+    let context = targeting("newer-macro", "512.1488");
+    parse_tree(&context, "/obj/var/flags = VIS_HIDE\n#ifdef VIS_HIDE\n#error defined\n#endif\n");
+    assert_eq!(version_warnings(&context), vec![
+        "\"VIS_HIDE\" requires BYOND 513, but the target version is 512.1488",
+    ]);
+    assert_eq!(context.errors().len(), 1);
+
+    let context = targeting("new-syntax", "515.1630");
+    parse_tree(&context, code);
+    context.assert_success();
+
+    let context = dm::Context::default();
+    parse_tree(&context, code);
+    context.assert_success();
+}
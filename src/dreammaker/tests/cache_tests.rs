@@ -0,0 +1,87 @@
+extern crate dreammaker as dm;
+
+mod common;
+
+use std::fs;
+
+use dm::constants::Constant;
+
+#[test]
+fn round_trip_and_invalidate() {
+    let dir = common::project("cache", &[]);
+    let dme = dir.join("env.dme");
+    let cache = dir.join("env.cache");
+    fs::write(&dme, "#include \"a.dm\"\n").unwrap();
+    fs::write(dir.join("a.dm"), "#warn \"careful\"\n/obj/a\n\tvar/v = 2 * 3\n/obj/a/proc/f()\n\treturn v\n").unwrap();
+
+    let context = dm::Context::default();
+    let parsed = dm::cache::parse_environment(&context, &dme, &cache, true).unwrap();
+    assert_eq!(context.errors().len(), 1);
+    assert!(cache.exists());
+
+    // A fresh context loads the same tree and diagnostics.
+    let context = dm::Context::default();
+    let loaded = dm::cache::load(&context, &dme, &cache, true).expect("cache was not used");
+    assert_eq!(loaded.types.len(), parsed.types.len());
+    let a = loaded.expect("/obj/a");
+    assert_eq!(a.get_value("v").and_then(|v| v.constant.clone()), Some(Constant::Float(6.)));
+    assert!(a.get_proc("f").is_some());
+    assert_eq!(context.errors().len(), 1);
+    assert_eq!(context.errors()[0].description(), "#warn careful");
+    assert_eq!(context.file_path(context.errors()[0].location().file), std::path::PathBuf::from("a.dm"));
+
+    // Changing any file invalidates the cache.
+    fs::write(dir.join("a.dm"), "/obj/a\n\tvar/v = 7\n").unwrap();
+    assert!(dm::cache::load(&dm::Context::default(), &dme, &cache, true).is_none());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn expansions_survive_the_cache() {
+    let dir = common::project("cache-expansions", &[]);
+    let dme = dir.join("env.dme");
+    let cache = dir.join("env.cache");
+    fs::write(&dme, "#define TWICE(x) x * 2\n/proc/f()\n\treturn TWICE(1)\n").unwrap();
+
+    let notes = |context: &dm::Context, tree: &dm::objtree::ObjectTree| {
+        let location = match tree.root().get_proc("f").unwrap().get().code {
+            dm::objtree::Code::Present(ref block) => block[0].location,
+            ref other => panic!("unexpected {:?}", other),
+        };
+        context.register_error(dm::DMError::new(location, "checked later"));
+        context.errors().last().unwrap().notes().len()
+    };
+
+    // both a fresh parse and a cached one record where the macro came from
+    let context = dm::Context::default();
+    let parsed = dm::cache::parse_environment(&context, &dme, &cache, true).unwrap();
+    assert_eq!(notes(&context, &parsed), 1);
+
+    let context = dm::Context::default();
+    let loaded = dm::cache::load(&context, &dme, &cache, true).expect("cache was not used");
+    assert_eq!(notes(&context, &loaded), 1);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn config_changes_invalidate() {
+    let dir = common::project("cache-config", &[]);
+    let dme = dir.join("env.dme");
+    let cache = dir.join("env.cache");
+    let config = dir.join("SpacemanDMM.toml");
+    fs::write(&dme, "#warn \"careful\"\n").unwrap();
+
+    let context = dm::Context::default();
+    dm::cache::parse_environment(&context, &dme, &cache, true).unwrap();
+    assert!(dm::cache::load(&dm::Context::default(), &dme, &cache, true).is_some());
+
+    // The stored diagnostics were kept under the old settings.
+    fs::write(&config, "[display]\nerror_level = \"error\"\n").unwrap();
+    let context = dm::Context::default();
+    context.force_config(&config);
+    assert!(dm::cache::load(&context, &dme, &cache, true).is_none());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
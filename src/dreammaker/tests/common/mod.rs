@@ -0,0 +1,33 @@
+//! Fixtures shared by the integration tests.
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::PathBuf;
+
+/// A path in the temporary directory unique to this test run.
+pub fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("dm-{}-{}", name, std::process::id()))
+}
+
+/// Write the given files into a fresh temporary directory.
+pub fn project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let root = temp_path(name);
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root).unwrap();
+    for &(path, contents) in files {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+    root
+}
+
+/// A context configured by the given `SpacemanDMM.toml` contents.
+pub fn configured(name: &str, toml: &str) -> dm::Context {
+    let path = temp_path(name).with_extension("toml");
+    fs::write(&path, toml).unwrap();
+    let context = dm::Context::default();
+    context.force_config(&path);
+    fs::remove_file(&path).unwrap();
+    context
+}
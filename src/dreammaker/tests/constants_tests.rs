@@ -13,3 +13,86 @@ fn floating_point_rgb() {
         Constant::String("#7f7f7f".to_owned()),
     );
 }
+
+fn eval(code: &str) -> Constant {
+    dm::constants::evaluate_str(Default::default(), code.as_bytes())
+        .unwrap_or_else(|e| panic!("evaluating {:?} failed: {}", code, e))
+}
+
+fn assert_matrix(constant: Constant, expected: [f32; 6]) {
+    let matrix = constant.as_matrix().expect("not a matrix");
+    for (actual, expected) in matrix.iter().zip(expected.iter()) {
+        assert!((actual - expected).abs() < 1e-5, "{:?} != {:?}", matrix, expected);
+    }
+}
+
+#[test]
+fn rgb_alpha() {
+    assert_eq!(eval("rgb(255, 0, 0, 128)"), Constant::string("#ff000080"));
+    assert_eq!(eval("rgb(r = 1, g = 2, b = 3, a = null)"), Constant::string("#010203"));
+}
+
+#[test]
+fn embedded_strings() {
+    assert_eq!(eval("\"[1 + 2] and [0.5] of [/obj/item][null]\""), Constant::string("3 and 0.5 of /obj/item"));
+    assert!(dm::constants::evaluate_str(Default::default(), b"\"\\the [\"x\"]\"").is_err());
+}
+
+#[test]
+fn list_arithmetic() {
+    assert_eq!(eval("list(1, 2) + list(3) + 4"), eval("list(1, 2, 3, 4)"));
+    assert_eq!(eval("list(1, 2, 1) - 1"), eval("list(1, 2)"));
+    assert_eq!(eval("list(1, 2) | list(2, 3)"), eval("list(1, 2, 3)"));
+    assert_eq!(eval("list(1, 2, 3) & list(3, 1)"), eval("list(1, 3)"));
+}
+
+#[test]
+fn text_and_numbers() {
+    assert_eq!(eval("text2num(\"12.5kg\")"), Constant::Float(12.5));
+    assert_eq!(eval("text2num(\"ff\", 16)"), Constant::Int(255));
+    assert_eq!(eval("text2num(\"none\")"), Constant::Null(None));
+    assert_eq!(eval("ascii2text(65)"), Constant::string("A"));
+    assert_eq!(eval("text2ascii(\"abc\", 2)"), Constant::Int(98));
+    assert_eq!(eval("uppertext(copytext(\"hello\", 2, 4))"), Constant::string("EL"));
+    assert_eq!(eval("num2text(1.0 / 3)"), Constant::string("0.333333"));
+    assert_eq!(eval("max(1, 5.5, 3)"), Constant::Float(5.5));
+    assert_eq!(eval("round(7.6)"), Constant::Int(7));
+    assert_eq!(eval("round(sin(90), 1)"), Constant::Int(1));
+}
+
+#[test]
+fn trig_in_degrees() {
+    assert_eq!(eval("round(sin(30) * 10, 1)"), Constant::Int(5));
+    assert_eq!(eval("round(cos(180), 1)"), Constant::Int(-1));
+    assert_eq!(eval("round(tan(45), 1)"), Constant::Int(1));
+    assert_eq!(eval("round(arcsin(1), 1)"), Constant::Int(90));
+    assert_eq!(eval("round(arccos(0), 1)"), Constant::Int(90));
+    assert_eq!(eval("round(arctan(1), 1)"), Constant::Int(45));
+}
+
+#[test]
+fn matrix_folding() {
+    assert_matrix(eval("matrix()"), [1., 0., 0., 0., 1., 0.]);
+    assert_matrix(eval("matrix(2, 0, 1, 0, 2, 1)"), [2., 0., 1., 0., 2., 1.]);
+    assert_matrix(eval("matrix().Scale(2).Translate(3, 4)"), [2., 0., 3., 0., 2., 4.]);
+    // MATRIX_ROTATE, MATRIX_SCALE and MATRIX_TRANSLATE are 5, 6 and 7
+    assert_matrix(eval("matrix(90, 5)"), [0., 1., 0., -1., 0., 0.]);
+    assert_matrix(eval("matrix(2, 3, 6) * matrix(1, 1, 7)"), [2., 0., 1., 0., 3., 1.]);
+    assert_matrix(eval("matrix(2, 0, 4, 0, 2, 6).Invert()"), [0.5, 0., -2., 0., 0.5, -3.]);
+    assert_eq!(eval("matrix(1, 0, 0, 0, 1, 0)").to_string(), "matrix(1, 0, 0, 0, 1, 0)");
+}
+
+#[test]
+fn constructor_calls() {
+    let icon = eval("icon('a.dmi', \"state\", dir = 2)");
+    assert_eq!(icon.as_path_str(), Some("a.dmi"));
+    assert_eq!(icon.call_argument("icon_state"), Some(&Constant::string("state")));
+    assert_eq!(icon.to_string(), "icon(icon = 'a.dmi', icon_state = \"state\", dir = 2)");
+    let sound = eval("sound('a.ogg', 1, channel = 5)");
+    assert_eq!(sound.as_path_str(), Some("a.ogg"));
+    assert_eq!(sound.to_string(), "sound(file = 'a.ogg', repeat = 1, channel = 5)");
+
+    assert_eq!(eval("newlist(/obj, /mob)"), eval("list(new /obj, new /mob)"));
+    assert_eq!(eval("filter(type = \"blur\", size = 2)").call_argument("size"), Some(&Constant::Int(2)));
+    assert!(dm::constants::evaluate_str(Default::default(), b"filter(type = \"blur\", color = 1)").is_err());
+}
@@ -0,0 +1,45 @@
+extern crate dreammaker as dm;
+
+use rayon::prelude::*;
+
+use dm::{Context, DMError, Location};
+
+fn assert_sync<T: Sync>() {}
+
+#[test]
+fn context_is_sync() {
+    assert_sync::<Context>();
+}
+
+#[test]
+fn concurrent_errors() {
+    let context = Context::default();
+    let file = context.register_file("concurrent.dm".as_ref());
+
+    (1..=100u32).into_par_iter().for_each(|line| {
+        // registering the same file from every thread yields one id
+        assert_eq!(context.register_file("concurrent.dm".as_ref()), file);
+        DMError::new(Location { file, line, column: 1 }, format!("error {}", line))
+            .register(&context);
+    });
+
+    context.sort_errors();
+    let errors = context.errors();
+    assert_eq!(errors.len(), 100);
+    for (i, error) in errors.iter().enumerate() {
+        assert_eq!(error.location().line, i as u32 + 1);
+        assert_eq!(error.description(), format!("error {}", i + 1));
+    }
+}
+
+#[test]
+fn errors_are_a_snapshot() {
+    let context = Context::default();
+    let file = context.register_file("snapshot.dm".as_ref());
+    DMError::new(Location { file, line: 1, column: 1 }, "first").register(&context);
+    let errors = context.errors();
+    // registering while a snapshot is held must not deadlock
+    DMError::new(Location { file, line: 2, column: 1 }, "second").register(&context);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(context.error_count(), 2);
+}
@@ -0,0 +1,101 @@
+extern crate dreammaker as dm;
+
+use dm::cst::*;
+use dm::lexer::Token;
+use dm::lexer::Punctuation;
+
+fn parse_cst(source: &str) -> Node {
+    let context = Default::default();
+    let tree = dm::cst::parse(&context, Default::default(), source.as_bytes());
+    context.assert_success();
+    tree
+}
+
+fn round_trip(source: &str) {
+    assert_eq!(parse_cst(source).to_string(), source);
+}
+
+#[test]
+fn round_trip_exact() {
+    round_trip("");
+    round_trip("/datum");
+    round_trip("/datum/var/x = 1 // a comment\n");
+    round_trip("/datum/proc/foo()\r\n\tif(x)   /* block /* nested */ comment */\r\n\t\treturn \"[x] and [y[1]]\"\r\n\telse\r\n\t\treturn 0x10\r\n");
+    round_trip("/obj\n    name = \\\n        \"thing\"\n\n\n    icon = 'icon.dmi'\n");
+    round_trip("/// doc comment\n/datum/proc/bar(a, b = 1e4)\n\t{\"block\nstring\"}\n\tvar/list/L = list(1; 2)\n");
+    round_trip("#define FOO(x) ((x) + 1)\n#warn the string\n");
+}
+
+#[test]
+fn spans_cover_source() {
+    let source = "/datum\n\tvar/x = 1 // comment\n\tproc/f()\n\t\treturn x\n";
+    let tree = parse_cst(source);
+    assert_eq!(tree.span, Span::new(0, source.len()));
+
+    let mut pos = 0;
+    for leaf in tree.leaves() {
+        assert_eq!(leaf.span.start, pos, "gap before {:?}", leaf);
+        assert_eq!(&source[leaf.span.start..leaf.span.end], leaf.text);
+        pos = leaf.span.end;
+    }
+    assert_eq!(pos, source.len());
+}
+
+#[test]
+fn trivia_and_synthetic_tokens() {
+    let tree = parse_cst("/datum\n\tvar/x // comment\n");
+
+    let comments: Vec<_> = tree.leaves()
+        .filter(|leaf| leaf.kind == LeafKind::Trivia(TriviaKind::Comment))
+        .map(|leaf| leaf.text.as_str())
+        .collect();
+    assert_eq!(comments, vec!["// comment"]);
+
+    let synthetic: Vec<_> = tree.leaves()
+        .filter(|leaf| leaf.is_synthetic())
+        .map(|leaf| (leaf.token().cloned(), leaf.span.len()))
+        .collect();
+    assert_eq!(synthetic, vec![
+        (Some(Token::Punct(Punctuation::LBrace)), 0),
+        (Some(Token::Punct(Punctuation::Semicolon)), 0),
+        (Some(Token::Punct(Punctuation::RBrace)), 0),
+    ]);
+}
+
+#[test]
+fn statements_and_blocks() {
+    let tree = parse_cst("/datum/proc/f()\n\tif(x)\n\t\ty()\n\telse\n\t\tz()\n/datum/proc/g()\n");
+
+    let statements: Vec<&Node> = tree.children.iter()
+        .filter_map(|element| match element {
+            Element::Node(node) if node.kind == NodeKind::Statement => Some(node),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(statements.len(), 2);
+    assert!(statements[0].to_string().starts_with("/datum/proc/f()"));
+    assert!(statements[1].to_string().starts_with("/datum/proc/g()"));
+
+    // The if/else is one statement containing two blocks.
+    let body = statements[0].children.iter()
+        .filter_map(|element| match element {
+            Element::Node(node) if node.kind == NodeKind::Block => Some(node),
+            _ => None,
+        })
+        .next()
+        .expect("no proc body");
+    let inner: Vec<&Node> = body.children.iter()
+        .filter_map(|element| match element {
+            Element::Node(node) if node.kind == NodeKind::Statement => Some(node),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(inner.len(), 1);
+    let blocks = inner[0].children.iter()
+        .filter(|element| match element {
+            Element::Node(node) => node.kind == NodeKind::Block,
+            _ => false,
+        })
+        .count();
+    assert_eq!(blocks, 2);
+}
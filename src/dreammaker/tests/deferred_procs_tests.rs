@@ -0,0 +1,78 @@
+extern crate dreammaker as dm;
+
+use dm::ast::Statement;
+use dm::objtree::{Code, ObjectTree};
+use dm::preprocessor::Preprocessor;
+use dm::indents::IndentProcessor;
+use dm::parser::Parser;
+
+const CODE: &str = "/proc/good()\n\treturn 1\n/proc/bad()\n\treturn 1 +\n/obj/proc/other()\n\tvar/x = 2\n\treturn x\n";
+
+fn parse_deferred(context: &dm::Context) -> ObjectTree {
+    let pp = Preprocessor::from_buffer(context, "deferred.dm".into(), CODE);
+    let mut parser = Parser::new(context, IndentProcessor::new(context, pp));
+    parser.defer_procs();
+    parser.parse_object_tree()
+}
+
+fn code_of<'a>(objtree: &'a ObjectTree, ty: &str, name: &str) -> &'a Code {
+    &objtree.find(ty).unwrap().get_proc(name).unwrap().get().code
+}
+
+#[test]
+fn bodies_are_deferred() {
+    let context = dm::Context::default();
+    let objtree = parse_deferred(&context);
+    context.assert_success();
+    for &(ty, name) in &[("", "good"), ("", "bad"), ("/obj", "other")] {
+        match code_of(&objtree, ty, name) {
+            Code::Deferred(tokens) => assert!(!tokens.is_empty()),
+            other => panic!("{} was not deferred: {:?}", name, other),
+        }
+    }
+}
+
+#[test]
+fn parse_all_bodies() {
+    let context = dm::Context::default();
+    let mut objtree = parse_deferred(&context);
+    objtree.parse_proc_bodies(&context);
+
+    match code_of(&objtree, "", "good") {
+        Code::Present(block) => assert_eq!(block.len(), 1),
+        other => panic!("unexpected {:?}", other),
+    }
+    match code_of(&objtree, "/obj", "other") {
+        Code::Present(block) => assert_eq!(block.len(), 2),
+        other => panic!("unexpected {:?}", other),
+    }
+    match code_of(&objtree, "", "bad") {
+        Code::Present(block) => {
+            assert_eq!(block.len(), 1);
+            assert_eq!(block[0].elem, Statement::Error(Vec::new()));
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+    assert_eq!(context.errors().len(), 1);
+}
+
+#[test]
+fn parse_one_body() {
+    let context = dm::Context::default();
+    let objtree = parse_deferred(&context);
+    let mut code = code_of(&objtree, "/obj", "other").clone();
+    assert!(code.parse(&context));
+    match code {
+        Code::Present(block) => assert_eq!(block.len(), 2),
+        other => panic!("unexpected {:?}", other),
+    }
+    context.assert_success();
+
+    // recovered statements still leave a block, but the body isn't good
+    let mut code = code_of(&objtree, "", "bad").clone();
+    assert!(!code.parse(&context));
+    match code {
+        Code::Present(block) => assert_eq!(block[0].elem, Statement::Error(Vec::new())),
+        other => panic!("unexpected {:?}", other),
+    }
+}
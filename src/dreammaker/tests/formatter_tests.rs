@@ -0,0 +1,60 @@
+extern crate dreammaker as dm;
+
+use dm::config::Format;
+
+fn format_with(source: &str, options: &Format) -> String {
+    let context = Default::default();
+    let result = dm::formatter::format(&context, Default::default(), source, options)
+        .expect("formatting changed the meaning of the code");
+    context.assert_success();
+    result
+}
+
+fn format(source: &str) -> String {
+    format_with(source, &Format::default())
+}
+
+#[test]
+fn indentation() {
+    assert_eq!(
+        format("/datum/proc/foo()\n  if(x)\n    return 1\n  return 0\n"),
+        "/datum/proc/foo()\n\tif(x)\n\t\treturn 1\n\treturn 0\n",
+    );
+    assert_eq!(
+        format_with("/obj\n\tvar/x = 1\n", &Format { indent_spaces: 4, .. Format::default() }),
+        "/obj\n    var/x = 1\n",
+    );
+}
+
+#[test]
+fn spacing() {
+    assert_eq!(
+        format("/datum/proc/foo (a,b=1)\n\tvar /x=a+b  \n\tif (x==2 &&b)\n\t\tfoo( x , 1 )\n"),
+        "/datum/proc/foo(a, b = 1)\n\tvar/x = a+b\n\tif (x == 2 && b)\n\t\tfoo(x, 1)\n",
+    );
+}
+
+#[test]
+fn comments_and_defines_untouched() {
+    let source = "#define  FOO(x)   ((x)+1) // keep\n#define BAR \\\n\t1,2\n/obj // trailing   \n\t/* block   comment */\n\tname=\"a,b\"\n";
+    assert_eq!(
+        format(source),
+        "#define  FOO(x)   ((x)+1) // keep\n#define BAR \\\n\t1,2\n/obj // trailing\n\t/* block   comment */\n\tname = \"a,b\"\n",
+    );
+}
+
+#[test]
+fn blank_lines() {
+    assert_eq!(format("\n\n/obj\n\n\n\n\n/mob\n\n"), "/obj\n\n\n/mob\n");
+    assert_eq!(
+        format_with("/obj\n\n\n/mob\n", &Format { max_blank_lines: 0, .. Format::default() }),
+        "/obj\n/mob\n",
+    );
+}
+
+#[test]
+fn idempotent() {
+    let source = "/datum/proc/foo(a, b)\n\tfor(var/i in 1 to 10)\n\t\tif(i % 2)\n\t\t\tcontinue\n\t\telse\n\t\t\tlist(\n\t\t\t\ti,\n\t\t\t\ta)\n";
+    let once = format(source);
+    assert_eq!(format(&once), once);
+}
@@ -0,0 +1,53 @@
+extern crate dreammaker as dm;
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use dm::includes::IncludeKind;
+use dm::preprocessor::Preprocessor;
+
+#[test]
+fn include_graph() {
+    let root = common::project("includes-graph", &[
+        ("env.dme", "#include \"code/a.dm\"\n#include \"maps/x.dmm\"\n#include \"maps/x.dmm\"\n"),
+        ("code/a.dm", "#include \"sub/b.dm\"\n/obj\n"),
+        ("code/sub/b.dm", "/mob\n"),
+        ("code/orphan.dm", "/turf\n"),
+        ("maps/x.dmm", ""),
+        ("maps/unused.dmm", ""),
+        (".git/ignored.dm", ""),
+    ]);
+
+    let context = dm::Context::default();
+    let mut pp = Preprocessor::new(&context, root.join("env.dme")).unwrap();
+    pp.by_ref().for_each(drop);
+    let graph = pp.include_graph();
+
+    let files: Vec<_> = graph.files().iter()
+        .map(|f| (f.path.to_str().unwrap().replace('\\', "/"), f.kind, f.parent, f.duplicate))
+        .collect();
+    assert_eq!(files, vec![
+        ("env.dme".to_owned(), IncludeKind::Code, None, false),
+        ("code/a.dm".to_owned(), IncludeKind::Code, Some(0), false),
+        ("code/sub/b.dm".to_owned(), IncludeKind::Code, Some(1), false),
+        ("maps/x.dmm".to_owned(), IncludeKind::Map, Some(0), false),
+        ("maps/x.dmm".to_owned(), IncludeKind::Map, Some(0), true),
+    ]);
+    assert_eq!(graph.children(0).collect::<Vec<_>>(), vec![1, 3, 4]);
+
+    let orphans = graph.orphans(&root).unwrap();
+    assert_eq!(orphans, vec![Path::new("code/orphan.dm"), Path::new("maps/unused.dmm")]);
+
+    // maps may be included more than once
+    assert!(context.errors().is_empty());
+
+    let mut dot = Vec::new();
+    graph.write_dot(&mut dot).unwrap();
+    let dot = String::from_utf8(dot).unwrap();
+    assert!(dot.contains("\"code/a.dm\" -> \"code/sub/b.dm\";"), "{}", dot);
+    assert!(dot.contains("\"env.dme\" -> \"maps/x.dmm\" [style=dashed];"), "{}", dot);
+
+    fs::remove_dir_all(&root).unwrap();
+}
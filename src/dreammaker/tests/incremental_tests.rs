@@ -0,0 +1,82 @@
+extern crate dreammaker as dm;
+
+mod common;
+
+use std::fs;
+
+use dm::constants::Constant;
+use dm::incremental::Environment;
+use dm::objtree::ObjectTree;
+
+fn value_of(objtree: &ObjectTree, path: &str) -> Option<Constant> {
+    objtree.expect(path).get_value("v").and_then(|value| value.constant.clone())
+}
+
+#[test]
+fn reprocess_dependents_only() {
+    let dir = common::project("incremental", &[
+        ("env.dme", "#include \"a.dm\"\n#include \"b.dm\"\n#include \"c.dm\"\n"),
+        ("a.dm", "#define X 1\n/obj/a\n\tvar/v = X\n"),
+        ("b.dm", "/obj/b\n\tvar/v = X + 1\n"),
+        ("c.dm", "/obj/c\n\tvar/v = 3\n"),
+    ]);
+    let context = dm::Context::default();
+    let (mut environment, mut objtree) = Environment::parse(&context, &dir.join("env.dme")).unwrap();
+    context.assert_success();
+    assert_eq!(value_of(&objtree, "/obj/b"), Some(Constant::Float(2.)));
+
+    let a = context.get_file("a.dm".as_ref()).unwrap();
+    let b = context.get_file("b.dm".as_ref()).unwrap();
+    let c = context.get_file("c.dm".as_ref()).unwrap();
+
+    // Changing a macro reprocesses the files that use it.
+    fs::write(dir.join("a.dm"), "#define X 5\n/obj/a\n\tvar/v = X\n").unwrap();
+    assert_eq!(environment.update(&context, a, &mut objtree).unwrap(), vec![a, b]);
+    context.assert_success();
+    assert_eq!(value_of(&objtree, "/obj/a"), Some(Constant::Float(5.)));
+    assert_eq!(value_of(&objtree, "/obj/b"), Some(Constant::Float(6.)));
+
+    // Other changes touch only the file itself.
+    fs::write(dir.join("a.dm"), "#define X 5\n/obj/a\n\tvar/v = X + 2\n").unwrap();
+    assert_eq!(environment.update(&context, a, &mut objtree).unwrap(), vec![a]);
+    fs::write(dir.join("c.dm"), "/obj/c\n\tvar/v = 4\n").unwrap();
+    assert_eq!(environment.update(&context, c, &mut objtree).unwrap(), vec![c]);
+    context.assert_success();
+    assert_eq!(value_of(&objtree, "/obj/a"), Some(Constant::Float(7.)));
+    assert_eq!(value_of(&objtree, "/obj/b"), Some(Constant::Float(6.)));
+    assert_eq!(value_of(&objtree, "/obj/c"), Some(Constant::Float(4.)));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn update_in_place() {
+    let dir = common::project("in-place", &[
+        ("env.dme", "#include \"a.dm\"\n#include \"b.dm\"\n"),
+        ("a.dm", "/obj/a\n\tvar/v = 1\n/obj/a/proc/foo()\n\treturn\n/obj/gone\n"),
+        ("b.dm", "/obj/a/foo()\n\treturn\n/obj/b\n\tvar/v = 3\n"),
+    ]);
+    let context = dm::Context::default();
+    let (mut environment, mut objtree) = Environment::parse(&context, &dir.join("env.dme")).unwrap();
+    context.assert_success();
+    context.register_error(dm::DMError::new(dm::Location::default(), "unrelated"));
+
+    let a = context.get_file("a.dm".as_ref()).unwrap();
+    let b = context.get_file("b.dm".as_ref()).unwrap();
+
+    fs::write(dir.join("a.dm"), "/obj/a\n\tvar/v = 2\n/obj/a/proc/foo()\n\treturn\n").unwrap();
+    assert_eq!(environment.update(&context, a, &mut objtree).unwrap(), vec![a]);
+
+    // Errors from elsewhere are kept, and the override in the later file
+    // still follows the definition.
+    let descriptions: Vec<String> = context.errors().iter().map(|e| e.description().to_owned()).collect();
+    assert_eq!(descriptions, vec!["unrelated".to_owned()]);
+    let foo = &objtree.expect("/obj/a").get().procs["foo"];
+    assert_eq!(foo.value.iter().map(|value| value.location.file).collect::<Vec<_>>(), vec![a, b]);
+
+    assert!(objtree.find("/obj/gone").is_none());
+    assert_eq!(value_of(&objtree, "/obj/a"), Some(Constant::Float(2.)));
+    assert_eq!(value_of(&objtree, "/obj/b"), Some(Constant::Float(3.)));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
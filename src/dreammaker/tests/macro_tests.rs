@@ -69,3 +69,93 @@ ok2
         Ident("ok2".into(), false),
     ]);
 }
+
+#[test]
+fn expansion_notes() {
+    let ctx = dm::Context::default();
+    let mut pp = Preprocessor::from_buffer(&ctx, "macro_tests.rs".into(), r#"
+#define INNER(x) x + )
+#define OUTER(y) INNER(y)
+/obj/var/v = OUTER(1)
+"#.trim());
+    pp.enable_macro_provenance();
+    dm::parser::parse(&ctx, dm::indents::IndentProcessor::new(&ctx, pp));
+
+    let errors = ctx.errors();
+    let error = errors.iter().find(|e| e.location().line == 3).expect("no error on line 3");
+    let notes: Vec<_> = error.notes().iter().map(|n| (n.location().line, n.description())).collect();
+    assert_eq!(notes, &[
+        (1, "expanded from macro \"INNER\""),
+        (2, "expanded from macro \"OUTER\""),
+    ]);
+}
+
+#[test]
+fn expansion_notes_after_parsing() {
+    let ctx = dm::Context::default();
+    let mut pp = Preprocessor::from_buffer(&ctx, "macro_tests.rs".into(), r#"
+#define HELPER(x) x.missing()
+/proc/test(x)
+    HELPER(x)
+"#.trim());
+    pp.enable_macro_provenance();
+    let mut parser = dm::parser::Parser::new(&ctx, dm::indents::IndentProcessor::new(&ctx, pp));
+    parser.enable_procs();
+    let tree = parser.parse_object_tree();
+    let location = match tree.root().get_proc("test").unwrap().get().code {
+        dm::objtree::Code::Present(ref block) => block[0].location,
+        ref other => panic!("unexpected {:?}", other),
+    };
+
+    // as a checker would report it, long after the tokens are gone
+    ctx.register_error(dm::DMError::new(location, "undefined proc"));
+    let errors = ctx.errors();
+    let notes: Vec<_> = errors[0].notes().iter().map(|n| (n.location().line, n.description())).collect();
+    assert_eq!(notes, &[(1, "expanded from macro \"HELPER\"")]);
+}
+
+#[test]
+fn expansion_provenance() {
+    let ctx = dm::Context::default();
+    let mut pp = Preprocessor::from_buffer(&ctx, "macro_tests.rs".into(), r#"
+#define INNER 1
+#define OUTER INNER + 2
+OUTER 3
+"#.trim());
+    pp.enable_macro_provenance();
+    let tokens: Vec<_> = pp
+        .filter(|loctok| !loctok.token.is_whitespace())
+        .map(|loctok| {
+            let names: Vec<String> = loctok.expansion.iter()
+                .flat_map(|chain| chain.iter().map(|each| each.name.clone()))
+                .collect();
+            (loctok.token, names)
+        })
+        .collect();
+    assert_eq!(tokens, vec![
+        (Int(1), vec!["OUTER".to_owned(), "INNER".to_owned()]),
+        (Punct(Add), vec!["OUTER".to_owned()]),
+        (Int(2), vec!["OUTER".to_owned()]),
+        (Int(3), vec![]),
+    ]);
+
+    // Without asking, nothing is recorded.
+    let pp = Preprocessor::from_buffer(&ctx, "macro_tests.rs".into(), "#define X 1\nX\n");
+    assert!(pp.into_iter().all(|loctok| loctok.expansion.is_none()));
+}
+
+#[test]
+fn expand_lines() {
+    let ctx = dm::Context::default();
+    let pp = Preprocessor::from_buffer(&ctx, "expand.dm".into(), r#"
+#define DOUBLE(x) ((x) * 2)
+#define NAME "thing"
+/obj/var/v = DOUBLE(3)
+/obj/name = NAME
+/obj/desc = NAME
+"#.trim());
+    let expanded = pp.expand_lines("expand.dm".as_ref(), 3..=4);
+    assert!(expanded.contains("((3) * 2)"), "{:?}", expanded);
+    assert!(expanded.contains("\"thing\""), "{:?}", expanded);
+    assert!(!expanded.contains("DOUBLE") && !expanded.contains("desc"), "{:?}", expanded);
+}
@@ -0,0 +1,56 @@
+extern crate dreammaker as dm;
+
+mod common;
+
+use dm::Severity;
+
+const CODE: &str = r#"
+#pragma ProcArgumentGlobal error
+/proc/a(static/x)
+#pragma push
+#pragma ProcArgumentGlobal ignore
+/proc/b(static/x)
+#pragma pop
+/proc/c(static/x)
+#pragma FakeWarning error
+#pragma UnusedVar sometimes
+#pragma pop
+"#;
+
+fn parse(context: &dm::Context) {
+    let pp = dm::preprocessor::Preprocessor::from_buffer(context, "pragma.dm".into(), CODE.trim());
+    dm::parser::Parser::new(context, dm::indents::IndentProcessor::new(context, pp)).parse_object_tree();
+}
+
+fn diagnostics(context: &dm::Context) -> Vec<(u32, &'static str, Severity)> {
+    let mut found: Vec<_> = context.errors().iter()
+        .filter_map(|e| e.errortype().map(|errortype| (e.location().line, errortype, e.severity())))
+        .collect();
+    found.sort_by_key(|&(line, _, _)| line);
+    found
+}
+
+#[test]
+fn pragma_levels() {
+    let context = dm::Context::default();
+    parse(&context);
+    assert_eq!(diagnostics(&context), vec![
+        (2, "static_in_proc_parameter", Severity::Error),
+        (7, "static_in_proc_parameter", Severity::Error),
+        (8, "unknown_pragma", Severity::Warning),
+        (9, "invalid_pragma", Severity::Warning),
+        (10, "invalid_pragma", Severity::Warning),
+    ]);
+}
+
+#[test]
+fn config_overrides_pragma() {
+    let context = common::configured("pragma", "[diagnostics]\nstatic_in_proc_parameter = \"info\"\n");
+
+    parse(&context);
+    let levels: Vec<_> = diagnostics(&context).into_iter()
+        .filter(|&(_, errortype, _)| errortype == "static_in_proc_parameter")
+        .map(|(line, _, severity)| (line, severity))
+        .collect();
+    assert_eq!(levels, vec![(2, Severity::Info), (5, Severity::Info), (7, Severity::Info)]);
+}
@@ -0,0 +1,187 @@
+extern crate dreammaker as dm;
+
+use dm::ast::*;
+use dm::lexer::Lexer;
+use dm::objtree::{Code, ObjectTree};
+use dm::parser::*;
+use dm::printer::{block_to_string, Printer};
+
+fn parse_expr(f: &str) -> Expression {
+    let context = Default::default();
+    let lexer = Lexer::new(&context, Default::default(), f.bytes().map(Ok));
+    let result = parse_expression(&context, Default::default(), lexer).expect("failed to parse expression");
+    context.assert_success();
+    result
+}
+
+fn parse_tree(code: String) -> ObjectTree {
+    let context = dm::Context::default();
+    let pp = dm::preprocessor::Preprocessor::from_buffer(&context, "printer.dm".into(), code);
+    let mut parser = Parser::new(&context, dm::indents::IndentProcessor::new(&context, pp));
+    parser.enable_procs();
+    let tree = parser.parse_object_tree();
+    context.assert_success();
+    tree
+}
+
+fn proc_body(tree: &ObjectTree, name: &str) -> Block {
+    match tree.root().get_proc(name).unwrap().get().code {
+        Code::Present(ref block) => block.clone(),
+        ref other => panic!("unexpected {:?}", other),
+    }
+}
+
+fn ident(name: &str) -> Expression {
+    Expression::from(Term::Ident(name.to_owned()))
+}
+
+fn binary(op: BinaryOp, lhs: Expression, rhs: Expression) -> Expression {
+    Expression::BinaryOp { op, lhs: Box::new(lhs), rhs: Box::new(rhs) }
+}
+
+#[test]
+fn precedence() {
+    let sum = binary(BinaryOp::Add, ident("a"), ident("b"));
+    assert_eq!(binary(BinaryOp::Mul, sum.clone(), ident("c")).to_string(), "(a + b) * c");
+    assert_eq!(binary(BinaryOp::Add, ident("c"), sum.clone()).to_string(), "c + (a + b)");
+    assert_eq!(binary(BinaryOp::Add, sum.clone(), ident("c")).to_string(), "a + b + c");
+
+    let assign = Expression::AssignOp {
+        op: AssignOp::Assign,
+        lhs: Box::new(ident("x")),
+        rhs: Box::new(Expression::AssignOp {
+            op: AssignOp::Assign,
+            lhs: Box::new(ident("y")),
+            rhs: Box::new(binary(BinaryOp::In, ident("a"), ident("L"))),
+        }),
+    };
+    assert_eq!(assign.to_string(), "x = y = (a in L)");
+
+    let negated = Expression::Base {
+        unary: vec![UnaryOp::Neg],
+        term: Spanned::new(Default::default(), Term::Expr(Box::new(Expression::Base {
+            unary: vec![UnaryOp::Neg, UnaryOp::PostIncr],
+            term: Spanned::new(Default::default(), Term::Ident("i".to_owned())),
+            follow: vec![],
+        }))),
+        follow: vec![],
+    };
+    assert_eq!(negated.to_string(), "-(-i++)");
+}
+
+#[test]
+fn expressions_round_trip() {
+    for source in &[
+        "a ? b : c ? d : e",
+        "(a ? b : c) + 1",
+        "x = locate(a) in world",
+        "(locate(a) in world).name",
+        "!(a && b) || ~c",
+        "2 ** 3 ** 2",
+        "L?[i]:name?.desc",
+        "new /obj{name = \"foo\"; desc = 'icon.dmi'}(loc)",
+        "new some_list[1].type()",
+        "input(usr, \"Pick\") as null|anything in list(\"a\" = 1, \"b\")",
+        "pick(prob(25); 1, 2)",
+        "call(src, \"proc\")(1, 2)",
+        "\"a [b] c [d.e()]\"",
+        "{\"a \" [b]\"}",
+        "/obj::name + ::glob + ::helper()",
+        "x %% 3 - 1.5",
+        "a in 1 to 5",
+        "..() + .(1)",
+        "as(num)",
+    ] {
+        let expr = parse_expr(source);
+        let printed = expr.to_string();
+        assert_eq!(parse_expr(&printed), expr, "{:?} printed as {:?}", source, printed);
+    }
+}
+
+const PROCS: &str = r#"
+/proc/helper(a, list/b = list(), c as num in list(1, 2))
+/proc/test(list/L)
+    set waitfor = 0
+    var/total = 0, static/count
+    var/list/things[4]
+    for(var/i = 1 to 10 step 2)
+        total += i
+    for(var/obj/O as obj in L)
+        continue
+    for(var/k, v in L)
+        break
+    for(var/j = 0; j < 5; j++)
+        total -= j
+    for(;;)
+        goto done
+    while(total > 0)
+        total--
+    do
+        total++
+    while(total < 5)
+    if(total == 1)
+        return
+    else if(total == 2)
+        throw EXCEPTION("two")
+    else
+        CRASH("three")
+    switch(total)
+        if(1, 2 to 3)
+            del total
+        if(4)
+        else
+            spawn(10)
+                world.log << "late"
+    try
+        helper(1)
+    catch(var/exception/e)
+        world.log << e
+    done:
+        return total
+    spawn()
+        count = things
+"#;
+
+#[test]
+fn procs_round_trip() {
+    let tree = parse_tree(PROCS.trim().to_owned());
+    let block = proc_body(&tree, "test");
+    let helper = tree.root().get_proc("helper").unwrap().get();
+
+    let mut printed = String::new();
+    {
+        let mut printer = Printer::new(&mut printed).with_indent("    ");
+        printer.proc_definition("/proc/helper", &helper.parameters, None, &[]).unwrap();
+        printer.proc_definition("/proc/test", &[], None, &block).unwrap();
+    }
+    let reparsed = parse_tree(printed.clone());
+    assert_eq!(proc_body(&reparsed, "test"), block, "printed as:\n{}", printed);
+    let reparsed_helper = reparsed.root().get_proc("helper").unwrap().get();
+    assert_eq!(reparsed_helper.parameters.len(), 3);
+    for (lhs, rhs) in reparsed_helper.parameters.iter().zip(helper.parameters.iter()) {
+        assert_eq!(lhs.name, rhs.name);
+        assert_eq!(lhs.default, rhs.default);
+        assert_eq!(lhs.input_type, rhs.input_type);
+        assert_eq!(lhs.in_list, rhs.in_list);
+    }
+
+    assert!(block_to_string(&block).unwrap().starts_with("set waitfor = 0\nvar/total = 0, static/count\n"));
+}
+
+#[test]
+fn failed_statements_are_marked() {
+    let block = vec![
+        Spanned::new(Default::default(), Statement::Error(Vec::new())),
+        Spanned::new(Default::default(), Statement::Break(None)),
+    ];
+    assert_eq!(block_to_string(&block).unwrap(), "// statement failed to parse\nbreak\n");
+
+    let nested = Statement::While { condition: ident("x"), block: Vec::new() };
+    let unprintable = vec![Spanned::new(Default::default(), Statement::ForLoop {
+        init: Some(Box::new(nested)),
+        test: None,
+        inc: None,
+        block: Vec::new(),
+    })];
+    assert!(block_to_string(&unprintable).is_err());
+}
@@ -0,0 +1,64 @@
+extern crate dreammaker as dm;
+
+use dm::ast::*;
+use dm::objtree::Code;
+use dm::parser::Parser;
+
+const CODE: &str = r#"
+/proc/test()
+    var/a = 1 +
+    var/b = 2
+    if(a)
+        b = 3 *
+        b = 4
+    return b
+"#;
+
+#[test]
+fn statement_recovery() {
+    let context = dm::Context::default();
+    let pp = dm::preprocessor::Preprocessor::from_buffer(&context, "recovery.dm".into(), CODE.trim());
+    let mut parser = Parser::new(&context, dm::indents::IndentProcessor::new(&context, pp));
+    parser.enable_procs();
+    let tree = parser.parse_object_tree();
+
+    // both broken statements are reported, not just the first
+    assert_eq!(context.errors().len(), 2);
+
+    let block = match tree.root().get_proc("test").unwrap().get().code {
+        Code::Present(ref block) => block,
+        ref other => panic!("unexpected {:?}", other),
+    };
+    assert_eq!(block.len(), 4);
+    // the broken declaration still declares its local, without a value
+    match block[0].elem {
+        Statement::Error(ref vars) => {
+            assert_eq!(vars.len(), 1);
+            assert_eq!(vars[0].name, "a");
+            assert!(vars[0].value.is_none());
+        }
+        ref other => panic!("unexpected {:?}", other),
+    }
+    assert_eq!(block[0].location.line, 2);
+    match block[1].elem {
+        Statement::Var(ref var) => assert_eq!(var.name, "b"),
+        ref other => panic!("unexpected {:?}", other),
+    }
+    match block[2].elem {
+        Statement::If { ref arms, .. } => {
+            let inner = &arms[0].1;
+            assert_eq!(inner.len(), 2);
+            assert_eq!(inner[0].elem, Statement::Error(Vec::new()));
+            assert_eq!(inner[0].location.line, 5);
+            match inner[1].elem {
+                Statement::Expr(Expression::AssignOp { .. }) => {}
+                ref other => panic!("unexpected {:?}", other),
+            }
+        }
+        ref other => panic!("unexpected {:?}", other),
+    }
+    match block[3].elem {
+        Statement::Return(Some(_)) => {}
+        ref other => panic!("unexpected {:?}", other),
+    }
+}
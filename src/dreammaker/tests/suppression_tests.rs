@@ -0,0 +1,83 @@
+extern crate dreammaker as dm;
+
+use dm::suppression::{parse_comment, SuppressionKind};
+
+const CODE: &str = r#"
+/proc/a(static/x) // dreamchecker-ignore: static_in_proc_parameter
+// dreamchecker-ignore: static_in_proc_parameter
+/proc/b(static/x)
+/proc/c(static/x)
+// dreamchecker-ignore-next-block: static_in_proc_parameter
+/mob
+    proc/d(static/x)
+
+    proc/e(static/x)
+/proc/f(static/x)
+// dreamchecker-ignore: invalid_set_value, static_in_proc_parameter
+/proc/g(static/x)
+// dreamchecker-ignore
+"#;
+
+fn diagnostics(context: &dm::Context) -> Vec<(u32, &'static str)> {
+    let mut found: Vec<_> = context.errors().iter()
+        .filter_map(|e| e.errortype().map(|errortype| (e.location().line, errortype)))
+        .collect();
+    found.sort();
+    found
+}
+
+#[test]
+fn suppression_comments() {
+    let context = dm::Context::default();
+    let pp = dm::preprocessor::Preprocessor::from_buffer(&context, "suppress.dm".into(), CODE.trim());
+    dm::parser::Parser::new(&context, dm::indents::IndentProcessor::new(&context, pp)).parse_object_tree();
+    assert_eq!(diagnostics(&context), vec![
+        (4, "static_in_proc_parameter"),
+        (10, "static_in_proc_parameter"),
+        (13, "invalid_suppression"),
+    ]);
+
+    context.register_unused_suppressions();
+    let unused: Vec<_> = context.errors().iter()
+        .filter(|e| e.errortype() == Some("unused_suppression"))
+        .map(|e| (e.location().line, e.description().to_owned()))
+        .collect();
+    assert_eq!(unused, vec![(11, "unused suppression of invalid_set_value".to_owned())]);
+}
+
+#[test]
+fn comment_syntax() {
+    assert_eq!(
+        parse_comment(" dreamchecker-ignore: a, b c"),
+        Some((SuppressionKind::Line, vec!["a".to_owned(), "b".to_owned(), "c".to_owned()])),
+    );
+    assert_eq!(
+        parse_comment("dreamchecker-ignore-next-block:a"),
+        Some((SuppressionKind::Block, vec!["a".to_owned()])),
+    );
+    assert_eq!(parse_comment(" dreamchecker-ignore"), Some((SuppressionKind::Line, vec![])));
+    assert_eq!(parse_comment(" dreamchecker-ignored: a"), None);
+    assert_eq!(parse_comment(" an ordinary comment"), None);
+}
+
+#[test]
+fn code_directly_after_comment() {
+    let code = "\
+// dreamchecker-ignore: static_in_proc_parameter
+/proc/a(static/x)
+/proc/b(static/x)
+// dreamchecker-ignore-next-block: static_in_proc_parameter
+/mob
+\tproc/c(static/x)
+
+\tproc/d(static/x)
+/proc/e(static/x)
+";
+    let context = dm::Context::default();
+    let pp = dm::preprocessor::Preprocessor::from_buffer(&context, "directly.dm".into(), code);
+    dm::parser::Parser::new(&context, dm::indents::IndentProcessor::new(&context, pp)).parse_object_tree();
+    assert_eq!(diagnostics(&context), vec![
+        (3, "static_in_proc_parameter"),
+        (9, "static_in_proc_parameter"),
+    ]);
+}
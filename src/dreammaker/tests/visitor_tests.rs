@@ -0,0 +1,153 @@
+extern crate dreammaker as dm;
+
+use dm::Location;
+use dm::ast::*;
+use dm::objtree::{Code, ObjectTree};
+use dm::parser::Parser;
+
+fn parse_tree(code: &'static str) -> ObjectTree {
+    let context = dm::Context::default();
+    let pp = dm::preprocessor::Preprocessor::from_buffer(&context, "visitor.dm".into(), code);
+    let mut parser = Parser::new(&context, dm::indents::IndentProcessor::new(&context, pp));
+    parser.enable_procs();
+    let tree = parser.parse_object_tree();
+    context.assert_success();
+    tree
+}
+
+fn proc_body(tree: &ObjectTree, name: &str) -> Block {
+    match tree.root().get_proc(name).unwrap().get().code {
+        Code::Present(ref block) => block.clone(),
+        ref other => panic!("unexpected {:?}", other),
+    }
+}
+
+const CODE: &str = r#"
+/proc/helper(a, b)
+/proc/test(list/L)
+    var/total = helper(1, 2)
+    for(var/i in L)
+        if(i > total)
+            total += helper(i, L[i])
+        else
+            switch(i)
+                if(1 to helper(0, 0))
+                    total = new /list(helper(3, 4))
+    spawn(helper(5, 6))
+        world.log << "[helper(7, 8)]"
+    return total
+"#;
+
+#[derive(Default)]
+struct CallCounter {
+    calls: Vec<(String, usize)>,
+}
+
+impl<'ast> Visitor<'ast> for CallCounter {
+    fn visit_term(&mut self, location: Location, term: &'ast Term) {
+        if let Term::Call(name, args) = term {
+            self.calls.push((name.clone(), args.len()));
+        }
+        walk_term(self, location, term);
+    }
+}
+
+#[test]
+fn visit_nested_calls() {
+    let tree = parse_tree(CODE.trim());
+    let block = proc_body(&tree, "test");
+    let mut counter = CallCounter::default();
+    counter.visit_block(&block);
+    assert_eq!(counter.calls.len(), 6);
+    assert!(counter.calls.iter().all(|(name, args)| name == "helper" && *args == 2));
+}
+
+struct Renamer;
+
+impl VisitorMut for Renamer {
+    fn visit_term_mut(&mut self, location: Location, term: &mut Term) {
+        match term {
+            Term::Call(name, _) if *name == "helper" => *name = "assist".to_owned(),
+            Term::Ident(name) if *name == "total" => *name = "sum".to_owned(),
+            _ => {}
+        }
+        walk_term_mut(self, location, term);
+    }
+
+    fn visit_var_statement_mut(&mut self, location: Location, var: &mut VarStatement) {
+        if var.name == "total" {
+            var.name = "sum".to_owned();
+        }
+        walk_var_statement_mut(self, location, var);
+    }
+}
+
+#[derive(Default)]
+struct IdentCollector {
+    idents: Vec<String>,
+}
+
+impl<'ast> Visitor<'ast> for IdentCollector {
+    fn visit_term(&mut self, location: Location, term: &'ast Term) {
+        match term {
+            Term::Ident(name) | Term::Call(name, _) => self.idents.push(name.clone()),
+            _ => {}
+        }
+        walk_term(self, location, term);
+    }
+
+    fn visit_var_statement(&mut self, location: Location, var: &'ast VarStatement) {
+        self.idents.push(var.name.clone());
+        walk_var_statement(self, location, var);
+    }
+}
+
+#[test]
+fn rename_in_place() {
+    let tree = parse_tree(CODE.trim());
+    let mut block = proc_body(&tree, "test");
+    Renamer.visit_block_mut(&mut block);
+
+    let mut collector = IdentCollector::default();
+    collector.visit_block(&block);
+    assert!(collector.idents.iter().any(|name| name == "sum"));
+    assert!(collector.idents.iter().any(|name| name == "assist"));
+    assert!(!collector.idents.iter().any(|name| name == "total" || name == "helper"));
+}
+
+#[derive(Default)]
+struct LocalCollector {
+    locals: Vec<String>,
+}
+
+impl<'ast> Visitor<'ast> for LocalCollector {
+    fn visit_var_statement(&mut self, location: Location, var: &'ast VarStatement) {
+        self.locals.push(var.name.clone());
+        walk_var_statement(self, location, var);
+    }
+
+    fn visit_loop_var(&mut self, _: Location, _: Option<&'ast VarType>, name: &'ast str) {
+        self.locals.push(name.to_owned());
+    }
+
+    fn visit_catch_param(&mut self, _: Location, param: &'ast TreePath) {
+        self.locals.push(param.last().unwrap().clone());
+    }
+}
+
+#[test]
+fn visit_loop_and_catch_vars() {
+    let tree = parse_tree(r#"
+/proc/test(list/L)
+    for(var/i = 1, i < 3, i++)
+        for(var/j in 1 to i)
+            for(var/k, v in L)
+                try
+                    world.log << "[j][k][v]"
+                catch(var/exception/e)
+                    world.log << e
+"#.trim());
+    let mut collector = LocalCollector::default();
+    collector.visit_block(&proc_body(&tree, "test"));
+    assert_eq!(collector.locals, vec!["i", "j", "k", "v", "e"]);
+}
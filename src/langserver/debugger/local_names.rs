@@ -51,6 +51,13 @@ impl<'o> WalkProc<'o> {
                 }
                 self.visit_block(block);
             },
+            Statement::ForKeyValue { var_type, key, value, block, .. } => {
+                if let Some(var_type) = var_type {
+                    self.visit_var(location, var_type, key, None);
+                }
+                self.visit_var(location, &VarType::default(), value, None);
+                self.visit_block(block);
+            },
             Statement::ForRange { var_type, name, start, end: _, step: _, block } => {
                 if let Some(var_type) = var_type {
                     self.visit_var(location, var_type, name, Some(start));
@@ -58,7 +65,7 @@ impl<'o> WalkProc<'o> {
                 self.visit_block(block);
             },
             Statement::Var(var) => self.visit_var_stmt(location, var),
-            Statement::Vars(vars) => {
+            Statement::Vars(vars) | Statement::Error(vars) => {
                 for each in vars.iter() {
                     self.visit_var_stmt(location, each);
                 }
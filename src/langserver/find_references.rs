@@ -234,6 +234,14 @@ impl<'o> WalkProc<'o> {
                 }
                 self.visit_block(block);
             },
+            Statement::ForKeyValue { var_type, key, value, in_list, block } => {
+                self.visit_expression(location, in_list, None);
+                if let Some(var_type) = var_type {
+                    self.visit_var(location, var_type, key, None);
+                }
+                self.visit_var(location, &VarType::default(), value, None);
+                self.visit_block(block);
+            },
             Statement::ForRange { var_type, name, start, end, step, block } => {
                 self.visit_expression(location, end, None);
                 if let Some(step) = step {
@@ -245,7 +253,7 @@ impl<'o> WalkProc<'o> {
                 self.visit_block(block);
             },
             Statement::Var(var) => self.visit_var_stmt(location, var),
-            Statement::Vars(vars) => {
+            Statement::Vars(vars) | Statement::Error(vars) => {
                 for each in vars.iter() {
                     self.visit_var_stmt(location, each);
                 }
@@ -406,6 +414,23 @@ impl<'o> WalkProc<'o> {
                     StaticType::None
                 }
             },
+            Term::GlobalIdent(name) => {
+                if let Some(decl) = self.objtree.root().get_var_declaration(name) {
+                    self.tab.use_symbol(decl.id, location);
+                    self.static_type(location, &decl.var_type.type_path)
+                } else {
+                    StaticType::None
+                }
+            },
+            Term::GlobalCall(name, args) => {
+                let root = self.objtree.root();
+                if let Some(proc) = root.get_proc(name) {
+                    self.visit_call(location, root, proc, args, false)
+                } else {
+                    self.visit_arguments(location, args);
+                    StaticType::None
+                }
+            },
             Term::Call(unscoped_name, args) => {
                 let src = self.ty;
                 if let Some(proc) = self.ty.get_proc(unscoped_name) {
@@ -537,7 +562,7 @@ impl<'o> WalkProc<'o> {
 
     fn visit_follow(&mut self, location: Location, lhs: StaticType<'o>, rhs: &'o Follow) -> StaticType<'o> {
         match rhs {
-            Follow::Index(expr) => {
+            Follow::Index(_, expr) => {
                 self.visit_expression(location, expr, None);
                 // TODO: call operator[] or operator[]=
                 // TODO: differentiate between L[1] and L[non_numeric_key]
@@ -394,6 +394,7 @@ impl<'a> Engine<'a> {
                 range: location_to_range(loc),
                 source: component_to_source(error.component()),
                 code: convert_errorcode(error.errortype()),
+                tags: convert_tags(error.errortype()),
                 related_information,
                 .. Default::default()
             };
@@ -489,8 +490,8 @@ impl<'a> Engine<'a> {
                     let contents = self.docs.get_contents(url).map_err(invalid_request)?.into_owned();
                     let mut pp = dm::preprocessor::Preprocessor::from_buffer(&self.context, filename.clone().into(), contents);
                     let file_id = self.context.get_file(filename.as_ref()).expect("file didn't exist?");
-                    // Clear old errors for this file. Hacky, but it will work for now.
-                    self.context.errors_mut().retain(|error| error.location().file != file_id);
+                    // Clear old errors, suppressions, and pragmas for this file.
+                    self.context.clear_file(file_id);
 
                     pp.enable_annotations();
                     let mut annotations = AnnotationTree::default();
@@ -536,6 +537,7 @@ impl<'a> Engine<'a> {
                             range: location_to_range(loc),
                             source: component_to_source(error.component()),
                             code: convert_errorcode(error.errortype()),
+                            tags: convert_tags(error.errortype()),
                             related_information,
                             .. Default::default()
                         };
@@ -1833,6 +1835,16 @@ fn convert_errorcode(errortype: Option<&'static str>) -> Option<lsp_types::Numbe
     errortype.map(|x| lsp_types::NumberOrString::String(x.to_owned()))
 }
 
+/// Unused and unreachable code is shown faded out rather than underlined.
+fn convert_tags(errortype: Option<&'static str>) -> Option<Vec<lsp_types::DiagnosticTag>> {
+    match errortype? {
+        "unused_local" | "unused_argument" | "unused_proc" | "unused_var" | "unreachable_code" => {
+            Some(vec![lsp_types::DiagnosticTag::Unnecessary])
+        },
+        _ => None,
+    }
+}
+
 enum UnscopedVar<'a> {
     Parameter {
         ty: TypeRef<'a>,